@@ -1,11 +0,0 @@
-use respire::pir::cuckoo_respire::CuckooRespireImpl;
-use respire::pir::respire::RespireParamsExpanded;
-use respire::pir::respire_harness::FactoryParams;
-use respire::{generate_main, respire};
-
-const BASE_PARAMS: RespireParamsExpanded = FactoryParams::batch_256(197, 8, 6, 6).expand().expand();
-
-type BasePIR = respire!(BASE_PARAMS);
-type CuckooPIR = CuckooRespireImpl<128, 197, { 2usize.pow(20) }, BasePIR>;
-
-generate_main!(CuckooPIR);
@@ -0,0 +1,36 @@
+use clap::Parser;
+use respire::pir::cuckoo_planner::plan_cuckoo;
+
+/// Choose the bucket count and bucket dimensions of a cuckoo batch PIR configuration
+#[derive(Parser, Debug)]
+struct Args {
+    #[arg(long)]
+    num_records: usize,
+    #[arg(long)]
+    batch_size: usize,
+    #[arg(long, default_value_t = 3)]
+    num_hash: usize,
+    /// log2 of the acceptable probability that a batch cannot be assigned to buckets
+    #[arg(long, default_value_t = -20.0, allow_hyphen_values = true)]
+    failure_log2: f64,
+    /// log2 of the acceptable probability that a bucket overflows for a random hash key. Encoding
+    /// draws another key when one does, so this only bounds the expected number of attempts.
+    #[arg(long, default_value_t = -1.0, allow_hyphen_values = true)]
+    overflow_log2: f64,
+}
+
+fn main() {
+    let args = Args::parse();
+    let plan = plan_cuckoo(
+        args.num_records,
+        args.batch_size,
+        args.num_hash,
+        args.failure_log2,
+        args.overflow_log2,
+    )
+    .unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    println!("{}", plan);
+}
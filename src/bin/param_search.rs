@@ -0,0 +1,79 @@
+use clap::Parser;
+use respire::pir::param_search::{search_params, CostModel, SearchTarget};
+use respire::pir::respire_harness::{parse_count, parse_size};
+use respire::pir::security::MIN_SECURITY_BITS;
+
+/// Choose RESPIRE parameters (NU1, NU2, N_VEC, Q2) for a database
+#[derive(Parser, Debug)]
+struct Args {
+    /// Database size in bytes, with an optional k/m/g/t suffix, e.g. 256m or 1g
+    #[arg(long, value_parser = parse_size)]
+    db_size: usize,
+    /// At most 256; shorter records are zero-padded
+    #[arg(long, default_value_t = 256)]
+    record_size: usize,
+    #[arg(long, default_value_t = 1, value_parser = parse_count)]
+    batch_size: usize,
+    /// log2 of the acceptable probability that a record fails to decode (and, for batches, that a
+    /// batch cannot be assigned to buckets)
+    #[arg(long, default_value_t = -40.0, allow_hyphen_values = true)]
+    failure_log2: f64,
+    #[arg(long, default_value_t = 3)]
+    num_hash: usize,
+    /// Minimum estimated security, in core-SVP bits
+    #[arg(long, default_value_t = MIN_SECURITY_BITS)]
+    min_security_bits: f64,
+    /// Server time per modular multiplication, in nanoseconds
+    #[arg(long, default_value_t = CostModel::default().ns_per_op)]
+    ns_per_op: f64,
+    /// Time to send a byte, in nanoseconds (8 for a 1 Gbit/s link)
+    #[arg(long, default_value_t = CostModel::default().ns_per_byte)]
+    ns_per_byte: f64,
+    /// Number of runner-up candidates to list
+    #[arg(long, default_value_t = 4)]
+    alternatives: usize,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.record_size == 0 || args.record_size > 256 {
+        eprintln!("record size must be between 1 and 256 bytes");
+        std::process::exit(1);
+    }
+    let target = SearchTarget {
+        db_size: args.db_size,
+        record_size: args.record_size,
+        batch_size: args.batch_size,
+        failure_log2: args.failure_log2,
+        num_hash: args.num_hash,
+        min_security_bits: args.min_security_bits,
+        cost: CostModel {
+            ns_per_op: args.ns_per_op,
+            ns_per_byte: args.ns_per_byte,
+        },
+    };
+    let candidates = search_params(&target).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    });
+    let Some(best) = candidates.first() else {
+        eprintln!(
+            "no parameters reach an error rate of 2^({}) with {} bits of security",
+            args.failure_log2, args.min_security_bits
+        );
+        std::process::exit(1);
+    };
+    if let Some(plan) = &best.cuckoo {
+        println!("{}", plan);
+        println!();
+    }
+    println!("Best of {} candidates:", candidates.len());
+    println!("{}", best);
+    if candidates.len() > 1 {
+        println!();
+        println!("Alternatives:");
+        for candidate in candidates.iter().skip(1).take(args.alternatives) {
+            println!("{}", candidate.to_string().lines().next().unwrap());
+        }
+    }
+}
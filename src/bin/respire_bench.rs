@@ -0,0 +1,119 @@
+use clap::Parser;
+use respire::pir::dyn_pir::DynPIR;
+use respire::pir::respire_harness::{parse_count, parse_size, write_csv, write_json};
+use respire::pir::shaped_pir::DbShape;
+use respire::standard_respire_registry;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Benchmark a standard parameter set, chosen by name or by database shape
+#[derive(Parser, Debug)]
+struct Args {
+    /// Database size in bytes, with an optional k/m/g/t suffix, e.g. 256m or 1g. The smallest
+    /// parameter set holding at least this many records of --record-size bytes is used, or, if
+    /// none has this shape, the one serving it most cheaply.
+    #[arg(long, value_parser = parse_size, required_unless_present_any = ["config", "list"])]
+    db_size: Option<usize>,
+    #[arg(long, default_value_t = 256, value_parser = parse_count)]
+    record_size: usize,
+    /// Number of records retrieved per query
+    #[arg(long, default_value_t = 1, value_parser = parse_count)]
+    batch_size: usize,
+    /// Name of the parameter set, e.g. 256m256 or 1g256_16, instead of --db-size
+    #[arg(long, conflicts_with = "db_size")]
+    config: Option<String>,
+    #[arg(long, default_value_t = 5)]
+    trials: usize,
+    /// Number of worker threads. Defaults to one per logical core.
+    #[arg(long)]
+    threads: Option<usize>,
+    /// List the available parameter sets and exit
+    #[arg(long)]
+    list: bool,
+    /// Instead of timing the trials, measure the noise after each phase of answering them and
+    /// compare it with the error analysis
+    #[arg(long)]
+    noise: bool,
+    /// Write the parameters, communication sizes, timings and trial outcomes (or the measured
+    /// noise with --noise) as JSON to this path
+    #[arg(long)]
+    json: Option<PathBuf>,
+    /// Write the same report as `key,value` rows, with nested keys joined by dots
+    #[arg(long)]
+    csv: Option<PathBuf>,
+}
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse();
+
+    let registry = standard_respire_registry!();
+    if args.list {
+        println!("name, db size (MiB), records, record size, batch size");
+        for engine in registry.iter() {
+            println!(
+                "{}, {}, {}, {}, {}",
+                engine.name(),
+                (engine.num_records() * engine.bytes_per_record()) >> 20,
+                engine.num_records(),
+                engine.bytes_per_record(),
+                engine.batch_size()
+            );
+        }
+        return;
+    }
+
+    let shaped;
+    let engine: &dyn DynPIR = match (&args.config, args.db_size) {
+        (Some(name), _) => registry.get(name).unwrap_or_else(|| {
+            eprintln!("unknown config {} (see --list)", name);
+            std::process::exit(1);
+        }),
+        (None, Some(db_size)) => {
+            let shape = DbShape {
+                num_records: db_size.div_ceil(args.record_size),
+                bytes_per_record: args.record_size,
+                batch_size: args.batch_size,
+            };
+            match registry.find(shape.num_records, shape.bytes_per_record, shape.batch_size) {
+                Some(engine) => engine,
+                None => {
+                    shaped = registry.for_shape(shape).unwrap_or_else(|| {
+                        eprintln!("no parameter set can hold {:?} (see --list)", shape);
+                        std::process::exit(1);
+                    });
+                    eprintln!(
+                        "No parameter set has this shape; benchmarking {}, which answers each query with {} of its own",
+                        shaped.host().name(),
+                        shaped.host_queries()
+                    );
+                    &shaped
+                }
+            }
+        }
+        (None, None) => unreachable!("clap requires --db-size, --config or --list"),
+    };
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .expect("failed to build the thread pool");
+    }
+
+    eprintln!("Using parameter set {}", engine.name());
+    if args.noise {
+        write_reports(&engine.run_noise_profile(args.trials), &args);
+    } else {
+        write_reports(&engine.run_benchmark(args.trials), &args);
+    }
+}
+
+fn write_reports<R: Serialize>(report: &R, args: &Args) {
+    if let Some(path) = &args.json {
+        write_json(report, path).expect("failed to write the JSON report");
+    }
+    if let Some(path) = &args.csv {
+        write_csv(report, path).expect("failed to write the CSV report");
+    }
+}
@@ -0,0 +1,140 @@
+use clap::Parser;
+use itertools::Itertools;
+use respire::pir::dyn_pir::DynPIR;
+use respire::pir::net::{recv, send, ClientMessage, Connection, Endpoint, ServerMessage};
+use respire::pir::respire_harness::parse_count;
+use respire::pir::shaped_pir::DbShape;
+use respire::standard_respire_registry;
+use std::io;
+use std::time::Instant;
+
+/// Privately retrieve records from a running respire_server
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the parameter set; must match the server's
+    #[arg(long, required_unless_present = "num_records")]
+    config: Option<String>,
+    /// Query a server started with the same --num-records, --record-size and --batch-size,
+    /// instead of --config
+    #[arg(long, conflicts_with = "config", value_parser = parse_count)]
+    num_records: Option<usize>,
+    #[arg(long, default_value_t = 256, value_parser = parse_count)]
+    record_size: usize,
+    #[arg(long, default_value_t = 1, value_parser = parse_count)]
+    batch_size: usize,
+    /// tcp:HOST:PORT or unix:PATH
+    #[arg(long, default_value = "tcp:127.0.0.1:7878")]
+    connect: Endpoint,
+    /// Do not pad the last batch to the batch size. Its response is shorter, but the server learns
+    /// how many records it asks for.
+    #[arg(long)]
+    truncate: bool,
+    /// Indices of the records to retrieve. They are queried in batches of the config's batch size.
+    #[arg(required = true)]
+    indices: Vec<usize>,
+}
+
+fn expect_reply(conn: &mut Connection) -> io::Result<ServerMessage> {
+    match recv(conn)? {
+        ServerMessage::Error(e) => Err(io::Error::other(e)),
+        msg => Ok(msg),
+    }
+}
+
+fn main() -> io::Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+
+    let registry = standard_respire_registry!();
+    let shaped;
+    let engine: &dyn DynPIR = match (&args.config, args.num_records) {
+        (Some(name), _) => registry.get(name).unwrap_or_else(|| {
+            eprintln!("unknown config {}", name);
+            std::process::exit(1);
+        }),
+        (None, Some(num_records)) => {
+            let shape = DbShape {
+                num_records,
+                bytes_per_record: args.record_size,
+                batch_size: args.batch_size,
+            };
+            shaped = registry.for_shape(shape).unwrap_or_else(|| {
+                eprintln!("no parameter set can hold {:?}", shape);
+                std::process::exit(1);
+            });
+            &shaped
+        }
+        (None, None) => unreachable!("clap requires --config or --num-records"),
+    };
+    if let Some(idx) = args.indices.iter().find(|&&i| i >= engine.num_records()) {
+        eprintln!("index {} out of range (< {})", idx, engine.num_records());
+        std::process::exit(1);
+    }
+
+    let (qk, pp) = engine.setup();
+    let hello = ClientMessage::Hello {
+        config: engine.name().to_string(),
+        public_params: engine.serialize_public_params(&pp),
+    };
+    let mut conn = Connection::connect(&args.connect)?;
+    let begin = Instant::now();
+    send(&mut conn, &hello)?;
+    let ServerMessage::Hello {
+        mut client_id,
+        db_hint,
+    } = expect_reply(&mut conn)?
+    else {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "expected hello"));
+    };
+    let db_hint = engine
+        .deserialize_db_hint(&db_hint)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    eprintln!("Session established in {:?}", Instant::now() - begin);
+
+    for chunk in args.indices.chunks(engine.batch_size()) {
+        let begin = Instant::now();
+        let (query, st) = if args.truncate {
+            engine.query_truncated(&qk, chunk, &db_hint)
+        } else {
+            engine.query(&qk, chunk, &db_hint)
+        }
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        send(
+            &mut conn,
+            &ClientMessage::Query {
+                client_id,
+                query: query.clone(),
+            },
+        )?;
+        let mut reply = expect_reply(&mut conn)?;
+        if let ServerMessage::UnknownClient(_) = reply {
+            // Our session was evicted; register again and retry
+            send(&mut conn, &hello)?;
+            let ServerMessage::Hello { client_id: id, .. } = expect_reply(&mut conn)? else {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "expected hello"));
+            };
+            client_id = id;
+            send(&mut conn, &ClientMessage::Query { client_id, query })?;
+            reply = expect_reply(&mut conn)?;
+        }
+        let ServerMessage::Response(r) = reply else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "expected response",
+            ));
+        };
+        let records = engine
+            .extract(&qk, &r, &st)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        eprintln!("Batch retrieved in {:?}", Instant::now() - begin);
+
+        for (idx, record) in chunk.iter().zip(records) {
+            println!(
+                "{}: {}",
+                idx,
+                record.iter().map(|b| format!("{:02x}", b)).join("")
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,323 @@
+use clap::Parser;
+use log::{info, warn};
+use respire::pir::db_file::{DbContents, DbFileError, SourceFile};
+use respire::pir::dyn_pir::{DynDatabaseHint, DynPIR, DynServer};
+use respire::pir::error::RespireError;
+use respire::pir::net::{
+    is_timeout, recv_limited, send, ClientMessage, Connection, Endpoint, Listener, ServerMessage,
+};
+use respire::pir::records::{RecordFile, RecordFormat};
+use respire::pir::respire_harness::parse_count;
+use respire::pir::session::SessionError;
+use respire::pir::shaped_pir::DbShape;
+use respire::standard_respire_registry;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Answer PIR queries over a database file
+#[derive(Parser, Debug)]
+struct Args {
+    /// Name of the parameter set, e.g. 256m256 or 1g256_16
+    #[arg(long, required_unless_present = "num_records")]
+    config: Option<String>,
+    /// Serve this many records of --record-size bytes, --batch-size per query, on the cheapest
+    /// parameter set that fits them, instead of --config
+    #[arg(long, conflicts_with = "config", value_parser = parse_count)]
+    num_records: Option<usize>,
+    #[arg(long, default_value_t = 256, value_parser = parse_count)]
+    record_size: usize,
+    #[arg(long, default_value_t = 1, value_parser = parse_count)]
+    batch_size: usize,
+    /// Database file. Records past the end of the file are zero.
+    #[arg(long)]
+    db: Option<PathBuf>,
+    /// fixed: records of exactly the config's record size, concatenated; lines: one record per
+    /// line; csv: index,value rows, with hex values prefixed by 0x
+    #[arg(long, value_enum, default_value_t = RecordFormat::Fixed)]
+    format: RecordFormat,
+    /// Encoded database file. If it exists and was encoded from the current --db (by its length
+    /// and modification time), it is memory-mapped; otherwise the database encoded from --db is
+    /// written to it. Without --db, an existing file is used as is.
+    #[arg(long)]
+    encoded_db: Option<PathBuf>,
+    /// tcp:HOST:PORT or unix:PATH
+    #[arg(long, default_value = "tcp:127.0.0.1:7878")]
+    listen: Endpoint,
+    /// Memory for the public parameters of registered clients, in MiB. Least recently used
+    /// clients are evicted beyond this.
+    #[arg(long, default_value_t = 4096)]
+    max_session_mib: usize,
+    /// Connections served at once; further clients wait until one closes
+    #[arg(long, default_value_t = 64)]
+    max_connections: usize,
+    /// Close a connection once a read or write on it has blocked for this many seconds, e.g. on a
+    /// client that stopped sending midway through a message or stays idle. 0 never closes it.
+    #[arg(long, default_value_t = 300)]
+    timeout_secs: u64,
+}
+
+struct Server<'a> {
+    engine: &'a dyn DynPIR,
+    /// Holds the database and the clients' public parameters, and nothing secret
+    server: Box<dyn DynServer + 'a>,
+    db_hint: Vec<u8>,
+    /// Length of the largest message a client sends for this parameter set
+    max_frame_len: u64,
+    timeout: Option<Duration>,
+}
+
+impl<'a> Server<'a> {
+    fn handle(&self, msg: ClientMessage) -> ServerMessage {
+        match msg {
+            ClientMessage::Hello {
+                config,
+                public_params,
+            } => {
+                if config != self.engine.name() {
+                    let msg = format!("server runs {}, not {}", self.engine.name(), config);
+                    return ServerMessage::Error(msg);
+                }
+                let pp = match self.engine.deserialize_public_params(&public_params) {
+                    Ok(pp) => pp,
+                    Err(e) => return ServerMessage::Error(e.to_string()),
+                };
+                match self.server.register_client(pp) {
+                    Ok(client_id) => {
+                        info!(
+                            "registered client {} ({} sessions, {} MiB)",
+                            client_id,
+                            self.server.num_sessions(),
+                            self.server.session_bytes() >> 20
+                        );
+                        ServerMessage::Hello {
+                            client_id,
+                            db_hint: self.db_hint.clone(),
+                        }
+                    }
+                    Err(e) => ServerMessage::Error(e.to_string()),
+                }
+            }
+            ClientMessage::Query { client_id, query } => {
+                let begin = Instant::now();
+                let reply = match self.server.answer(client_id, &query) {
+                    Ok(r) => ServerMessage::Response(r),
+                    Err(RespireError::Session(SessionError::UnknownClient(id))) => {
+                        return ServerMessage::UnknownClient(id);
+                    }
+                    Err(e) => ServerMessage::Error(e.to_string()),
+                };
+                info!(
+                    "answered query from {} in {:?}",
+                    client_id,
+                    Instant::now() - begin
+                );
+                reply
+            }
+        }
+    }
+
+    fn serve(&self, mut conn: Connection) {
+        if let Err(e) = conn.set_timeout(self.timeout) {
+            warn!("failed to set the connection timeout: {}", e);
+            return;
+        }
+        loop {
+            let msg = match recv_limited(&mut conn, self.max_frame_len) {
+                Ok(msg) => msg,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return,
+                Err(e) if is_timeout(&e) => {
+                    info!("closing connection silent for {:?}", self.timeout.unwrap());
+                    return;
+                }
+                Err(e) => {
+                    warn!("failed to read message: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = send(&mut conn, &self.handle(msg)) {
+                warn!("failed to send reply: {}", e);
+                return;
+            }
+        }
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let args = Args::parse();
+
+    let registry = standard_respire_registry!();
+    let shaped;
+    let engine: &dyn DynPIR = match (&args.config, args.num_records) {
+        (Some(name), _) => registry.get(name).unwrap_or_else(|| {
+            eprintln!("unknown config {}; available:", name);
+            for entry in registry.iter() {
+                eprintln!("    {}", entry.name());
+            }
+            std::process::exit(1);
+        }),
+        (None, Some(num_records)) => {
+            let shape = DbShape {
+                num_records,
+                bytes_per_record: args.record_size,
+                batch_size: args.batch_size,
+            };
+            shaped = registry.for_shape(shape).unwrap_or_else(|| {
+                eprintln!("no parameter set can hold {:?}", shape);
+                std::process::exit(1);
+            });
+            &shaped
+        }
+        (None, None) => unreachable!("clap requires --config or --num-records"),
+    };
+    engine.print_summary();
+
+    if args.max_connections == 0 {
+        eprintln!("--max-connections must be at least 1");
+        std::process::exit(1);
+    }
+    let max_session_bytes = args.max_session_mib << 20;
+    if max_session_bytes < engine.params().public_params_memory_size() {
+        eprintln!(
+            "--max-session-mib must be at least {} to hold one client",
+            engine
+                .params()
+                .public_params_memory_size()
+                .div_ceil(1 << 20)
+        );
+        std::process::exit(1);
+    }
+
+    // An encoded database is only used for the current version of --db, if given
+    let contents = DbContents {
+        source: args.db.as_ref().map(|db_path| {
+            SourceFile::of(db_path).unwrap_or_else(|e| {
+                eprintln!("failed to read {}: {}", db_path.display(), e);
+                std::process::exit(1);
+            })
+        }),
+        ..DbContents::default()
+    };
+    let loaded = match &args.encoded_db {
+        Some(path) if path.exists() => {
+            let begin = Instant::now();
+            match engine.load_db(path, &contents) {
+                Ok(loaded) => {
+                    eprintln!("Mapped encoded database in {:?}", Instant::now() - begin);
+                    Some(loaded)
+                }
+                Err(DbFileError::ContentsMismatch { .. }) => {
+                    warn!(
+                        "{} was encoded from another version of the database file, re-encoding",
+                        path.display()
+                    );
+                    None
+                }
+                Err(e) => {
+                    eprintln!("failed to load {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        _ => None,
+    };
+    let (db, db_hint) = loaded.unwrap_or_else(|| {
+        let Some(db_path) = &args.db else {
+            eprintln!("--db is required unless --encoded-db exists");
+            std::process::exit(1);
+        };
+        let records = RecordFile::open(
+            db_path,
+            args.format,
+            engine.bytes_per_record(),
+            engine.num_records(),
+        )
+        .unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {}", db_path.display(), e);
+            std::process::exit(1);
+        });
+        eprintln!(
+            "Loaded {} records from {}",
+            records.len(),
+            db_path.display()
+        );
+        let begin = Instant::now();
+        let (db, db_hint) = engine
+            .encode_db(&|i| records.record(i))
+            .unwrap_or_else(|e| {
+                eprintln!("failed to encode {}: {}", db_path.display(), e);
+                std::process::exit(1);
+            });
+        eprintln!("Encoded database in {:?}", Instant::now() - begin);
+        if let Some(path) = &args.encoded_db {
+            engine
+                .save_db(&db, &db_hint, &contents, path)
+                .unwrap_or_else(|e| {
+                    eprintln!("failed to write {}: {}", path.display(), e);
+                    std::process::exit(1);
+                });
+            eprintln!("Wrote encoded database to {}", path.display());
+        }
+        (db, db_hint)
+    });
+    let max_frame_len = max_client_frame_len(engine, &db_hint);
+    let db_hint = engine.serialize_db_hint(&db_hint);
+
+    let server = Server {
+        engine,
+        server: engine.server(db, max_session_bytes),
+        db_hint,
+        max_frame_len,
+        timeout: (args.timeout_secs > 0).then(|| Duration::from_secs(args.timeout_secs)),
+    };
+
+    let listener = Listener::bind(&args.listen).unwrap_or_else(|e| {
+        eprintln!("failed to listen on {}: {}", args.listen, e);
+        std::process::exit(1);
+    });
+    eprintln!("Listening on {}", args.listen);
+
+    let active = (Mutex::new(0_usize), Condvar::new());
+    thread::scope(|s| loop {
+        {
+            let (count, closed) = &active;
+            let mut count = count.lock().unwrap();
+            while *count >= args.max_connections {
+                count = closed.wait(count).unwrap();
+            }
+            *count += 1;
+        }
+        match listener.accept() {
+            Ok(conn) => {
+                let (server, active) = (&server, &active);
+                s.spawn(move || {
+                    server.serve(conn);
+                    *active.0.lock().unwrap() -= 1;
+                    active.1.notify_one();
+                });
+            }
+            Err(e) => {
+                warn!("failed to accept connection: {}", e);
+                *active.0.lock().unwrap() -= 1;
+            }
+        }
+    });
+}
+
+///
+/// Upper bound on the frames a well-behaved client sends: a hello with public parameters, or a
+/// query for a full batch, both measured on freshly generated ones, plus room for the framing of
+/// the message itself.
+///
+fn max_client_frame_len(engine: &dyn DynPIR, db_hint: &DynDatabaseHint) -> u64 {
+    let (qk, pp) = engine.setup();
+    let batch = (0..engine.batch_size().min(engine.num_records())).collect::<Vec<_>>();
+    let (query, _) = engine
+        .query(&qk, &batch, db_hint)
+        .expect("a batch of the first records is a valid query");
+    let largest = engine.serialize_public_params(&pp).len().max(query.len());
+    (largest + 4096) as u64
+}
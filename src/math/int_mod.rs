@@ -432,28 +432,28 @@ mod test {
         type ZBIG = IntMod<{ u64::MAX - 1 }>;
 
         let a: Z31 = 0_u64.into();
-        assert_eq!(0_u64, a.into());
+        assert_eq!(0_u64, u64::from(a));
 
         let a: Z31 = 1_u64.into();
-        assert_eq!(1_u64, a.into());
+        assert_eq!(1_u64, u64::from(a));
 
         let a: Z31 = 30_u64.into();
-        assert_eq!(30_u64, a.into());
+        assert_eq!(30_u64, u64::from(a));
 
         let a: Z31 = 31_u64.into();
-        assert_eq!(0_u64, a.into());
+        assert_eq!(0_u64, u64::from(a));
 
         let a: Z31 = 32_u64.into();
-        assert_eq!(1_u64, a.into());
+        assert_eq!(1_u64, u64::from(a));
 
         let a: Z31 = ((31 * 439885 + 4) as u64).into();
-        assert_eq!(4_u64, a.into());
+        assert_eq!(4_u64, u64::from(a));
 
         let a: ZBIG = (u64::MAX - 1).into();
-        assert_eq!(0_u64, a.into());
+        assert_eq!(0_u64, u64::from(a));
 
         let a: ZBIG = u64::MAX.into();
-        assert_eq!(1_u64, a.into());
+        assert_eq!(1_u64, u64::from(a));
 
         let a: i64 = Z31::from(0_u64).into();
         assert_eq!(0_i64, a);
@@ -481,7 +481,7 @@ mod test {
 
         let a: Z31 = 10_u64.into();
         let b: Z31 = -a;
-        assert_eq!(21_u64, b.into());
+        assert_eq!(21_u64, u64::from(b));
 
         let a: Z31 = 0_u64.into();
         let b: Z31 = -a;
@@ -489,49 +489,49 @@ mod test {
 
         let mut a: Z31 = 23_u64.into();
         let b: Z31 = 24_u64.into();
-        assert_eq!(16_u64, (a + b).into());
+        assert_eq!(16_u64, u64::from(a + b));
         a += Z31::from(24_u64);
-        assert_eq!(16_u64, a.into());
+        assert_eq!(16_u64, u64::from(a));
 
         let mut a: Z31 = 23_u64.into();
         let b: Z31 = 24_u64.into();
-        assert_eq!(30_u64, (a - b).into());
+        assert_eq!(30_u64, u64::from(a - b));
         a -= Z31::from(24_u64);
-        assert_eq!(30_u64, a.into());
+        assert_eq!(30_u64, u64::from(a));
 
         let mut a: Z31 = 16_u64.into();
         let b: Z31 = 3_u64.into();
-        assert_eq!(17_u64, (a * b).into());
+        assert_eq!(17_u64, u64::from(a * b));
         a *= Z31::from(3_u64);
-        assert_eq!(17_u64, a.into());
+        assert_eq!(17_u64, u64::from(a));
 
         let a: ZBIG = 10_u64.into();
         let b: ZBIG = -a;
-        assert_eq!(u64::MAX - 10 - 1, b.into());
+        assert_eq!(u64::MAX - 10 - 1, u64::from(b));
 
         let mut a: ZBIG = (u64::MAX - 50005).into();
         let b: ZBIG = 60006_u64.into();
-        assert_eq!(10002_u64, (a + b).into());
+        assert_eq!(10002_u64, u64::from(a + b));
         a += ZBIG::from(60006_u64);
-        assert_eq!(10002_u64, a.into());
+        assert_eq!(10002_u64, u64::from(a));
 
         let mut a: ZBIG = 50005_u64.into();
         let b: ZBIG = 70007_u64.into();
-        assert_eq!(u64::MAX - 20003, (a - b).into());
+        assert_eq!(u64::MAX - 20003, u64::from(a - b));
         a -= ZBIG::from(70007_u64);
-        assert_eq!(u64::MAX - 20003, a.into());
+        assert_eq!(u64::MAX - 20003, u64::from(a));
 
         let mut a: ZBIG = (u64::MAX - 1 - 1984).into();
         let b: ZBIG = (u64::MAX - 1 - 3968).into();
-        assert_eq!(7872512_u64, (a * b).into());
+        assert_eq!(7872512_u64, u64::from(a * b));
         a *= ZBIG::from(u64::MAX - 1 - 3968);
-        assert_eq!(7872512_u64, a.into());
+        assert_eq!(7872512_u64, u64::from(a));
 
         let mut a: ZBIG = (u64::MAX - 1 - 1984).into();
         let b: ZBIG = 3968_u64.into();
-        assert_eq!(u64::MAX - 1 - 7872512, (a * b).into());
+        assert_eq!(u64::MAX - 1 - 7872512, u64::from(a * b));
         a *= ZBIG::from(3968_u64);
-        assert_eq!(u64::MAX - 1 - 7872512, a.into());
+        assert_eq!(u64::MAX - 1 - 7872512, u64::from(a));
     }
 
     #[test]
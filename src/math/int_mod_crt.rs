@@ -283,22 +283,22 @@ mod test {
         type Z55 = IntModCRT<5, 11>;
 
         let a: Z55 = 0_u64.into();
-        assert_eq!(0_u64, a.into());
+        assert_eq!(0_u64, u64::from(a));
 
         let a: Z55 = 1_u64.into();
-        assert_eq!(1_u64, a.into());
+        assert_eq!(1_u64, u64::from(a));
 
         let a: Z55 = 54_u64.into();
-        assert_eq!(54_u64, a.into());
+        assert_eq!(54_u64, u64::from(a));
 
         let a: Z55 = 55_u64.into();
-        assert_eq!(0_u64, a.into());
+        assert_eq!(0_u64, u64::from(a));
 
         let a: Z55 = 56_u64.into();
-        assert_eq!(1_u64, a.into());
+        assert_eq!(1_u64, u64::from(a));
 
         let a: Z55 = ((55 * 439885 + 16) as u64).into();
-        assert_eq!(16_u64, a.into());
+        assert_eq!(16_u64, u64::from(a));
     }
 
     #[test]
@@ -307,7 +307,7 @@ mod test {
 
         let a: Z55 = 21_u64.into();
         let b: Z55 = -a;
-        assert_eq!(34_u64, b.into());
+        assert_eq!(34_u64, u64::from(b));
 
         let a: Z55 = 0_u64.into();
         let b: Z55 = -a;
@@ -315,21 +315,21 @@ mod test {
 
         let mut a: Z55 = 23_u64.into();
         let b: Z55 = 45_u64.into();
-        assert_eq!(13_u64, (a + b).into());
+        assert_eq!(13_u64, u64::from(a + b));
         a += Z55::from(45_u64);
-        assert_eq!(13_u64, a.into());
+        assert_eq!(13_u64, u64::from(a));
 
         let mut a: Z55 = 23_u64.into();
         let b: Z55 = 45_u64.into();
-        assert_eq!(33_u64, (a - b).into());
+        assert_eq!(33_u64, u64::from(a - b));
         a -= Z55::from(45_u64);
-        assert_eq!(33_u64, a.into());
+        assert_eq!(33_u64, u64::from(a));
 
         let mut a: Z55 = 16_u64.into();
         let b: Z55 = 4_u64.into();
-        assert_eq!(9_u64, (a * b).into());
+        assert_eq!(9_u64, u64::from(a * b));
         a *= Z55::from(4_u64);
-        assert_eq!(9_u64, a.into());
+        assert_eq!(9_u64, u64::from(a));
     }
 
     #[test]
@@ -24,6 +24,19 @@ pub const SIMD_LANES: usize = 1;
 #[cfg(not(target_feature = "avx2"))]
 pub type SimdVec = u64;
 
+///
+/// View a slice of `SimdVec`s as the `u64`s in all their lanes, in order.
+///
+pub fn simd_lanes(v: &[SimdVec]) -> &[u64] {
+    // Safety: SimdVec has the repr of [u64; SIMD_LANES]
+    unsafe { std::slice::from_raw_parts(v.as_ptr().cast::<u64>(), v.len() * SIMD_LANES) }
+}
+
+pub fn simd_lanes_mut(v: &mut [SimdVec]) -> &mut [u64] {
+    // Safety: see simd_lanes
+    unsafe { std::slice::from_raw_parts_mut(v.as_mut_ptr().cast::<u64>(), v.len() * SIMD_LANES) }
+}
+
 ///
 /// Executes `s += a * b` on all four lanes. `a` and `b` are 32 bit; `s` is 64 bit.
 ///
@@ -0,0 +1,157 @@
+//! Records carrying a checksum, to detect decryption failures.
+//!
+//! The parameters are chosen so that decryption fails rarely (see `params_error_rate_estimate`),
+//! but a failure silently yields garbage. Each record here starts with a checksum of its index and
+//! value, which the client verifies after decoding. Including the index also catches records
+//! returned from the wrong position. A corrupted record can simply be queried again.
+
+use crate::pir::error::{CorruptedRecords, RespireError};
+use crate::pir::pir::{PIRRecordBytes, PIR};
+use siphasher::sip::SipHasher24;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+/// Length of the checksum at the start of each record
+pub const CHECKSUM_BYTES: usize = 4;
+
+pub struct CheckedState<S> {
+    state: S,
+    idxs: Vec<usize>,
+}
+
+///
+/// `ThePIR` with a checksum in each record, leaving `VALUE_BYTES` for the value itself.
+///
+pub struct CheckedPIR<ThePIR: PIR> {
+    phantom: PhantomData<ThePIR>,
+}
+
+impl<ThePIR: PIR> CheckedPIR<ThePIR> {
+    pub const VALUE_BYTES: usize = ThePIR::BYTES_PER_RECORD - CHECKSUM_BYTES;
+
+    ///
+    /// Encode values of up to `VALUE_BYTES` bytes, which are zero-padded. Panics if a value is
+    /// longer.
+    ///
+    pub fn encode_db<F: Fn(usize) -> Vec<u8>>(
+        values_generator: F,
+    ) -> (ThePIR::Database, ThePIR::DatabaseHint) {
+        ThePIR::encode_db(|i| Self::record(i, &values_generator(i)), None)
+    }
+
+    pub fn record(idx: usize, value: &[u8]) -> ThePIR::RecordBytes {
+        assert!(
+            value.len() <= Self::VALUE_BYTES,
+            "value of {} bytes exceeds the maximum of {}",
+            value.len(),
+            Self::VALUE_BYTES
+        );
+        let mut padded = value.to_vec();
+        padded.resize(Self::VALUE_BYTES, 0);
+        let mut bytes = Self::checksum(idx, &padded).to_vec();
+        bytes.extend(padded);
+        ThePIR::RecordBytes::from_bytes(&bytes).unwrap()
+    }
+
+    pub fn checksum(idx: usize, value: &[u8]) -> [u8; CHECKSUM_BYTES] {
+        let mut hasher = SipHasher24::new();
+        hasher.write(&(idx as u64).to_le_bytes());
+        hasher.write(value);
+        hasher.finish().to_le_bytes()[..CHECKSUM_BYTES]
+            .try_into()
+            .unwrap()
+    }
+
+    pub fn query(
+        qk: &ThePIR::QueryKey,
+        idxs: &[usize],
+        db_hint: &ThePIR::DatabaseHint,
+    ) -> Result<(ThePIR::Query, CheckedState<ThePIR::State>), RespireError> {
+        let (q, state) = ThePIR::query(qk, idxs, db_hint, None)?;
+        let st = CheckedState {
+            state,
+            idxs: idxs.to_vec(),
+        };
+        Ok((q, st))
+    }
+
+    pub fn answer(
+        pp: &ThePIR::PublicParams,
+        db: &ThePIR::Database,
+        q: &ThePIR::Query,
+    ) -> Result<ThePIR::Response, RespireError> {
+        ThePIR::answer(pp, db, q, None)
+    }
+
+    /// The (padded) values of the batch, or `RespireError::Corrupted` if some failed verification
+    pub fn extract(
+        qk: &ThePIR::QueryKey,
+        r: &ThePIR::Response,
+        st: &CheckedState<ThePIR::State>,
+    ) -> Result<Vec<Vec<u8>>, RespireError> {
+        let records = ThePIR::extract(qk, r, &st.state, None)?;
+        let verified = records
+            .iter()
+            .zip(st.idxs.iter())
+            .map(|(record, &idx)| {
+                let (checksum, value) = record.as_bytes().split_at(CHECKSUM_BYTES);
+                (checksum == Self::checksum(idx, value)).then(|| value.to_vec())
+            })
+            .collect::<Vec<_>>();
+        if verified.iter().all(Option::is_some) {
+            Ok(verified.into_iter().flatten().collect())
+        } else {
+            Err(RespireError::Corrupted(CorruptedRecords {
+                records: verified,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::respire_harness::CuckooRespireTestSmall;
+
+    #[test]
+    fn test_checked() {
+        type ThePIR = CheckedPIR<CuckooRespireTestSmall>;
+        let value = |i: usize| format!("value {}", i).into_bytes();
+        let padded = |i: usize| {
+            let mut bytes = value(i);
+            bytes.resize(ThePIR::VALUE_BYTES, 0);
+            bytes
+        };
+        // Record 7 is stored in place of 8, and record 9 is garbled
+        let (db, hint) = CuckooRespireTestSmall::encode_db(
+            |i| match i {
+                8 => ThePIR::record(7, &value(7)),
+                9 => {
+                    let mut record = ThePIR::record(9, &value(9)).as_bytes().to_vec();
+                    record[CHECKSUM_BYTES + 2] ^= 1;
+                    <CuckooRespireTestSmall as PIR>::RecordBytes::from_bytes(&record).unwrap()
+                }
+                i => ThePIR::record(i, &value(i)),
+            },
+            None,
+        );
+        let (qk, pp) = CuckooRespireTestSmall::setup(None);
+
+        let (q, st) = ThePIR::query(&qk, &[3, 59], &hint).unwrap();
+        let r = ThePIR::answer(&pp, &db, &q).unwrap();
+        assert_eq!(
+            ThePIR::extract(&qk, &r, &st).unwrap(),
+            vec![padded(3), padded(59)]
+        );
+
+        for (idxs, positions) in [([8, 4], vec![0]), ([4, 9], vec![1])] {
+            let (q, st) = ThePIR::query(&qk, &idxs, &hint).unwrap();
+            let r = ThePIR::answer(&pp, &db, &q).unwrap();
+            let Err(RespireError::Corrupted(err)) = ThePIR::extract(&qk, &r, &st) else {
+                panic!("corruption not detected");
+            };
+            assert_eq!(err.positions(), positions);
+            assert_eq!(err.records[1 - positions[0]], Some(padded(4)));
+        }
+    }
+}
@@ -0,0 +1,308 @@
+//! Choosing the number of buckets and the bucket dimensions for cuckoo batch PIR.
+//!
+//! Two things can go wrong with a cuckoo configuration:
+//! - A client cannot assign its batch to distinct buckets. By Hall's theorem this happens exactly
+//!   when some `s` of the queried records hash only into `s - 1` buckets, so a union bound over
+//!   the record subsets and bucket sets bounds the failure probability. The bound is dominated by
+//!   small subsets, e.g. two records whose hashes all land in the same bucket.
+//! - A bucket receives more records than it can hold when the database is encoded. The load of a
+//!   bucket is binomial with mean `num_hash * num_records / num_bucket`, and `encode_db` simply
+//!   draws a new hash key when a bucket overflows, so this only needs to be unlikely enough that
+//!   few keys are drawn.
+
+use crate::pir::respire::RespireParamsExpanded;
+use crate::pir::respire_harness::FactoryParams;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// The bucket parameters, up to the dimensions chosen here
+const TEMPLATE: RespireParamsExpanded = FactoryParams::batch_256(2, 1, 1, 1).expand().expand();
+/// Records per base database ring element, and per response ring element
+const PACK_RATIO_DB: usize = TEMPLATE.pack_ratio_db();
+const PACK_RATIO_RESPONSE: usize = TEMPLATE.pack_ratio_response();
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CuckooPlan {
+    pub num_records: usize,
+    pub batch_size: usize,
+    pub num_hash: usize,
+    pub num_bucket: usize,
+    /// Dimensions of each bucket's database, which holds `bucket_capacity` records
+    pub nu1: usize,
+    pub nu2: usize,
+    pub n_vec: usize,
+    /// log2 of the bound on the probability that a batch cannot be assigned to buckets
+    pub failure_log2: f64,
+    pub bucket_capacity: usize,
+    pub mean_bucket_load: f64,
+    /// Bucket capacity minus the mean load, in standard deviations of the load
+    pub overflow_margin: f64,
+    /// log2 of the estimated probability that some bucket overflows for a given hash key
+    pub overflow_log2: f64,
+}
+
+impl CuckooPlan {
+    pub const fn factory_params(&self) -> FactoryParams {
+        FactoryParams::batch_256(self.num_bucket, self.n_vec, self.nu1, self.nu2)
+    }
+}
+
+impl fmt::Display for CuckooPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} records, batch size {}, {} hash functions",
+            self.num_records, self.batch_size, self.num_hash
+        )?;
+        writeln!(
+            f,
+            "Buckets: {} (insertion failure probability <= 2^({:.1}))",
+            self.num_bucket, self.failure_log2
+        )?;
+        writeln!(
+            f,
+            "Bucket capacity: {} (NU1 = {}, NU2 = {}), mean load {:.0}",
+            self.bucket_capacity, self.nu1, self.nu2, self.mean_bucket_load
+        )?;
+        writeln!(
+            f,
+            "Overflow margin: {:.2} standard deviations (overflow probability ~ 2^({:.1}) per hash key)",
+            self.overflow_margin, self.overflow_log2
+        )?;
+        write!(
+            f,
+            "Configuration: CuckooRespireImpl<{}, {}, {}, respire!(FactoryParams::batch_256({}, {}, {}, {}).expand().expand())>",
+            self.batch_size,
+            self.num_bucket,
+            self.num_records,
+            self.num_bucket,
+            self.n_vec,
+            self.nu1,
+            self.nu2
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PlanError {
+    /// A batch retrieves at least one record
+    EmptyBatch,
+    /// Batches of a single record need no cuckoo hashing
+    BatchTooSmall(usize),
+    /// With a single hash function, records colliding in it can never be assigned
+    TooFewHashes(usize),
+    TooFewRecords {
+        num_records: usize,
+        batch_size: usize,
+    },
+    /// A target probability is not a finite log2
+    InvalidTarget(f64),
+}
+
+impl fmt::Display for PlanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanError::EmptyBatch => write!(f, "batch size must be at least 1"),
+            PlanError::BatchTooSmall(batch_size) => {
+                write!(f, "batch size {} is too small, need at least 2", batch_size)
+            }
+            PlanError::TooFewHashes(num_hash) => {
+                write!(
+                    f,
+                    "{} hash functions are too few, need at least 2",
+                    num_hash
+                )
+            }
+            PlanError::TooFewRecords {
+                num_records,
+                batch_size,
+            } => write!(
+                f,
+                "{} records are fewer than the batch size {}",
+                num_records, batch_size
+            ),
+            PlanError::InvalidTarget(log2) => write!(f, "invalid target probability 2^({})", log2),
+        }
+    }
+}
+
+impl std::error::Error for PlanError {}
+
+// ln(k!) for k = 0..len
+fn ln_factorials(len: usize) -> Vec<f64> {
+    let mut result = Vec::with_capacity(len);
+    let mut acc = 0_f64;
+    result.push(acc);
+    for k in 1..len {
+        acc += (k as f64).ln();
+        result.push(acc);
+    }
+    result
+}
+
+fn log_sum_exp(terms: impl Iterator<Item = f64>) -> f64 {
+    let terms: Vec<f64> = terms.collect();
+    let max = terms.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    if max == f64::NEG_INFINITY {
+        return max;
+    }
+    max + terms.iter().map(|t| (t - max).exp()).sum::<f64>().ln()
+}
+
+///
+/// log2 of the union bound on the probability that `batch_size` records, each hashed to
+/// `num_hash` independent uniform buckets out of `num_bucket`, cannot be assigned to distinct
+/// buckets.
+///
+pub fn cuckoo_failure_log2(batch_size: usize, num_bucket: usize, num_hash: usize) -> f64 {
+    let ln_fact = ln_factorials(batch_size.max(num_bucket) + 1);
+    let ln_choose = |n: usize, k: usize| ln_fact[n] - ln_fact[k] - ln_fact[n - k];
+    let m = num_bucket as f64;
+    // Some s records hash into some s - 1 buckets
+    let ln_bound = log_sum_exp((2..=batch_size.min(num_bucket + 1)).map(|s| {
+        ln_choose(batch_size, s)
+            + ln_choose(num_bucket, s - 1)
+            + (num_hash * s) as f64 * ((s - 1) as f64 / m).ln()
+    }));
+    ln_bound.min(0_f64) / 2_f64.ln()
+}
+
+///
+/// log2 of the probability that one of `num_bucket` buckets gets more than `capacity` records,
+/// using the normal approximation of each bucket's load and a union bound over the buckets.
+/// Returns the estimate and the margin in standard deviations.
+///
+fn overflow_log2(
+    num_records: usize,
+    num_hash: usize,
+    num_bucket: usize,
+    capacity: usize,
+) -> (f64, f64) {
+    let p = 1_f64 / num_bucket as f64;
+    let mean = (num_records * num_hash) as f64 * p;
+    let stddev = (mean * (1_f64 - p)).sqrt();
+    let margin = (capacity as f64 - mean) / stddev;
+    if margin <= 1_f64 {
+        return (0_f64, margin);
+    }
+    // Gaussian tail bound Q(z) <= phi(z) / z
+    let ln_tail = -margin * margin / 2_f64 - (margin * (2_f64 * PI).sqrt()).ln();
+    let ln_overflow = (num_bucket as f64).ln() + ln_tail;
+    (ln_overflow.min(0_f64) / 2_f64.ln(), margin)
+}
+
+///
+/// Choose the fewest buckets for which the insertion failure probability is at most
+/// `2^failure_log2`, and the smallest bucket databases (as `batch_256` dimensions) for which the
+/// overflow probability is at most `2^overflow_log2`.
+///
+pub fn plan_cuckoo(
+    num_records: usize,
+    batch_size: usize,
+    num_hash: usize,
+    failure_log2: f64,
+    overflow_log2_target: f64,
+) -> Result<CuckooPlan, PlanError> {
+    if batch_size < 2 {
+        return Err(PlanError::BatchTooSmall(batch_size));
+    }
+    if num_hash < 2 {
+        return Err(PlanError::TooFewHashes(num_hash));
+    }
+    if num_records < batch_size {
+        return Err(PlanError::TooFewRecords {
+            num_records,
+            batch_size,
+        });
+    }
+    if let Some(&log2) = [failure_log2, overflow_log2_target]
+        .iter()
+        .find(|log2| !log2.is_finite())
+    {
+        return Err(PlanError::InvalidTarget(log2));
+    }
+
+    // The bound decreases with the number of buckets, so search for the first that is enough
+    let enough = |m: usize| cuckoo_failure_log2(batch_size, m, num_hash) <= failure_log2;
+    let mut hi = batch_size;
+    while !enough(hi) {
+        hi *= 2;
+    }
+    let mut lo = hi / 2;
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if enough(mid) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    let num_bucket = hi;
+
+    let mut nu = 0_usize;
+    let (overflow, margin) = loop {
+        let capacity = PACK_RATIO_DB << nu;
+        let (overflow, margin) = overflow_log2(num_records, num_hash, num_bucket, capacity);
+        if overflow <= overflow_log2_target {
+            break (overflow, margin);
+        }
+        nu += 1;
+    };
+
+    Ok(CuckooPlan {
+        num_records,
+        batch_size,
+        num_hash,
+        num_bucket,
+        nu1: nu.div_ceil(2),
+        nu2: nu / 2,
+        n_vec: num_bucket
+            .div_ceil(PACK_RATIO_RESPONSE)
+            .min(FactoryParams::MAX_N_VEC),
+        failure_log2: cuckoo_failure_log2(batch_size, num_bucket, num_hash),
+        bucket_capacity: PACK_RATIO_DB << nu,
+        mean_bucket_load: (num_records * num_hash) as f64 / num_bucket as f64,
+        overflow_margin: margin,
+        overflow_log2: overflow,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        // Two records colliding in all three hashes dominates for small batches
+        let two = cuckoo_failure_log2(2, 7, 3);
+        assert!((two - (-5_f64 * 7_f64.log2())).abs() < 0.01);
+        assert!(cuckoo_failure_log2(8, 7, 3) == 0_f64);
+
+        let plan = plan_cuckoo(1 << 20, 256, 3, -20_f64, -1_f64).unwrap();
+        assert!(plan.failure_log2 <= -20_f64);
+        assert!(cuckoo_failure_log2(256, plan.num_bucket - 1, 3) > -20_f64);
+        assert_eq!(plan.bucket_capacity, 4 << (plan.nu1 + plan.nu2));
+        assert!(plan.overflow_log2 <= -1_f64 && plan.overflow_margin > 0_f64);
+        // Half the capacity would not be enough
+        let (half, _) = overflow_log2(1 << 20, 3, plan.num_bucket, plan.bucket_capacity / 2);
+        assert!(half > -1_f64);
+        assert_eq!(plan.n_vec, 8);
+
+        assert_eq!(
+            plan_cuckoo(1 << 20, 1, 3, -20_f64, -1_f64),
+            Err(PlanError::BatchTooSmall(1))
+        );
+        assert_eq!(
+            plan_cuckoo(1 << 20, 16, 1, -20_f64, -1_f64),
+            Err(PlanError::TooFewHashes(1))
+        );
+        assert!(matches!(
+            plan_cuckoo(8, 16, 3, -20_f64, -1_f64),
+            Err(PlanError::TooFewRecords { .. })
+        ));
+        assert!(matches!(
+            plan_cuckoo(1 << 20, 16, 3, f64::NEG_INFINITY, -1_f64),
+            Err(PlanError::InvalidTarget(_))
+        ));
+    }
+}
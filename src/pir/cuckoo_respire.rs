@@ -1,28 +1,180 @@
-use crate::pir::pir::{Stats, PIR};
+use crate::pir::db_file::{map_db_file, write_db_file, DbContents, DbFileError, DbFileHeader};
+use crate::pir::error::RespireError;
+use crate::pir::noise::NoiseSamples;
+use crate::pir::pir::{par_map_stats, PIRSummary, Stats, PIR};
 use crate::pir::respire::Respire;
 use itertools::Itertools;
 use log::{info, warn};
 use rand::{thread_rng, Rng};
 use rayon::prelude::*;
 use serde::ser::SerializeSeq;
-use serde::{Serialize, Serializer};
-use std::collections::hash_map::DefaultHasher;
+use serde::{Deserialize, Serialize, Serializer};
+use siphasher::sip::SipHasher24;
 use std::collections::HashMap;
-use std::hash::{Hash, Hasher};
+use std::fmt;
+use std::hash::Hasher;
+use std::io;
 use std::marker::PhantomData;
-use std::sync::Arc;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
+/// Evictions of a single item after which cuckoo insertion gives up
+pub const CUCKOO_MAX_DEPTH: usize = 1 << 16;
+/// Hash keys `encode_db` tries before giving up on buckets overflowing
+pub const CUCKOO_MAX_KEYS: usize = 16;
+
 pub trait CuckooRespire: PIR {
     type BaseRespire: PIR + Respire;
     const NUM_BUCKET: usize;
+    const NUM_HASH: usize;
+    const STASH_SIZE: usize;
+}
+
+///
+/// One query per bucket, followed by one per stash entry, which goes to the bucket at the same
+/// position in `stash_buckets`.
+///
+#[derive(Serialize, Deserialize)]
+pub struct CuckooQuery<Q> {
+    pub queries: Vec<Q>,
+    pub stash_buckets: Vec<usize>,
+}
+
+///
+/// Everything needed to locate records in the buckets. Each bucket holds the records hashing to
+/// it, sorted by index (a record hashing to a bucket twice is stored twice), followed by empty
+/// slots. The layout is thus determined by the hash key and the number of records, see
+/// `CuckooRespireImpl::bucket_slots`.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CuckooHint {
+    /// Key of the hash assigning records to buckets, see `CuckooRespireImpl::idx_to_buckets`
+    pub hash_key: [u8; 16],
+    /// Records `0..num_records` are in the database, including appended ones
+    pub num_records: usize,
+    /// Number of occupied slots in each bucket
+    pub bucket_sizes: Vec<usize>,
+    #[serde(skip)]
+    slot_cache: SlotCache,
 }
 
+///
+/// The slot of every record in each of its buckets, for records `0..num_records()`, see
+/// `CuckooRespireImpl::bucket_slots`. Since records only ever get appended, a table for fewer
+/// records is a prefix of the table for more.
+///
+#[derive(Clone, Default)]
+struct SlotTable {
+    hash_key: [u8; 16],
+    num_bucket: usize,
+    num_hash: usize,
+    /// `slots[i * num_hash + h]` is the slot of record `i` in its `h`-th bucket
+    slots: Vec<u32>,
+    /// Number of slots taken by the records in the table, per bucket
+    bucket_sizes: Vec<usize>,
+}
+
+impl SlotTable {
+    fn num_records(&self) -> usize {
+        self.slots.len().checked_div(self.num_hash).unwrap_or(0)
+    }
+}
+
+/// A [`SlotTable`] built on first use, shared by clones of a hint and ignored when comparing them
+#[derive(Clone, Default)]
+struct SlotCache(Arc<Mutex<Arc<SlotTable>>>);
+
+impl PartialEq for SlotCache {
+    fn eq(&self, _: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SlotCache {}
+
+impl fmt::Debug for SlotCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SlotCache")
+    }
+}
+
+///
+/// The change to a [`CuckooHint`] from appending records, to be applied to the clients' copies.
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CuckooHintDelta {
+    /// The number of records of the hint this delta applies to
+    pub base_num_records: usize,
+    pub num_records: usize,
+    pub bucket_sizes: Vec<usize>,
+}
+
+impl CuckooHint {
+    pub fn apply(&mut self, delta: &CuckooHintDelta) -> Result<(), CuckooError> {
+        if self.num_records != delta.base_num_records {
+            return Err(CuckooError::StaleHint {
+                expected: delta.base_num_records,
+                found: self.num_records,
+            });
+        }
+        self.num_records = delta.num_records;
+        self.bucket_sizes.clone_from(&delta.bucket_sizes);
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CuckooError {
+    /// The record index is past the end of the database
+    UnknownRecord(usize),
+    /// An appended record hashes to a bucket with no free slot left
+    BucketFull(usize),
+    /// A hint delta was applied to a hint with a different number of records
+    StaleHint { expected: usize, found: usize },
+    /// Cuckoo insertion found no assignment of the queried records to buckets
+    InsertionFailed,
+    /// Some bucket overflowed for every one of this many hash keys tried while encoding
+    BucketsOverflow(usize),
+}
+
+impl fmt::Display for CuckooError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CuckooError::UnknownRecord(idx) => write!(f, "record {} is not in the database", idx),
+            CuckooError::BucketFull(bucket) => write!(f, "bucket {} is full", bucket),
+            CuckooError::StaleHint { expected, found } => write!(
+                f,
+                "hint delta applies to {} records, but the hint has {}",
+                expected, found
+            ),
+            CuckooError::InsertionFailed => {
+                write!(f, "could not assign the queried records to buckets")
+            }
+            CuckooError::BucketsOverflow(num_keys) => write!(
+                f,
+                "buckets overflowed for {} hash keys; use cuckoo_plan to choose larger buckets",
+                num_keys
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CuckooError {}
+
+///
+/// Batch PIR by cuckoo hashing the queried records into buckets, each of which is a `BaseRespire`
+/// database. Every record is stored in the `NUM_HASH` buckets it hashes to. Besides one query per
+/// bucket, each batch makes `STASH_SIZE` extra queries to buckets chosen at random, which give the
+/// cuckoo insertion room to place records it could not fit otherwise.
+///
 pub struct CuckooRespireImpl<
     const BATCH_SIZE: usize,
     const NUM_BUCKET: usize,
     const NUM_RECORDS: usize,
     BaseRespire: PIR + Respire,
+    const NUM_HASH: usize = 3,
+    const STASH_SIZE: usize = 0,
 > {
     phantom: PhantomData<BaseRespire>,
 }
@@ -32,10 +184,15 @@ impl<
         const NUM_BUCKET: usize,
         const NUM_RECORDS: usize,
         BaseRespire: PIR + Respire,
-    > CuckooRespire for CuckooRespireImpl<BATCH_SIZE, NUM_BUCKET, NUM_RECORDS, BaseRespire>
+        const NUM_HASH: usize,
+        const STASH_SIZE: usize,
+    > CuckooRespire
+    for CuckooRespireImpl<BATCH_SIZE, NUM_BUCKET, NUM_RECORDS, BaseRespire, NUM_HASH, STASH_SIZE>
 {
     type BaseRespire = BaseRespire;
     const NUM_BUCKET: usize = NUM_BUCKET;
+    const NUM_HASH: usize = NUM_HASH;
+    const STASH_SIZE: usize = STASH_SIZE;
 }
 
 impl<
@@ -43,16 +200,19 @@ impl<
         const NUM_BUCKET: usize,
         const NUM_RECORDS: usize,
         BaseRespire: PIR + Respire,
-    > PIR for CuckooRespireImpl<BATCH_SIZE, NUM_BUCKET, NUM_RECORDS, BaseRespire>
+        const NUM_HASH: usize,
+        const STASH_SIZE: usize,
+    > PIR
+    for CuckooRespireImpl<BATCH_SIZE, NUM_BUCKET, NUM_RECORDS, BaseRespire, NUM_HASH, STASH_SIZE>
 {
     type QueryKey = BaseRespire::QueryKey;
     type PublicParams = BaseRespire::PublicParams;
 
-    type Query = Vec<BaseRespire::QueryOne>;
+    type Query = CuckooQuery<BaseRespire::QueryOne>;
 
     type Response = Vec<BaseRespire::AnswerOneCompressed>;
     type Database = Vec<<BaseRespire as PIR>::Database>;
-    type DatabaseHint = Vec<Vec<Option<usize>>>;
+    type DatabaseHint = CuckooHint;
     type State = Vec<(usize, usize)>;
     type RecordBytes = BaseRespire::RecordBytes;
     const BYTES_PER_RECORD: usize = BaseRespire::BYTES_PER_RECORD;
@@ -68,60 +228,345 @@ impl<
             (BaseRespire::BYTES_PER_RECORD * Self::NUM_RECORDS) as f64 / 1024_f64 / 1024_f64,
         );
         eprintln!(
-            "Cuckoo hashing with 3 hash functions, {} batch size, {} buckets, {} bucket size",
+            "Cuckoo hashing with {} hash functions, {} batch size, {} buckets, {} stash queries, {} bucket size",
+            Self::NUM_HASH,
             Self::BATCH_SIZE,
             Self::NUM_BUCKET,
+            Self::STASH_SIZE,
             BaseRespire::DB_SIZE,
         );
-        eprintln!("Parameters (base RESPIRE): {:#?}", BaseRespire::params());
-        eprintln!(
-            "Public param size: {:.3} KiB",
-            BaseRespire::params_public_param_size() as f64 / 1024_f64
+        Self::summary().print();
+    }
+
+    fn summary() -> PIRSummary {
+        let (response_size, response_full_vecs, response_rem) = Self::params_response_info();
+        PIRSummary {
+            num_records: Self::NUM_RECORDS,
+            bytes_per_record: Self::BYTES_PER_RECORD,
+            batch_size: Self::BATCH_SIZE,
+            query_size: Self::params_query_size(),
+            response_size,
+            response_full_vecs,
+            response_rem,
+            record_size: Self::params_record_size(),
+            rate: Self::params_rate(),
+            ..BaseRespire::summary()
+        }
+    }
+
+    fn encode_db<F: Fn(usize) -> Self::RecordBytes>(
+        records_generator: F,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> (Self::Database, Self::DatabaseHint) {
+        Self::try_encode_db(records_generator, time_stats).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    fn try_encode_db<F: Fn(usize) -> Self::RecordBytes>(
+        records_generator: F,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(Self::Database, Self::DatabaseHint), RespireError> {
+        let mut rng = thread_rng();
+        for _ in 0..CUCKOO_MAX_KEYS {
+            let hash_key = rng.gen();
+            let max_count = Self::bucket_sizes(&hash_key).into_iter().max().unwrap();
+            if max_count <= BaseRespire::DB_SIZE {
+                return Ok(Self::encode_db_with_key(
+                    hash_key,
+                    records_generator,
+                    time_stats,
+                ));
+            }
+            warn!(
+                "Cuckoo DB encoding: bucket of size {} overflows {}, trying another hash key",
+                max_count,
+                BaseRespire::DB_SIZE
+            );
+        }
+        Err(CuckooError::BucketsOverflow(CUCKOO_MAX_KEYS).into())
+    }
+
+    fn setup(time_stats: Option<&mut Stats<Duration>>) -> (Self::QueryKey, Self::PublicParams) {
+        BaseRespire::setup(time_stats)
+    }
+
+    fn query(
+        qk: &Self::QueryKey,
+        record_idxs: &[usize],
+        hint: &Self::DatabaseHint,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(Self::Query, Self::State), RespireError> {
+        Self::try_query(qk, record_idxs, hint, time_stats)
+    }
+
+    fn answer(
+        pp: &Self::PublicParams,
+        dbs: &Self::Database,
+        qs: &Self::Query,
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Self::Response, RespireError> {
+        Self::check_query(qs)?;
+        let answers = par_map_stats(
+            qs.queries.par_iter().zip(Self::bin_dbs(dbs, qs).par_iter()),
+            time_stats.as_deref_mut(),
+            None,
+            |(q, db), times, _| BaseRespire::answer_one(pp, db, q, times),
         );
-        eprintln!(
-            "Query size: {:.3} KiB",
-            Self::params_query_size() as f64 / 1024_f64
+        Ok(answers
+            .chunks(BaseRespire::RESPONSE_CHUNK_SIZE)
+            .map(|chunk| BaseRespire::answer_compress_chunk(pp, chunk, time_stats.as_deref_mut()))
+            .collect_vec())
+    }
+
+    fn answer_measured(
+        pp: &Self::PublicParams,
+        dbs: &Self::Database,
+        qs: &Self::Query,
+        qk: &Self::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
+    ) -> Result<Self::Response, RespireError> {
+        Self::check_query(qs)?;
+        let answers = par_map_stats(
+            qs.queries.par_iter().zip(Self::bin_dbs(dbs, qs).par_iter()),
+            None,
+            Some(&mut *noise_stats),
+            |(q, db), _, noise| BaseRespire::answer_one_measured(pp, db, q, qk, noise.unwrap()),
         );
+        Ok(answers
+            .chunks(BaseRespire::RESPONSE_CHUNK_SIZE)
+            .map(|chunk| BaseRespire::answer_compress_chunk_measured(pp, chunk, qk, noise_stats))
+            .collect_vec())
+    }
 
-        let (resp_size, resp_full_vecs, resp_rem) = Self::params_response_info();
-        info!(
-            "Response: {} record(s) => {} ring elem(s) => {} full vector(s), {} remainder",
+    fn extract(
+        qk: &Self::QueryKey,
+        r: &Self::Response,
+        cuckoo_mapping: &Self::State,
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Vec<Self::RecordBytes>, RespireError> {
+        let mut result_by_bin = Vec::with_capacity(Self::NUM_BINS);
+        for r_one in r {
+            let extracted = BaseRespire::extract_one(qk, r_one, time_stats.as_deref_mut())?;
+            for record in extracted {
+                if result_by_bin.len() < Self::NUM_BINS {
+                    result_by_bin.push(record);
+                }
+            }
+        }
+        if result_by_bin.len() != Self::NUM_BINS {
+            return Err(RespireError::WrongLength {
+                what: "response",
+                expected: Self::NUM_BINS,
+                found: result_by_bin.len(),
+            });
+        }
+
+        let uncuckoo_begin = Instant::now();
+        let mut result = vec![BaseRespire::RecordBytes::default(); cuckoo_mapping.len()];
+        for (bin_idx, idxs_idx) in cuckoo_mapping.iter().copied() {
+            result[idxs_idx] = result_by_bin[bin_idx].clone();
+        }
+        let uncuckoo_end = Instant::now();
+        if let Some(time_stats) = time_stats {
+            time_stats.add("extract_uncuckoo", uncuckoo_end - uncuckoo_begin);
+        }
+        Ok(result)
+    }
+
+    fn save_db(
+        dbs: &Self::Database,
+        hint: &Self::DatabaseHint,
+        contents: &DbContents,
+        path: &Path,
+    ) -> io::Result<()> {
+        let header = DbFileHeader::new(
+            Self::NUM_RECORDS,
+            Self::BATCH_SIZE,
             Self::NUM_BUCKET,
-            Self::NUM_BUCKET.div_ceil(BaseRespire::PACK_RATIO_RESPONSE),
-            resp_full_vecs,
-            resp_rem
-        );
-        eprintln!(
-            "Response size (batch): {:.3} KiB",
-            resp_size as f64 / 1024_f64
+            BaseRespire::ENCODED_DB_LEN,
+            contents.clone(),
+            bincode::serialize(hint).unwrap(),
         );
+        let segments = dbs
+            .iter()
+            .map(|db| BaseRespire::db_storage(db))
+            .collect_vec();
+        write_db_file(path, &BaseRespire::params(), &header, &segments)
+    }
 
-        eprintln!(
-            "Record size (batch): {:.3} KiB",
-            Self::params_record_size() as f64 / 1024_f64
+    fn load_db(
+        path: &Path,
+        contents: &DbContents,
+    ) -> Result<(Self::Database, Self::DatabaseHint), DbFileError> {
+        let expected = DbFileHeader::new(
+            Self::NUM_RECORDS,
+            Self::BATCH_SIZE,
+            Self::NUM_BUCKET,
+            BaseRespire::ENCODED_DB_LEN,
+            contents.clone(),
+            Vec::new(),
         );
-        eprintln!("Rate: {:.3}", Self::params_rate());
+        let (segments, db_hint) = map_db_file(
+            path,
+            &BaseRespire::params(),
+            &expected,
+            BaseRespire::PACKED_DIM1_SIZE,
+        )?;
+        let hint: Self::DatabaseHint =
+            bincode::deserialize(&db_hint).map_err(|e| DbFileError::Header(e.into()))?;
+        let dbs = segments
+            .into_iter()
+            .map(BaseRespire::db_from_storage)
+            .collect_vec();
+        Ok((dbs, hint))
+    }
+}
 
-        eprintln!(
-            "Error rate (estimated): 2^({:.3})",
-            BaseRespire::params_error_rate_estimate().log2()
+impl<
+        const BATCH_SIZE: usize,
+        const NUM_BUCKET: usize,
+        const NUM_RECORDS: usize,
+        BaseRespire: PIR + Respire,
+        const NUM_HASH: usize,
+        const STASH_SIZE: usize,
+    > CuckooRespireImpl<BATCH_SIZE, NUM_BUCKET, NUM_RECORDS, BaseRespire, NUM_HASH, STASH_SIZE>
+{
+    /// Number of queries in a batch: one per bucket, plus the stash
+    pub const NUM_BINS: usize = NUM_BUCKET + STASH_SIZE;
+
+    /// The `NUM_HASH` bucket indices are digits of a single 64 bit hash, see `idx_to_buckets`
+    const HASH_DIGITS_FIT: () = assert!(
+        (NUM_BUCKET as u64).checked_pow(NUM_HASH as u32).is_some(),
+        "NUM_BUCKET^NUM_HASH must fit in 64 bits"
+    );
+
+    ///
+    /// `PIR::query`, failing if there are more than `BATCH_SIZE` records, if they are not in the
+    /// database (as of `hint`), or if they cannot be assigned to distinct buckets (which is
+    /// unlikely for suitable parameters).
+    ///
+    pub fn try_query(
+        qk: &<Self as PIR>::QueryKey,
+        record_idxs: &[usize],
+        hint: &CuckooHint,
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(<Self as PIR>::Query, <Self as PIR>::State), RespireError> {
+        let cuckoo_begin = Instant::now();
+        // Every bin is queried regardless, so fewer records need no further padding
+        if record_idxs.len() > Self::BATCH_SIZE {
+            return Err(RespireError::BatchTooLarge {
+                len: record_idxs.len(),
+                max: Self::BATCH_SIZE,
+            });
+        }
+        if let Some(&idx) = record_idxs.iter().find(|&&idx| idx >= hint.num_records) {
+            return Err(CuckooError::UnknownRecord(idx).into());
+        }
+        // Chosen independently of the records, so that they reveal nothing about them
+        let mut rng = thread_rng();
+        let stash_buckets = (0..Self::STASH_SIZE)
+            .map(|_| rng.gen_range(0..Self::NUM_BUCKET))
+            .collect_vec();
+        let cuckoo_mapping = Self::cuckoo(
+            &hint.hash_key,
+            record_idxs,
+            &stash_buckets,
+            CUCKOO_MAX_DEPTH,
         )
+        .filter(|mapping| mapping.len() == record_idxs.len())
+        .ok_or(CuckooError::InsertionFailed)?;
+
+        let bucket_of_bin = |bin: usize| {
+            if bin < Self::NUM_BUCKET {
+                bin
+            } else {
+                stash_buckets[bin - Self::NUM_BUCKET]
+            }
+        };
+        let targets = cuckoo_mapping
+            .iter()
+            .map(|&(bin_idx, idxs_idx)| (bucket_of_bin(bin_idx), record_idxs[idxs_idx]))
+            .collect_vec();
+        let mut actual_idxs = vec![0usize; Self::NUM_BINS];
+        for (&(bin_idx, _), slot) in cuckoo_mapping
+            .iter()
+            .zip(Self::bucket_slots(hint, &targets))
+        {
+            actual_idxs[bin_idx] = slot;
+        }
+        let cuckoo_end = Instant::now();
+        if let Some(time_stats) = time_stats.as_deref_mut() {
+            time_stats.add("query_cuckoo", cuckoo_end - cuckoo_begin);
+        }
+
+        let queries = actual_idxs
+            .iter()
+            .copied()
+            .map(|idx| BaseRespire::query_one(qk, idx, time_stats.as_deref_mut()))
+            .collect_vec();
+        let q = CuckooQuery {
+            queries,
+            stash_buckets,
+        };
+        Ok((q, cuckoo_mapping))
     }
 
-    fn encode_db<F: Fn(usize) -> Self::RecordBytes>(
+    /// Check a query before answering it, see `PIR::answer`.
+    pub fn check_query(qs: &<Self as PIR>::Query) -> Result<(), RespireError> {
+        if qs.queries.len() != Self::NUM_BINS {
+            return Err(RespireError::WrongLength {
+                what: "cuckoo query",
+                expected: Self::NUM_BINS,
+                found: qs.queries.len(),
+            });
+        }
+        if qs.stash_buckets.len() != Self::STASH_SIZE {
+            return Err(RespireError::WrongLength {
+                what: "cuckoo stash",
+                expected: Self::STASH_SIZE,
+                found: qs.stash_buckets.len(),
+            });
+        }
+        if let Some(&bucket) = qs.stash_buckets.iter().find(|&&b| b >= Self::NUM_BUCKET) {
+            return Err(RespireError::UnknownBucket {
+                bucket,
+                num_buckets: Self::NUM_BUCKET,
+            });
+        }
+        for q in qs.queries.iter() {
+            BaseRespire::check_query_one(q)?;
+        }
+        Ok(())
+    }
+
+    /// The database each query of a checked query is answered against: the buckets, then the
+    /// stash buckets it names
+    fn bin_dbs<'a>(
+        dbs: &'a <Self as PIR>::Database,
+        qs: &<Self as PIR>::Query,
+    ) -> Vec<&'a <BaseRespire as PIR>::Database> {
+        dbs.iter()
+            .chain(qs.stash_buckets.iter().map(|&b| &dbs[b]))
+            .collect_vec()
+    }
+
+    ///
+    /// `encode_db`, with the records assigned to buckets using the given hash key rather than a
+    /// random one.
+    ///
+    pub fn encode_db_with_key<F: Fn(usize) -> <Self as PIR>::RecordBytes>(
+        hash_key: [u8; 16],
         records_generator: F,
-        //time_stats: Option<&mut Stats<Duration>>,
-    ) -> (Self::Database, Self::DatabaseHint) {
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> (<Self as PIR>::Database, <Self as PIR>::DatabaseHint) {
         let begin = Instant::now();
-        // TODO the bucket layouts can be determined during setup since it is database independent
         let mut bucket_layouts = vec![Vec::with_capacity(BaseRespire::DB_SIZE); Self::NUM_BUCKET];
         for i in 0..Self::NUM_RECORDS {
-            let (b1, b2, b3) = Self::idx_to_buckets(i);
-            bucket_layouts[b1].push(Some(i));
-            bucket_layouts[b2].push(Some(i));
-            bucket_layouts[b3].push(Some(i));
+            for b in Self::idx_to_buckets(&hash_key, i) {
+                bucket_layouts[b].push(Some(i));
+            }
         }
-        let max_count = bucket_layouts.iter().map(|b| b.len()).max().unwrap();
+        let bucket_sizes = bucket_layouts.iter().map(|b| b.len()).collect_vec();
+        let max_count = bucket_sizes.iter().copied().max().unwrap();
         info!(
             "Cuckoo DB encoding: worst bucket size {} out of {}",
             max_count,
@@ -134,7 +579,12 @@ impl<
                 BaseRespire::DB_SIZE
             );
         }
-        assert!(max_count <= BaseRespire::DB_SIZE);
+        assert!(
+            max_count <= BaseRespire::DB_SIZE,
+            "bucket of size {} overflows {} for this hash key",
+            max_count,
+            BaseRespire::DB_SIZE
+        );
 
         for b in bucket_layouts.iter_mut() {
             while b.len() < BaseRespire::DB_SIZE {
@@ -143,141 +593,213 @@ impl<
         }
 
         let mut result = Vec::with_capacity(Self::NUM_BUCKET);
-        let zero = Self::RecordBytes::default();
+        let zero = <Self as PIR>::RecordBytes::default();
         for (b_idx, b) in bucket_layouts.iter().enumerate() {
             info!("Encoding bucket {} of {}...", b_idx + 1, Self::NUM_BUCKET);
             let bucket_records_generator =
                 |i: usize| b[i].map_or(zero.clone(), |i| records_generator(i));
-            result.push(BaseRespire::encode_db(bucket_records_generator).0); //, None).0);
+            result.push(BaseRespire::encode_db(bucket_records_generator, None).0);
         }
 
         let end = Instant::now();
-        /*if let Some(time_stats) = time_stats {
+        if let Some(time_stats) = time_stats {
             time_stats.add("encode", end - begin);
-        }*/
-        (result, bucket_layouts)
+        }
+        let hint = CuckooHint {
+            hash_key,
+            num_records: Self::NUM_RECORDS,
+            bucket_sizes,
+            slot_cache: SlotCache::default(),
+        };
+        (result, hint)
     }
 
-    fn setup() -> (Self::QueryKey, Self::PublicParams) {
-        //time_stats: Option<&mut Stats<Duration>>) -> (Self::QueryKey, Self::PublicParams) {
-        BaseRespire::setup() //time_stats)
+    /// Number of records in each bucket for a hash key
+    fn bucket_sizes(hash_key: &[u8; 16]) -> Vec<usize> {
+        let mut sizes = vec![0; Self::NUM_BUCKET];
+        for i in 0..Self::NUM_RECORDS {
+            for b in Self::idx_to_buckets(hash_key, i) {
+                sizes[b] += 1;
+            }
+        }
+        sizes
     }
 
-    fn query(
-        qk: &Self::QueryKey,
-        record_idxs: &[usize],
-        bucket_layouts: &Self::DatabaseHint,
-        //mut time_stats: Option<&mut Stats<Duration>>,
-    ) -> (Self::Query, Self::State) {
-        let cuckoo_begin = Instant::now();
-        assert_eq!(record_idxs.len(), Self::BATCH_SIZE);
-        let cuckoo_mapping = Self::cuckoo(record_idxs, 2usize.pow(16)).unwrap();
-        assert_eq!(cuckoo_mapping.len(), Self::BATCH_SIZE);
-
-        let mut actual_idxs = vec![0usize; Self::NUM_BUCKET];
-        for (bucket_idx, idxs_idx) in cuckoo_mapping.iter().copied() {
-            let record_idx = record_idxs[idxs_idx];
-            // TODO optimize this linear search
-            actual_idxs[bucket_idx] = bucket_layouts[bucket_idx]
-                .iter()
-                .copied()
-                .find_position(|slot| slot.is_some_and(|i| i == record_idx))
-                .unwrap()
-                .0;
-        }
-        let cuckoo_end = Instant::now();
-        /*if let Some(time_stats) = time_stats.as_deref_mut() {
-            time_stats.add("query_cuckoo", cuckoo_end - cuckoo_begin);
-        }*/
+    ///
+    /// The buckets of record `i`, from SipHash-2-4 of `i` (as a little endian `u64`) keyed by
+    /// `hash_key`, so that they are the same on every platform and Rust version.
+    ///
+    pub fn idx_to_buckets(hash_key: &[u8; 16], i: usize) -> [usize; NUM_HASH] {
+        let () = Self::HASH_DIGITS_FIT;
+        let modulus = Self::NUM_BUCKET as u64;
+        let mut hasher = SipHasher24::new_with_key(hash_key);
+        hasher.write(&(i as u64).to_le_bytes());
+        // The base NUM_BUCKET digits of the hash
+        let mut hashed = hasher.finish();
+        std::array::from_fn(|_| {
+            let h = hashed % modulus;
+            hashed /= modulus;
+            h as usize
+        })
+    }
 
-        assert_eq!(actual_idxs.len(), Self::NUM_BUCKET);
-        let q = actual_idxs
+    ///
+    /// The slot of each (bucket, record index) pair in `targets`, where the record hashes to the
+    /// bucket. As buckets are sorted, this is the number of lower-indexed records in the bucket.
+    /// The slots of all records are computed once per hint, on first use, and cached in it.
+    ///
+    pub fn bucket_slots(hint: &CuckooHint, targets: &[(usize, usize)]) -> Vec<usize> {
+        let table = Self::slot_table(hint);
+        targets
             .iter()
-            .copied()
-            .map(|idx| BaseRespire::query_one(qk, idx)) //time_stats.as_deref_mut()))
-            .collect_vec();
+            .map(|&(bucket, idx)| {
+                let h = Self::idx_to_buckets(&hint.hash_key, idx)
+                    .iter()
+                    .position(|&b| b == bucket)
+                    .expect("record does not hash to the bucket");
+                table.slots[idx * NUM_HASH + h] as usize
+            })
+            .collect()
+    }
 
-        (q, cuckoo_mapping)
+    /// The cached [`SlotTable`] of `hint`, extended to all of its records
+    fn slot_table(hint: &CuckooHint) -> Arc<SlotTable> {
+        let mut cached = hint.slot_cache.0.lock().unwrap();
+        if cached.hash_key != hint.hash_key
+            || cached.num_bucket != NUM_BUCKET
+            || cached.num_hash != NUM_HASH
+        {
+            *cached = Arc::new(SlotTable {
+                hash_key: hint.hash_key,
+                num_bucket: NUM_BUCKET,
+                num_hash: NUM_HASH,
+                slots: Vec::new(),
+                bucket_sizes: vec![0; NUM_BUCKET],
+            });
+        }
+        let begin = cached.num_records();
+        if begin < hint.num_records {
+            let buckets = (begin..hint.num_records)
+                .into_par_iter()
+                .map(|i| Self::idx_to_buckets(&hint.hash_key, i))
+                .collect::<Vec<_>>();
+            let table = Arc::make_mut(&mut cached);
+            table.slots.reserve(buckets.len() * NUM_HASH);
+            for bucket in buckets.into_iter().flatten() {
+                table.slots.push(table.bucket_sizes[bucket] as u32);
+                table.bucket_sizes[bucket] += 1;
+            }
+        }
+        cached.clone()
     }
 
-    fn answer(
-        pp: &Self::PublicParams,
-        dbs: &Self::Database,
-        qs: &Self::Query,
-        qk: Option<&Self::QueryKey>,
-        // mut time_stats: Option<&mut Stats<Duration>>,
-    ) -> Self::Response {
-        assert_eq!(qs.len(), Self::NUM_BUCKET);
-        let answers: Vec<BaseRespire::AnswerOne> = (*qs)
-            .par_iter()
-            .zip((*dbs).par_iter())
-            .map(|(q, db)| BaseRespire::answer_one(pp, db, q, qk)) //time_stats.as_deref_mut()))
-            .collect(); //.collect_vec();
-        let answers_compressed = answers
-            .chunks(BaseRespire::RESPONSE_CHUNK_SIZE)
-            .map(|chunk| {
-                BaseRespire::answer_compress_chunk(pp, chunk, qk) //time_stats.as_deref_mut())
+    ///
+    /// Replace the given records in all buckets that hold them. The bucket layouts do not change,
+    /// so clients need no new hint. See [`Respire::update_records`] for how this interacts with
+    /// in-flight queries.
+    ///
+    pub fn update_records(
+        dbs: &mut <Self as PIR>::Database,
+        hint: &CuckooHint,
+        updates: &[(usize, <Self as PIR>::RecordBytes)],
+    ) -> Result<(), RespireError> {
+        // (bucket, record index, number of copies in the bucket)
+        let mut targets = Vec::new();
+        for (idx, _) in updates {
+            if *idx >= hint.num_records {
+                return Err(CuckooError::UnknownRecord(*idx).into());
+            }
+            let buckets = Self::idx_to_buckets(&hint.hash_key, *idx);
+            for (bucket, copies) in buckets.into_iter().counts() {
+                targets.push((bucket, *idx, copies));
+            }
+        }
+
+        let slots = Self::bucket_slots(
+            hint,
+            &targets.iter().map(|&(b, idx, _)| (b, idx)).collect_vec(),
+        );
+        let records: HashMap<usize, &<Self as PIR>::RecordBytes> =
+            updates.iter().map(|(idx, record)| (*idx, record)).collect();
+        let patches = targets
+            .iter()
+            .zip(slots)
+            .flat_map(|(&(bucket, idx, copies), slot)| {
+                let record = records[&idx];
+                (slot..slot + copies).map(move |slot| (bucket, slot, record.clone()))
             })
             .collect_vec();
-        answers_compressed
+        Self::patch_buckets(dbs, patches);
+        Ok(())
     }
 
-    fn extract(
-        qk: &Self::QueryKey,
-        r: &Self::Response,
-        cuckoo_mapping: &Self::State,
-        // mut time_stats: Option<&mut Stats<Duration>>,
-    ) -> Vec<Self::RecordBytes> {
-        let mut result_by_bucket = Vec::with_capacity(Self::NUM_BUCKET);
-        for r_one in r {
-            let extracted = BaseRespire::extract_one(qk, r_one); //time_stats.as_deref_mut());
-            for record in extracted {
-                if result_by_bucket.len() < Self::NUM_BUCKET {
-                    result_by_bucket.push(record);
+    ///
+    /// Append records to the database, as records `hint.num_records..`, placing them in the first
+    /// free slots of their buckets. Returns the change to the hint, which has already been applied
+    /// to `hint` and has to be applied to the clients' copies as well. Nothing is changed on error.
+    ///
+    /// The appended records are counted in the hint only: `NUM_RECORDS` and `summary` keep
+    /// describing the database as encoded.
+    ///
+    pub fn append_records(
+        dbs: &mut <Self as PIR>::Database,
+        hint: &mut CuckooHint,
+        records: &[<Self as PIR>::RecordBytes],
+    ) -> Result<CuckooHintDelta, RespireError> {
+        let mut delta = CuckooHintDelta {
+            base_num_records: hint.num_records,
+            num_records: hint.num_records + records.len(),
+            bucket_sizes: hint.bucket_sizes.clone(),
+        };
+        let mut patches = Vec::new();
+        for (idx, record) in (hint.num_records..).zip(records) {
+            for bucket in Self::idx_to_buckets(&hint.hash_key, idx) {
+                let slot = delta.bucket_sizes[bucket];
+                if slot >= BaseRespire::DB_SIZE {
+                    return Err(CuckooError::BucketFull(bucket).into());
                 }
+                delta.bucket_sizes[bucket] += 1;
+                patches.push((bucket, slot, record.clone()));
             }
         }
-        assert_eq!(result_by_bucket.len(), Self::NUM_BUCKET);
 
-        let uncuckoo_begin = Instant::now();
-        let mut result = vec![BaseRespire::RecordBytes::default(); Self::BATCH_SIZE];
-        assert_eq!(cuckoo_mapping.len(), Self::BATCH_SIZE);
-        for (bucket_idx, idxs_idx) in cuckoo_mapping.iter().copied() {
-            result[idxs_idx] = result_by_bucket[bucket_idx].clone();
-        }
-        let uncuckoo_end = Instant::now();
-        /*if let Some(time_stats) = time_stats {
-            time_stats.add("extract_uncuckoo", uncuckoo_end - uncuckoo_begin);
-        }*/
-        result
+        Self::patch_buckets(dbs, patches);
+        hint.apply(&delta).unwrap();
+        Ok(delta)
     }
-}
 
-impl<
-        const BATCH_SIZE: usize,
-        const NUM_BUCKET: usize,
-        const NUM_RECORDS: usize,
-        BaseRespire: PIR + Respire,
-    > CuckooRespireImpl<BATCH_SIZE, NUM_BUCKET, NUM_RECORDS, BaseRespire>
-{
-    fn idx_to_buckets(i: usize) -> (usize, usize, usize) {
-        let modulus = Self::NUM_BUCKET as u64;
-        assert!(modulus.checked_pow(3).is_some());
-        // TODO: DefaultHasher is not stable
-        let mut hasher = DefaultHasher::new();
-        i.hash(&mut hasher);
-        let hashed = hasher.finish();
-        let h1 = hashed % modulus;
-        let h2 = (hashed / modulus) % modulus;
-        let h3 = (hashed / modulus / modulus) % modulus;
-        (h1 as usize, h2 as usize, h3 as usize)
+    fn patch_buckets(
+        dbs: &mut <Self as PIR>::Database,
+        slots: impl IntoIterator<Item = (usize, usize, <Self as PIR>::RecordBytes)>,
+    ) {
+        let mut by_bucket = vec![Vec::new(); Self::NUM_BUCKET];
+        for (bucket, slot, record) in slots {
+            by_bucket[bucket].push((slot, record));
+        }
+        for (db, updates) in dbs.iter_mut().zip(by_bucket) {
+            if !updates.is_empty() {
+                BaseRespire::update_records(db, &updates).expect("bucket slots are in range");
+            }
+        }
     }
 
     ///
-    /// Returns a vector of (bucket slot index, item index) pairs.
+    /// Assign the items to bins: bins `0..NUM_BUCKET` are the buckets, and bin `NUM_BUCKET + k`
+    /// is an extra query to bucket `stash_buckets[k]`. Returns a vector of (bin index, item index)
+    /// pairs, or `None` if an item was evicted `max_depth` times.
     ///
-    fn cuckoo(items: &[usize], max_depth: usize) -> Option<Vec<(usize, usize)>> {
-        // Maps bucket slot indices to item indices
+    fn cuckoo(
+        hash_key: &[u8; 16],
+        items: &[usize],
+        stash_buckets: &[usize],
+        max_depth: usize,
+    ) -> Option<Vec<(usize, usize)>> {
+        let mut bins_of_bucket = (0..Self::NUM_BUCKET).map(|b| vec![b]).collect_vec();
+        for (k, &b) in stash_buckets.iter().enumerate() {
+            bins_of_bucket[b].push(Self::NUM_BUCKET + k);
+        }
+
+        // Maps bin indices to item indices
         let mut mapping = HashMap::with_capacity(items.len());
         let mut remaining = Vec::from_iter((0..items.len()).map(|idx| (idx, 0usize)));
         let mut rng = thread_rng();
@@ -285,38 +807,26 @@ impl<
             if depth >= max_depth {
                 return None;
             }
-            let (i1, i2, i3) = Self::idx_to_buckets(items[idx]);
-            match (mapping.get(&i1), mapping.get(&i2), mapping.get(&i3)) {
-                (None, _, _) => {
-                    mapping.insert(i1, idx);
-                }
-                (_, None, _) => {
-                    mapping.insert(i2, idx);
+            let candidates = Self::idx_to_buckets(hash_key, items[idx])
+                .iter()
+                .flat_map(|&b| bins_of_bucket[b].iter().copied())
+                .collect_vec();
+            match candidates.iter().find(|bin| !mapping.contains_key(*bin)) {
+                Some(&bin) => {
+                    mapping.insert(bin, idx);
                 }
-                (_, _, None) => {
-                    mapping.insert(i3, idx);
+                None => {
+                    let bin = candidates[rng.gen_range(0..candidates.len())];
+                    remaining.push((mapping[&bin], depth + 1));
+                    mapping.insert(bin, idx);
                 }
-                (Some(&curr1), Some(&curr2), Some(&curr3)) => match rng.gen_range(0..3) {
-                    0 => {
-                        remaining.push((curr1, depth + 1));
-                        mapping.insert(i1, idx);
-                    }
-                    1 => {
-                        remaining.push((curr2, depth + 1));
-                        mapping.insert(i2, idx);
-                    }
-                    _ => {
-                        remaining.push((curr3, depth + 1));
-                        mapping.insert(i3, idx);
-                    }
-                },
             }
         }
         Some(mapping.into_iter().collect_vec())
     }
 
     pub fn params_query_size() -> usize {
-        Self::NUM_BUCKET * BaseRespire::params_query_one_size()
+        Self::NUM_BINS * BaseRespire::params_query_one_size()
     }
 
     pub fn params_record_size() -> usize {
@@ -327,7 +837,7 @@ impl<
     /// size, number of full vectors, remainder size
     ///
     pub fn params_response_info() -> (usize, usize, usize) {
-        let num_ring_elem = Self::NUM_BUCKET.div_ceil(BaseRespire::PACK_RATIO_RESPONSE);
+        let num_ring_elem = Self::NUM_BINS.div_ceil(BaseRespire::PACK_RATIO_RESPONSE);
         let num_full_vecs = num_ring_elem / BaseRespire::N_VEC;
         let num_rem = num_ring_elem % BaseRespire::N_VEC;
 
@@ -348,3 +858,172 @@ impl<
         (Self::params_record_size() as f64) / (Self::params_response_info().0 as f64)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::pir::PIRRecordBytes;
+    use crate::pir::respire_harness::{CuckooBaseTestSmall, CuckooRespireTestSmall};
+
+    fn record(i: usize, salt: u8) -> <CuckooRespireTestSmall as PIR>::RecordBytes {
+        let bytes = (0..CuckooRespireTestSmall::BYTES_PER_RECORD)
+            .map(|j| (i * 11 + j) as u8 ^ salt)
+            .collect_vec();
+        PIRRecordBytes::from_bytes(&bytes).unwrap()
+    }
+
+    fn retrieve(
+        dbs: &<CuckooRespireTestSmall as PIR>::Database,
+        hint: &CuckooHint,
+        indices: &[usize],
+    ) -> Vec<<CuckooRespireTestSmall as PIR>::RecordBytes> {
+        let (qk, pp) = CuckooRespireTestSmall::setup(None);
+        let (q, st) = CuckooRespireTestSmall::query(&qk, indices, hint, None).unwrap();
+        let r = CuckooRespireTestSmall::answer(&pp, dbs, &q, None).unwrap();
+        CuckooRespireTestSmall::extract(&qk, &r, &st, None).unwrap()
+    }
+
+    #[test]
+    fn test_stable_hash() {
+        let key = *b"0123456789abcdef";
+        let buckets = (0..4)
+            .map(|i| CuckooRespireTestSmall::idx_to_buckets(&key, i))
+            .collect_vec();
+        // Fixed, so that any change to the hash (which would break existing hints) is noticed
+        assert_eq!(buckets, vec![[1, 1, 4], [3, 4, 4], [4, 5, 0], [1, 2, 2]]);
+
+        let (_, hint) = CuckooRespireTestSmall::encode_db_with_key(key, |i| record(i, 0), None);
+        let (_, other) = CuckooRespireTestSmall::encode_db_with_key(key, |i| record(i, 1), None);
+        assert_eq!(hint, other);
+    }
+
+    #[test]
+    fn test_bucket_slots() {
+        let key = [7; 16];
+        let mut layouts = vec![Vec::new(); CuckooRespireTestSmall::NUM_BUCKET];
+        for i in 0..CuckooRespireTestSmall::NUM_RECORDS {
+            for b in CuckooRespireTestSmall::idx_to_buckets(&key, i) {
+                layouts[b].push(i);
+            }
+        }
+        let targets = layouts
+            .iter()
+            .enumerate()
+            .flat_map(|(b, layout)| layout.iter().map(move |&i| (b, i)))
+            .collect_vec();
+        let (_, hint) = CuckooRespireTestSmall::encode_db_with_key(key, |i| record(i, 0), None);
+        let slots = CuckooRespireTestSmall::bucket_slots(&hint, &targets);
+        for (&(b, i), slot) in targets.iter().zip(slots) {
+            assert_eq!(layouts[b].iter().position(|&j| j == i), Some(slot));
+        }
+
+        // The table is cached in the hint and its clones, and extended to appended records
+        let mut appended = hint.clone();
+        let idx = CuckooRespireTestSmall::NUM_RECORDS;
+        let bucket = CuckooRespireTestSmall::idx_to_buckets(&key, idx)[0];
+        appended.num_records += 1;
+        assert_eq!(
+            CuckooRespireTestSmall::bucket_slots(&appended, &[(bucket, idx)]),
+            vec![layouts[bucket].len()]
+        );
+        assert!(Arc::ptr_eq(
+            &CuckooRespireTestSmall::slot_table(&hint),
+            &CuckooRespireTestSmall::slot_table(&appended)
+        ));
+    }
+
+    #[test]
+    fn test_stash() {
+        let (dbs, hint) = CuckooRespireTestSmall::encode_db(|i| record(i, 0), None);
+
+        // More records than buckets cannot be assigned without a stash
+        type Overfull = CuckooRespireImpl<7, 6, 60, CuckooBaseTestSmall>;
+        let (qk, pp) = Overfull::setup(None);
+        let indices = (0..7).collect_vec();
+        assert!(matches!(
+            Overfull::try_query(&qk, &indices, &hint, None),
+            Err(RespireError::Cuckoo(CuckooError::InsertionFailed))
+        ));
+        assert!(matches!(
+            Overfull::try_query(&qk, &[0, 1, 2, 3, 4, 5, 60], &hint, None),
+            Err(RespireError::Cuckoo(CuckooError::UnknownRecord(60)))
+        ));
+        assert!(matches!(
+            Overfull::try_query(&qk, &(0..8).collect_vec(), &hint, None),
+            Err(RespireError::BatchTooLarge { len: 8, max: 7 })
+        ));
+
+        type Stashed = CuckooRespireImpl<7, 6, 60, CuckooBaseTestSmall, 3, 6>;
+        assert!(Stashed::cuckoo(&hint.hash_key, &indices, &[0, 1, 2, 3, 4, 5], 1000).is_some());
+
+        type StashedSmall = CuckooRespireImpl<2, 6, 60, CuckooBaseTestSmall, 3, 2>;
+        let (q, st) = StashedSmall::try_query(&qk, &[8, 9], &hint, None).unwrap();
+        assert_eq!(q.queries.len(), 8);
+        let r = StashedSmall::answer(&pp, &dbs, &q, None).unwrap();
+        let extracted = StashedSmall::extract(&qk, &r, &st, None).unwrap();
+        assert_eq!(extracted, vec![record(8, 0), record(9, 0)]);
+
+        // The server rejects stash buckets that do not exist
+        let mut bad_q = q;
+        bad_q.stash_buckets[1] = 6;
+        assert!(matches!(
+            StashedSmall::answer(&pp, &dbs, &bad_q, None),
+            Err(RespireError::UnknownBucket { bucket: 6, .. })
+        ));
+
+        // Too many records for the buckets under any hash key
+        type Overflowing = CuckooRespireImpl<2, 6, 400, CuckooBaseTestSmall>;
+        assert!(matches!(
+            Overflowing::try_encode_db(|_| Default::default(), None),
+            Err(RespireError::Cuckoo(CuckooError::BucketsOverflow(
+                CUCKOO_MAX_KEYS
+            )))
+        ));
+    }
+
+    #[test]
+    fn test_update_append() {
+        let num_records = CuckooRespireTestSmall::NUM_RECORDS;
+        let (mut dbs, mut hint) = CuckooRespireTestSmall::encode_db(|i| record(i, 0), None);
+        let mut client_hint = hint.clone();
+
+        CuckooRespireTestSmall::update_records(&mut dbs, &hint, &[(3, record(3, 1))]).unwrap();
+        let delta = CuckooRespireTestSmall::append_records(
+            &mut dbs,
+            &mut hint,
+            &[record(num_records, 0), record(num_records + 1, 0)],
+        )
+        .unwrap();
+        assert_eq!(delta.num_records, num_records + 2);
+        let delta = bincode::deserialize(&bincode::serialize(&delta).unwrap()).unwrap();
+        client_hint.apply(&delta).unwrap();
+        assert_eq!(client_hint, hint);
+        assert_eq!(
+            client_hint.apply(&delta),
+            Err(CuckooError::StaleHint {
+                expected: num_records,
+                found: num_records + 2
+            })
+        );
+
+        assert_eq!(
+            retrieve(&dbs, &client_hint, &[3, num_records]),
+            vec![record(3, 1), record(num_records, 0)]
+        );
+        assert_eq!(
+            retrieve(&dbs, &client_hint, &[num_records + 1, 4]),
+            vec![record(num_records + 1, 0), record(4, 0)]
+        );
+
+        assert!(matches!(
+            CuckooRespireTestSmall::update_records(&mut dbs, &hint, &[(1000, record(0, 0))]),
+            Err(RespireError::Cuckoo(CuckooError::UnknownRecord(1000)))
+        ));
+        let too_many = (0..200).map(|i| record(i, 0)).collect_vec();
+        assert!(matches!(
+            CuckooRespireTestSmall::append_records(&mut dbs, &mut hint, &too_many),
+            Err(RespireError::Cuckoo(CuckooError::BucketFull(_)))
+        ));
+        assert_eq!(hint, client_hint);
+    }
+}
@@ -0,0 +1,518 @@
+//! Persisting encoded databases, and memory-mapping them back.
+//!
+//! Encoding is by far the most expensive part of starting a server, so the encoded database can be
+//! written to disk once and then mapped read-only on every start. Mapping copies nothing, and
+//! several processes mapping the same file share its pages.
+//!
+//! Layout: `header length (u64) || header || padding || segment 0 || padding || segment 1 ...`.
+//! The header is written with [`serialize_with_header`] (so it records the format version and the
+//! full parameter set) and contains a [`DbFileHeader`], which also records what the records stand
+//! for beyond the parameter set (see [`DbContents`]). Every segment is one encoded RESPIRE
+//! database, i.e. a `[SimdVec]` in native byte order, and starts at a multiple of [`DB_FILE_ALIGN`]
+//! bytes. Plain RESPIRE databases have one segment; cuckoo databases have one per bucket.
+
+use crate::math::simd_utils::{SimdVec, SIMD_LANES};
+use crate::pir::respire::RespireParamsExpanded;
+use crate::pir::serialization::{
+    deserialize_with_header, serialize_with_header, ObjectKind, SerializationError,
+};
+use crate::pir::shaped_pir::DbShape;
+use memmap2::Mmap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::mem::size_of;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+/// Alignment of segments within the file. A page, so that mapped segments are suitably aligned
+/// for `SimdVec`.
+pub const DB_FILE_ALIGN: usize = 4096;
+
+///
+/// Storage of an encoded database: an owned or memory-mapped base, divided into blocks of
+/// `block_len` `SimdVec`s, with copy-on-write overrides for the blocks changed since.
+///
+/// Cloning only clones the pointers to the base and to the changed blocks, and writing to a block
+/// copies just that block if it is shared. A mapped base is never written to, or copied: the file
+/// stays mapped, and changed blocks are kept in memory.
+///
+#[derive(Clone)]
+pub struct DbStorage {
+    base: DbBase,
+    block_len: usize,
+    /// Blocks changed since the base was encoded or mapped, by block index
+    changed: HashMap<usize, Arc<Vec<SimdVec>>>,
+}
+
+#[derive(Clone)]
+enum DbBase {
+    Owned(Arc<Vec<SimdVec>>),
+    Mapped {
+        mmap: Arc<Mmap>,
+        offset: usize,
+        len: usize,
+    },
+}
+
+impl DbBase {
+    fn as_slice(&self) -> &[SimdVec] {
+        match self {
+            DbBase::Owned(db) => db.as_slice(),
+            DbBase::Mapped { mmap, offset, len } => {
+                let bytes = &mmap[*offset..*offset + len * size_of::<SimdVec>()];
+                // Safety: the range is in bounds (checked in map_db_file), aligned since the mapping
+                // is page-aligned and the offset is a multiple of DB_FILE_ALIGN, and any bit pattern
+                // is a valid SimdVec.
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const SimdVec, *len) }
+            }
+        }
+    }
+}
+
+impl DbStorage {
+    ///
+    /// Own `db`, divided into blocks of `block_len`, which must divide its length.
+    ///
+    pub fn new(db: Vec<SimdVec>, block_len: usize) -> Self {
+        assert_eq!(db.len() % block_len, 0);
+        Self {
+            base: DbBase::Owned(Arc::new(db)),
+            block_len,
+            changed: HashMap::new(),
+        }
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        matches!(self.base, DbBase::Mapped { .. })
+    }
+
+    /// Number of `SimdVec`s
+    pub fn len(&self) -> usize {
+        self.base.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn block_len(&self) -> usize {
+        self.block_len
+    }
+
+    pub fn num_blocks(&self) -> usize {
+        self.len() / self.block_len
+    }
+
+    /// Number of blocks held in memory on top of the base
+    pub fn num_changed_blocks(&self) -> usize {
+        self.changed.len()
+    }
+
+    #[inline]
+    pub fn block(&self, block_idx: usize) -> &[SimdVec] {
+        if !self.changed.is_empty() {
+            if let Some(block) = self.changed.get(&block_idx) {
+                return block.as_slice();
+            }
+        }
+        let start = block_idx * self.block_len;
+        &self.base.as_slice()[start..start + self.block_len]
+    }
+
+    ///
+    /// Mutable access to a block, copying it if it is shared with the base or with other clones.
+    ///
+    pub fn block_mut(&mut self, block_idx: usize) -> &mut [SimdVec] {
+        let (base, block_len) = (&self.base, self.block_len);
+        let block = self.changed.entry(block_idx).or_insert_with(|| {
+            let start = block_idx * block_len;
+            Arc::new(base.as_slice()[start..start + block_len].to_vec())
+        });
+        Arc::make_mut(block).as_mut_slice()
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &[SimdVec]> + '_ {
+        (0..self.num_blocks()).map(|i| self.block(i))
+    }
+
+    /// The database as one vector
+    pub fn to_vec(&self) -> Vec<SimdVec> {
+        self.blocks()
+            .flat_map(|block| block.iter().cloned())
+            .collect()
+    }
+}
+
+///
+/// What the records of a database file stand for, beyond what the parameter set determines. A file
+/// is only mapped back for the same contents, see [`DbContents::accepts`].
+///
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbContents {
+    /// The shape of the records, if they are laid out by a [`crate::pir::shaped_pir::ShapedPIR`]
+    pub shape: Option<DbShape>,
+    /// The record file the database was encoded from, if any
+    pub source: Option<SourceFile>,
+}
+
+impl DbContents {
+    ///
+    /// Whether a file saved for `found` can be used for these contents: the shapes must match, and
+    /// so must the sources, unless this does not name one.
+    ///
+    pub fn accepts(&self, found: &DbContents) -> bool {
+        self.shape == found.shape && (self.source.is_none() || self.source == found.source)
+    }
+}
+
+///
+/// Identifies a version of a record file by its length and modification time, so that a database
+/// encoded from it is not served once the file has changed.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFile {
+    pub len: u64,
+    /// Nanoseconds since the Unix epoch
+    pub modified_nanos: u128,
+}
+
+impl SourceFile {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let metadata = std::fs::metadata(path)?;
+        let modified = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_err(io::Error::other)?;
+        Ok(Self {
+            len: metadata.len(),
+            modified_nanos: modified.as_nanos(),
+        })
+    }
+}
+
+///
+/// Describes the database stored in a file, beyond the RESPIRE parameters in the header.
+///
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbFileHeader {
+    /// The database layout depends on the SIMD lane count the encoder was compiled with
+    pub simd_lanes: usize,
+    pub little_endian: bool,
+    pub num_records: usize,
+    pub batch_size: usize,
+    pub num_segments: usize,
+    /// Number of `SimdVec`s per segment
+    pub segment_len: usize,
+    pub contents: DbContents,
+    /// bincode encoded `PIR::DatabaseHint`
+    pub db_hint: Vec<u8>,
+}
+
+impl DbFileHeader {
+    pub fn new(
+        num_records: usize,
+        batch_size: usize,
+        num_segments: usize,
+        segment_len: usize,
+        contents: DbContents,
+        db_hint: Vec<u8>,
+    ) -> Self {
+        Self {
+            simd_lanes: SIMD_LANES,
+            little_endian: cfg!(target_endian = "little"),
+            num_records,
+            batch_size,
+            num_segments,
+            segment_len,
+            contents,
+            db_hint,
+        }
+    }
+
+    // Everything except the contents and the hint must match
+    fn matches(&self, other: &DbFileHeader) -> bool {
+        (
+            self.simd_lanes,
+            self.little_endian,
+            self.num_records,
+            self.batch_size,
+            self.num_segments,
+            self.segment_len,
+        ) == (
+            other.simd_lanes,
+            other.little_endian,
+            other.num_records,
+            other.batch_size,
+            other.num_segments,
+            other.segment_len,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum DbFileError {
+    Io(io::Error),
+    Header(SerializationError),
+    /// The file was written for a different database shape or build
+    LayoutMismatch {
+        expected: Box<DbFileHeader>,
+        found: Box<DbFileHeader>,
+    },
+    /// The file holds a database of the same layout, but for different contents
+    ContentsMismatch {
+        expected: Box<DbContents>,
+        found: Box<DbContents>,
+    },
+    /// The file is shorter than its header says
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for DbFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbFileError::Io(e) => write!(f, "{}", e),
+            DbFileError::Header(e) => write!(f, "bad header: {}", e),
+            DbFileError::LayoutMismatch { expected, found } => write!(
+                f,
+                "database layout mismatch (expected {} lanes, {} records, batch size {}, {} segments of {}; found {} lanes, {} records, batch size {}, {} segments of {})",
+                expected.simd_lanes,
+                expected.num_records,
+                expected.batch_size,
+                expected.num_segments,
+                expected.segment_len,
+                found.simd_lanes,
+                found.num_records,
+                found.batch_size,
+                found.num_segments,
+                found.segment_len,
+            ),
+            DbFileError::ContentsMismatch { expected, found } => write!(
+                f,
+                "database was encoded for {:?}, expected {:?}",
+                found, expected
+            ),
+            DbFileError::Truncated { expected, found } => write!(
+                f,
+                "file is truncated ({} bytes, expected {})",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DbFileError {}
+
+impl From<io::Error> for DbFileError {
+    fn from(e: io::Error) -> Self {
+        DbFileError::Io(e)
+    }
+}
+
+impl From<SerializationError> for DbFileError {
+    fn from(e: SerializationError) -> Self {
+        DbFileError::Header(e)
+    }
+}
+
+fn align_up(x: usize) -> usize {
+    x.div_ceil(DB_FILE_ALIGN) * DB_FILE_ALIGN
+}
+
+///
+/// Write `segments` to `path`. `header` must describe `segments`.
+///
+pub fn write_db_file(
+    path: &Path,
+    params: &RespireParamsExpanded,
+    header: &DbFileHeader,
+    segments: &[&DbStorage],
+) -> io::Result<()> {
+    assert_eq!(segments.len(), header.num_segments);
+    let header_bytes = serialize_with_header(ObjectKind::Database, params, header);
+    let mut writer = BufWriter::new(File::create(path)?);
+    writer.write_all(&(header_bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&header_bytes)?;
+    let mut pos = size_of::<u64>() + header_bytes.len();
+    for segment in segments {
+        assert_eq!(segment.len(), header.segment_len);
+        writer.write_all(&vec![0_u8; align_up(pos) - pos])?;
+        pos = align_up(pos);
+
+        for block in segment.blocks() {
+            // Safety: SimdVec is plain old data
+            let bytes = unsafe {
+                std::slice::from_raw_parts(
+                    block.as_ptr() as *const u8,
+                    std::mem::size_of_val(block),
+                )
+            };
+            writer.write_all(bytes)?;
+            pos += bytes.len();
+        }
+    }
+    writer.into_inner()?.sync_all()
+}
+
+///
+/// Map the database file at `path`, checking that it was written for `params` and a database
+/// matching `expected`, whose contents must accept the file's (its `db_hint` is ignored). Returns
+/// the segments, divided into blocks of `block_len`, and the database hint.
+///
+/// The file must not be modified while it is mapped.
+///
+pub fn map_db_file(
+    path: &Path,
+    params: &RespireParamsExpanded,
+    expected: &DbFileHeader,
+    block_len: usize,
+) -> Result<(Vec<DbStorage>, Vec<u8>), DbFileError> {
+    assert_eq!(expected.segment_len % block_len, 0);
+    let file = File::open(path)?;
+    // Safety: see the requirement above
+    let mmap = Arc::new(unsafe { Mmap::map(&file)? });
+
+    let truncated = |expected: usize| DbFileError::Truncated {
+        expected,
+        found: mmap.len(),
+    };
+    let len_size = size_of::<u64>();
+    if mmap.len() < len_size {
+        return Err(truncated(len_size));
+    }
+    let header_len = u64::from_le_bytes(mmap[..len_size].try_into().unwrap()) as usize;
+    let header_end = len_size.saturating_add(header_len);
+    if mmap.len() < header_end {
+        return Err(truncated(header_end));
+    }
+    let header: DbFileHeader =
+        deserialize_with_header(ObjectKind::Database, params, &mmap[len_size..header_end])?;
+    if !header.matches(expected) {
+        return Err(DbFileError::LayoutMismatch {
+            expected: Box::new(expected.clone()),
+            found: Box::new(header),
+        });
+    }
+    if !expected.contents.accepts(&header.contents) {
+        return Err(DbFileError::ContentsMismatch {
+            expected: Box::new(expected.contents.clone()),
+            found: Box::new(header.contents),
+        });
+    }
+
+    let segment_bytes = header.segment_len * size_of::<SimdVec>();
+    let mut segments = Vec::with_capacity(header.num_segments);
+    let mut pos = header_end;
+    for _ in 0..header.num_segments {
+        pos = align_up(pos);
+        if mmap.len() < pos + segment_bytes {
+            return Err(truncated(pos + segment_bytes));
+        }
+        segments.push(DbStorage {
+            base: DbBase::Mapped {
+                mmap: mmap.clone(),
+                offset: pos,
+                len: header.segment_len,
+            },
+            block_len,
+            changed: HashMap::new(),
+        });
+        pos += segment_bytes;
+    }
+    Ok((segments, header.db_hint))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::math::simd_utils::simd_lanes;
+    use crate::pir::pir::{PIRRecordBytes, PIR};
+    use crate::pir::respire::Respire;
+    use crate::pir::respire_harness::{RespireTest, RespireTestSmall};
+
+    #[test]
+    fn test_save_load() {
+        let records = (0..RespireTestSmall::NUM_RECORDS)
+            .map(|i| {
+                let bytes = (0..RespireTestSmall::BYTES_PER_RECORD)
+                    .map(|j| (i * 5 + j) as u8)
+                    .collect::<Vec<_>>();
+                <RespireTestSmall as PIR>::RecordBytes::from_bytes(&bytes).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let (db, db_hint) = RespireTestSmall::encode_db(|i| records[i].clone(), None);
+
+        let path = std::env::temp_dir().join(format!("respire_db_{}", std::process::id()));
+        RespireTestSmall::save_db(&db, &db_hint, &DbContents::default(), &path).unwrap();
+        let (mapped, mapped_hint) =
+            RespireTestSmall::load_db(&path, &DbContents::default()).unwrap();
+        assert!(mapped.is_mapped());
+        assert_eq!(simd_lanes(&mapped.to_vec()), simd_lanes(&db.to_vec()));
+
+        let (qk, pp) = RespireTestSmall::setup(None);
+        let indices = [RespireTestSmall::NUM_RECORDS - 1];
+        let (q, st) = RespireTestSmall::query(&qk, &indices, &mapped_hint, None).unwrap();
+        let r = RespireTestSmall::answer(&pp, &mapped, &q, None).unwrap();
+        let extracted = RespireTestSmall::extract(&qk, &r, &st, None).unwrap();
+        assert_eq!(extracted, vec![records[indices[0]].clone()]);
+
+        // Updating a mapped database keeps the mapping and copies only the changed blocks
+        let mut updated = mapped.clone();
+        RespireTestSmall::update_records(&mut updated, &[(0, records[1].clone())]).unwrap();
+        assert!(updated.is_mapped());
+        assert!(0 < updated.num_changed_blocks());
+        assert!(updated.num_changed_blocks() < updated.num_blocks());
+        assert_eq!(simd_lanes(&mapped.to_vec()), simd_lanes(&db.to_vec()));
+
+        // Saving writes the changed blocks. The file is tied to the version of the record file it
+        // was encoded from, unless that is not checked.
+        let updated_path = path.with_extension("updated");
+        let source = |len| DbContents {
+            source: Some(SourceFile {
+                len,
+                modified_nanos: 1,
+            }),
+            ..DbContents::default()
+        };
+        RespireTestSmall::save_db(&updated, &db_hint, &source(10), &updated_path).unwrap();
+        let (reloaded, _) = RespireTestSmall::load_db(&updated_path, &source(10)).unwrap();
+        assert_eq!(reloaded.num_changed_blocks(), 0);
+        assert_eq!(
+            simd_lanes(&reloaded.to_vec()),
+            simd_lanes(&updated.to_vec())
+        );
+        assert!(RespireTestSmall::load_db(&updated_path, &DbContents::default()).is_ok());
+        assert!(matches!(
+            RespireTestSmall::load_db(&updated_path, &source(11)),
+            Err(DbFileError::ContentsMismatch { .. })
+        ));
+        std::fs::remove_file(&updated_path).unwrap();
+
+        assert!(matches!(
+            RespireTest::load_db(&path, &DbContents::default()),
+            Err(DbFileError::Header(
+                SerializationError::ParamsMismatch { .. }
+            ))
+        ));
+
+        let len = std::fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 1)
+            .unwrap();
+        assert!(matches!(
+            RespireTestSmall::load_db(&path, &DbContents::default()),
+            Err(DbFileError::Truncated { .. })
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,733 @@
+//! Runtime selection of PIR parameter sets.
+//!
+//! The RESPIRE implementations are parameterized by const generics, so every parameter set is a
+//! distinct type. [`DynPIR`] erases that type behind an object-safe interface, and [`PIRRegistry`]
+//! maps a [`RespireParamsExpanded`] (or a name) chosen at runtime to the registered instantiation
+//! with exactly those parameters. The registered instantiations still run the fully specialized
+//! const-generic code, so there is no arithmetic overhead compared to using the types directly.
+//! Only registered parameter sets can be used; a database shape that no registered set has exactly
+//! can be served on top of one of them, with its parameters, by [`ShapedPIR`].
+
+use crate::pir::cuckoo_respire::CuckooRespire;
+use crate::pir::db_file::{DbContents, DbFileError};
+use crate::pir::error::RespireError;
+use crate::pir::pir::{PIRRecordBytes, PIR};
+use crate::pir::respire::{Respire, RespireParamsExpanded};
+use crate::pir::respire_harness::{run_benchmark, run_noise_profile, BenchReport, NoiseReport};
+use crate::pir::respire_server::RespireServer;
+use crate::pir::serialization::{
+    deserialize_with_header, serialize_with_header, ObjectKind, SerializationError,
+};
+use crate::pir::session::{ClientId, SessionError};
+use crate::pir::shaped_pir::{DbShape, ShapedPIR};
+use std::any::Any;
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+
+macro_rules! dyn_value {
+    ($name: ident, $what: literal) => {
+        #[doc = concat!("Type-erased ", $what, " produced by a [`DynPIR`].")]
+        pub struct $name(Box<dyn Any + Send + Sync>);
+
+        impl $name {
+            pub(crate) fn new<T: Any + Send + Sync>(value: T) -> Self {
+                Self(Box::new(value))
+            }
+
+            pub(crate) fn downcast<T: Any>(&self) -> &T {
+                self.0
+                    .downcast_ref::<T>()
+                    .expect(concat!($what, " was produced by a different parameter set"))
+            }
+
+            // Only some of the values are ever taken apart
+            #[allow(dead_code)]
+            pub(crate) fn into_inner<T: Any>(self) -> T {
+                *self
+                    .0
+                    .downcast::<T>()
+                    .expect(concat!($what, " was produced by a different parameter set"))
+            }
+        }
+    };
+}
+
+dyn_value!(DynQueryKey, "query key");
+dyn_value!(DynPublicParams, "public parameters");
+dyn_value!(DynDatabase, "encoded database");
+dyn_value!(DynDatabaseHint, "database hint");
+dyn_value!(DynState, "query state");
+
+///
+/// Object-safe counterpart of [`PIR`]. Records are passed as raw bytes; queries and responses are
+/// passed in their serialized (bincode) form, so they can be handed to a transport directly.
+///
+pub trait DynPIR: Send + Sync {
+    fn name(&self) -> &str;
+    /// The (base) RESPIRE parameters. For cuckoo configurations these are the per-bucket parameters.
+    fn params(&self) -> &RespireParamsExpanded;
+    fn bytes_per_record(&self) -> usize;
+    fn num_records(&self) -> usize;
+    fn batch_size(&self) -> usize;
+
+    fn print_summary(&self);
+
+    /// Fails if a record does not have exactly `bytes_per_record` bytes.
+    fn encode_db(
+        &self,
+        records_generator: &dyn Fn(usize) -> Vec<u8>,
+    ) -> Result<(DynDatabase, DynDatabaseHint), RespireError>;
+    fn setup(&self) -> (DynQueryKey, DynPublicParams);
+    fn query(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+    ) -> Result<(Vec<u8>, DynState), RespireError>;
+    fn query_truncated(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+    ) -> Result<(Vec<u8>, DynState), RespireError>;
+    /// Fails if `q` is not a well-formed serialized query for this parameter set.
+    fn answer(
+        &self,
+        pp: &DynPublicParams,
+        db: &DynDatabase,
+        q: &[u8],
+    ) -> Result<Vec<u8>, RespireError>;
+    /// Fails if `r` is not a well-formed serialized response for this parameter set.
+    fn extract(
+        &self,
+        qk: &DynQueryKey,
+        r: &[u8],
+        st: &DynState,
+    ) -> Result<Vec<Vec<u8>>, RespireError>;
+
+    /// See [`crate::pir::serialization`] for the format.
+    fn serialize_query_key(&self, qk: &DynQueryKey) -> Vec<u8>;
+    fn deserialize_query_key(&self, bytes: &[u8]) -> Result<DynQueryKey, SerializationError>;
+    fn serialize_public_params(&self, pp: &DynPublicParams) -> Vec<u8>;
+    fn deserialize_public_params(
+        &self,
+        bytes: &[u8],
+    ) -> Result<DynPublicParams, SerializationError>;
+    /// The database hint is public; the server sends it to clients before they query.
+    fn serialize_db_hint(&self, db_hint: &DynDatabaseHint) -> Vec<u8>;
+    fn deserialize_db_hint(&self, bytes: &[u8]) -> Result<DynDatabaseHint, bincode::Error>;
+
+    /// A server answering queries over `db`, see [`RespireServer`].
+    fn server(&self, db: DynDatabase, max_session_bytes: usize) -> Box<dyn DynServer + '_>;
+
+    /// See [`crate::pir::db_file`] for the format.
+    fn save_db(
+        &self,
+        db: &DynDatabase,
+        db_hint: &DynDatabaseHint,
+        contents: &DbContents,
+        path: &Path,
+    ) -> io::Result<()>;
+    fn load_db(
+        &self,
+        path: &Path,
+        contents: &DbContents,
+    ) -> Result<(DynDatabase, DynDatabaseHint), DbFileError>;
+
+    /// See [`run_benchmark`].
+    fn run_benchmark(&self, trials: usize) -> BenchReport;
+    /// See [`run_noise_profile`].
+    fn run_noise_profile(&self, trials: usize) -> NoiseReport;
+}
+
+///
+/// Object-safe counterpart of [`RespireServer`], made by [`DynPIR::server`]. Queries and responses
+/// are serialized as for [`DynPIR::answer`].
+///
+pub trait DynServer: Send + Sync {
+    fn register_client(&self, pp: DynPublicParams) -> Result<ClientId, SessionError>;
+    /// Fails without doing any work if the query is malformed or the client is unknown.
+    fn answer(&self, client: ClientId, q: &[u8]) -> Result<Vec<u8>, RespireError>;
+    fn num_sessions(&self) -> usize;
+    fn session_bytes(&self) -> usize;
+}
+
+impl<ThePIR: PIR> DynServer for RespireServer<ThePIR>
+where
+    ThePIR::PublicParams: Send + Sync + 'static,
+    ThePIR::Database: Clone + Send + Sync,
+{
+    fn register_client(&self, pp: DynPublicParams) -> Result<ClientId, SessionError> {
+        RespireServer::register_client(self, pp.into_inner())
+    }
+
+    fn answer(&self, client: ClientId, q: &[u8]) -> Result<Vec<u8>, RespireError> {
+        let q: ThePIR::Query = bincode::deserialize(q)?;
+        let r = RespireServer::answer(self, client, &q)?;
+        Ok(bincode::serialize(&r).unwrap())
+    }
+
+    fn num_sessions(&self) -> usize {
+        self.sessions().len()
+    }
+
+    fn session_bytes(&self) -> usize {
+        self.sessions().total_bytes()
+    }
+}
+
+///
+/// Adapter from a statically parameterized [`PIR`] to [`DynPIR`].
+///
+pub struct DynPIRImpl<ThePIR: PIR> {
+    name: String,
+    params: RespireParamsExpanded,
+    phantom: PhantomData<fn() -> ThePIR>,
+}
+
+impl<ThePIR: PIR> DynPIRImpl<ThePIR> {
+    pub fn new(name: &str, params: RespireParamsExpanded) -> Self {
+        Self {
+            name: name.to_owned(),
+            params,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<ThePIR: PIR + 'static> DynPIR for DynPIRImpl<ThePIR>
+where
+    ThePIR::QueryKey: Send + Sync + 'static,
+    ThePIR::PublicParams: Send + Sync + 'static,
+    ThePIR::Database: Clone + Send + Sync + 'static,
+    ThePIR::DatabaseHint: Send + Sync + 'static,
+    ThePIR::State: Send + Sync + 'static,
+{
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn params(&self) -> &RespireParamsExpanded {
+        &self.params
+    }
+
+    fn bytes_per_record(&self) -> usize {
+        ThePIR::BYTES_PER_RECORD
+    }
+
+    fn num_records(&self) -> usize {
+        ThePIR::NUM_RECORDS
+    }
+
+    fn batch_size(&self) -> usize {
+        ThePIR::BATCH_SIZE
+    }
+
+    fn print_summary(&self) {
+        ThePIR::print_summary();
+    }
+
+    fn encode_db(
+        &self,
+        records_generator: &dyn Fn(usize) -> Vec<u8>,
+    ) -> Result<(DynDatabase, DynDatabaseHint), RespireError> {
+        // The generator is called once per record, so the first bad record is remembered rather
+        // than generating the records twice
+        let wrong_len = Cell::new(None);
+        let (db, db_hint) = ThePIR::try_encode_db(
+            |i| {
+                let record = records_generator(i);
+                ThePIR::RecordBytes::from_bytes(record.as_slice()).unwrap_or_else(|| {
+                    wrong_len.set(wrong_len.get().or(Some(record.len())));
+                    Default::default()
+                })
+            },
+            None,
+        )?;
+        match wrong_len.get() {
+            Some(found) => Err(RespireError::WrongLength {
+                what: "record",
+                expected: ThePIR::BYTES_PER_RECORD,
+                found,
+            }),
+            None => Ok((DynDatabase::new(db), DynDatabaseHint::new(db_hint))),
+        }
+    }
+
+    fn setup(&self) -> (DynQueryKey, DynPublicParams) {
+        let (qk, pp) = ThePIR::setup(None);
+        (DynQueryKey::new(qk), DynPublicParams::new(pp))
+    }
+
+    fn query(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+    ) -> Result<(Vec<u8>, DynState), RespireError> {
+        let (q, st) = ThePIR::query(qk.downcast(), indices, db_hint.downcast(), None)?;
+        Ok((bincode::serialize(&q).unwrap(), DynState::new(st)))
+    }
+
+    fn query_truncated(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+    ) -> Result<(Vec<u8>, DynState), RespireError> {
+        let (q, st) = ThePIR::query_truncated(qk.downcast(), indices, db_hint.downcast(), None)?;
+        Ok((bincode::serialize(&q).unwrap(), DynState::new(st)))
+    }
+
+    fn answer(
+        &self,
+        pp: &DynPublicParams,
+        db: &DynDatabase,
+        q: &[u8],
+    ) -> Result<Vec<u8>, RespireError> {
+        let q: ThePIR::Query = bincode::deserialize(q)?;
+        let r = ThePIR::answer(pp.downcast(), db.downcast(), &q, None)?;
+        Ok(bincode::serialize(&r).unwrap())
+    }
+
+    fn extract(
+        &self,
+        qk: &DynQueryKey,
+        r: &[u8],
+        st: &DynState,
+    ) -> Result<Vec<Vec<u8>>, RespireError> {
+        let r: ThePIR::Response = bincode::deserialize(r)?;
+        Ok(ThePIR::extract(qk.downcast(), &r, st.downcast(), None)?
+            .iter()
+            .map(|record| record.as_bytes().to_vec())
+            .collect())
+    }
+
+    fn serialize_query_key(&self, qk: &DynQueryKey) -> Vec<u8> {
+        serialize_with_header(
+            ObjectKind::QueryKey,
+            &self.params,
+            qk.downcast::<ThePIR::QueryKey>(),
+        )
+    }
+
+    fn deserialize_query_key(&self, bytes: &[u8]) -> Result<DynQueryKey, SerializationError> {
+        let qk: ThePIR::QueryKey =
+            deserialize_with_header(ObjectKind::QueryKey, &self.params, bytes)?;
+        Ok(DynQueryKey::new(qk))
+    }
+
+    fn serialize_public_params(&self, pp: &DynPublicParams) -> Vec<u8> {
+        serialize_with_header(
+            ObjectKind::PublicParams,
+            &self.params,
+            pp.downcast::<ThePIR::PublicParams>(),
+        )
+    }
+
+    fn deserialize_public_params(
+        &self,
+        bytes: &[u8],
+    ) -> Result<DynPublicParams, SerializationError> {
+        let pp: ThePIR::PublicParams =
+            deserialize_with_header(ObjectKind::PublicParams, &self.params, bytes)?;
+        Ok(DynPublicParams::new(pp))
+    }
+
+    fn serialize_db_hint(&self, db_hint: &DynDatabaseHint) -> Vec<u8> {
+        bincode::serialize(db_hint.downcast::<ThePIR::DatabaseHint>()).unwrap()
+    }
+
+    fn deserialize_db_hint(&self, bytes: &[u8]) -> Result<DynDatabaseHint, bincode::Error> {
+        let db_hint: ThePIR::DatabaseHint = bincode::deserialize(bytes)?;
+        Ok(DynDatabaseHint::new(db_hint))
+    }
+
+    fn server(&self, db: DynDatabase, max_session_bytes: usize) -> Box<dyn DynServer + '_> {
+        Box::new(RespireServer::<ThePIR>::with_params(
+            db.into_inner(),
+            &self.params,
+            max_session_bytes,
+        ))
+    }
+
+    fn save_db(
+        &self,
+        db: &DynDatabase,
+        db_hint: &DynDatabaseHint,
+        contents: &DbContents,
+        path: &Path,
+    ) -> io::Result<()> {
+        ThePIR::save_db(db.downcast(), db_hint.downcast(), contents, path)
+    }
+
+    fn load_db(
+        &self,
+        path: &Path,
+        contents: &DbContents,
+    ) -> Result<(DynDatabase, DynDatabaseHint), DbFileError> {
+        let (db, db_hint) = ThePIR::load_db(path, contents)?;
+        Ok((DynDatabase::new(db), DynDatabaseHint::new(db_hint)))
+    }
+
+    fn run_benchmark(&self, trials: usize) -> BenchReport {
+        run_benchmark::<ThePIR>(trials)
+    }
+
+    fn run_noise_profile(&self, trials: usize) -> NoiseReport {
+        run_noise_profile::<ThePIR>(trials)
+    }
+}
+
+/// No registered parameter set has the requested parameters.
+#[derive(Debug)]
+pub struct UnregisteredParams {
+    /// The database shape of the requested parameters
+    pub shape: DbShape,
+}
+
+impl fmt::Display for UnregisteredParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "no registered parameter set has the requested parameters ({} records of {} bytes, {} per query)",
+            self.shape.num_records, self.shape.bytes_per_record, self.shape.batch_size
+        )
+    }
+}
+
+impl std::error::Error for UnregisteredParams {}
+
+///
+/// A set of parameter sets that can be selected at runtime.
+///
+#[derive(Default)]
+pub struct PIRRegistry {
+    entries: Vec<Box<dyn DynPIR>>,
+}
+
+impl PIRRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, entry: Box<dyn DynPIR>) {
+        assert!(
+            self.get(entry.name()).is_none(),
+            "duplicate parameter set name {}",
+            entry.name()
+        );
+        self.entries.push(entry);
+    }
+
+    pub fn register_respire<ThePIR: Respire + 'static>(&mut self, name: &str)
+    where
+        DynPIRImpl<ThePIR>: DynPIR,
+    {
+        self.register(Box::new(DynPIRImpl::<ThePIR>::new(name, ThePIR::params())));
+    }
+
+    pub fn register_cuckoo<ThePIR: CuckooRespire + 'static>(&mut self, name: &str)
+    where
+        DynPIRImpl<ThePIR>: DynPIR,
+    {
+        self.register(Box::new(DynPIRImpl::<ThePIR>::new(
+            name,
+            ThePIR::BaseRespire::params(),
+        )));
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn DynPIR> {
+        self.entries.iter().map(|e| e.as_ref())
+    }
+
+    pub fn get(&self, name: &str) -> Option<&dyn DynPIR> {
+        self.iter().find(|e| e.name() == name)
+    }
+
+    ///
+    /// The registered parameter set with exactly the parameters `params`.
+    ///
+    pub fn from_params(
+        &self,
+        params: &RespireParamsExpanded,
+    ) -> Result<&dyn DynPIR, UnregisteredParams> {
+        self.iter()
+            .find(|e| e.params() == params)
+            .ok_or_else(|| UnregisteredParams {
+                shape: DbShape::of_params(params),
+            })
+    }
+
+    ///
+    /// Serve `shape` on the registered set needing the least server work per query. The
+    /// parameters are those of that set, see [`ShapedPIR`].
+    ///
+    pub fn for_shape(&self, shape: DbShape) -> Option<ShapedPIR<'_>> {
+        self.iter()
+            .filter_map(|e| ShapedPIR::new(e, shape))
+            .min_by_key(|r| (r.cost(), r.host().batch_size()))
+    }
+
+    ///
+    /// Find the smallest registered parameter set holding at least `num_records` records of
+    /// `bytes_per_record` bytes, answering `batch_size` records per query.
+    ///
+    pub fn find(
+        &self,
+        num_records: usize,
+        bytes_per_record: usize,
+        batch_size: usize,
+    ) -> Option<&dyn DynPIR> {
+        self.iter()
+            .filter(|e| {
+                e.bytes_per_record() == bytes_per_record
+                    && e.batch_size() == batch_size
+                    && e.num_records() >= num_records
+            })
+            .min_by_key(|e| e.num_records())
+    }
+}
+
+///
+/// Build a [`PIRRegistry`] from a list of named parameter sets, e.g.
+///
+/// ```ignore
+/// let registry = respire_registry! {
+///     "256m256" => respire(FactoryParams::single_record_256(9, 9).expand().expand()),
+///     "256m256_16" => cuckoo(16, 25, 2usize.pow(20), FactoryParams::batch_256(25, 7, 8, 7).expand().expand()),
+/// };
+/// ```
+///
+/// The arguments to `cuckoo` are the batch size, the number of buckets, the number of records and
+/// the per-bucket parameters. Every entry is monomorphized in the crate invoking the macro.
+///
+#[macro_export]
+macro_rules! respire_registry {
+    (@register $registry: ident, $name: literal, respire($params: expr)) => {{
+        $registry.register_respire::<$crate::respire!($params)>($name);
+    }};
+    (@register $registry: ident, $name: literal, cuckoo($batch_size: expr, $num_bucket: expr, $num_records: expr, $params: expr)) => {{
+        type BasePIR = $crate::respire!($params);
+        $registry.register_cuckoo::<$crate::pir::cuckoo_respire::CuckooRespireImpl<
+            { $batch_size },
+            { $num_bucket },
+            { $num_records },
+            BasePIR,
+        >>($name);
+    }};
+    ($($name: literal => $kind: ident($($args: tt)*)),* $(,)?) => {{
+        let mut registry = $crate::pir::dyn_pir::PIRRegistry::new();
+        $($crate::respire_registry!(@register registry, $name, $kind($($args)*));)*
+        registry
+    }};
+}
+
+///
+/// A [`PIRRegistry`] with the parameter sets benchmarked in the paper, named after the database
+/// size and record size (and batch size, for the cuckoo configurations), e.g. `"1g256_16"`.
+///
+#[macro_export]
+macro_rules! standard_respire_registry {
+    () => {{
+        use $crate::pir::respire_harness::FactoryParams;
+        $crate::respire_registry! {
+            "64m256" => respire(FactoryParams::single_record_256(8, 8).expand().expand()),
+            "256m256" => respire(FactoryParams::single_record_256(9, 9).expand().expand()),
+            "512m256" => respire(FactoryParams::single_record_256(9, 10).expand().expand()),
+            "1g256" => respire(FactoryParams::single_record_256(10, 10).expand().expand()),
+            "2g256" => respire(FactoryParams::single_record_256(10, 11).expand().expand()),
+            "4g256" => respire(FactoryParams::single_record_256(11, 11).expand().expand()),
+            "8g256" => respire(FactoryParams::single_record_256(11, 12).expand().expand()),
+            "256m256_4" => cuckoo(4, 7, 2usize.pow(20), FactoryParams::batch_256(7, 2, 9, 8).expand().expand()),
+            "256m256_8" => cuckoo(8, 13, 2usize.pow(20), FactoryParams::batch_256(13, 4, 8, 8).expand().expand()),
+            "256m256_16" => cuckoo(16, 25, 2usize.pow(20), FactoryParams::batch_256(25, 7, 8, 7).expand().expand()),
+            "256m256_32" => cuckoo(32, 49, 2usize.pow(20), FactoryParams::batch_256(49, 8, 7, 7).expand().expand()),
+            "256m256_64" => cuckoo(64, 98, 2usize.pow(20), FactoryParams::batch_256(98, 8, 7, 6).expand().expand()),
+            "256m256_128" => cuckoo(128, 197, 2usize.pow(20), FactoryParams::batch_256(197, 8, 6, 6).expand().expand()),
+            "256m256_256" => cuckoo(256, 398, 2usize.pow(20), FactoryParams::batch_256(398, 8, 6, 5).expand().expand()),
+            "1g256_4" => cuckoo(4, 7, 2usize.pow(22), FactoryParams::batch_256(7, 2, 10, 9).expand().expand()),
+            "1g256_8" => cuckoo(8, 13, 2usize.pow(22), FactoryParams::batch_256(13, 4, 9, 9).expand().expand()),
+            "1g256_16" => cuckoo(16, 25, 2usize.pow(22), FactoryParams::batch_256(25, 7, 9, 8).expand().expand()),
+            "1g256_32" => cuckoo(32, 49, 2usize.pow(22), FactoryParams::batch_256(49, 8, 8, 8).expand().expand()),
+            "1g256_64" => cuckoo(64, 97, 2usize.pow(22), FactoryParams::batch_256(97, 8, 8, 7).expand().expand()),
+            "1g256_128" => cuckoo(128, 194, 2usize.pow(22), FactoryParams::batch_256(194, 8, 7, 7).expand().expand()),
+            "1g256_256" => cuckoo(256, 391, 2usize.pow(22), FactoryParams::batch_256(391, 8, 7, 6).expand().expand()),
+        }
+    }};
+}
+
+#[cfg(test)]
+mod test {
+    use crate::pir::db_file::{DbContents, DbFileError};
+    use crate::pir::dyn_pir::DynPIR;
+    use crate::pir::error::RespireError;
+    use crate::pir::respire_harness::{RESPIRE_TEST_PARAMS, RESPIRE_TEST_SMALL_PARAMS};
+    use crate::pir::security::{estimate_security, MIN_SECURITY_BITS};
+    use crate::pir::session::ClientId;
+    use crate::pir::shaped_pir::DbShape;
+
+    #[test]
+    fn test_registry_roundtrip() {
+        let registry = respire_registry! {
+            "test_small" => respire(RESPIRE_TEST_SMALL_PARAMS),
+        };
+
+        assert!(registry.get("missing").is_none());
+        let engine = registry.get("test_small").unwrap();
+        assert_eq!(
+            registry
+                .from_params(&RESPIRE_TEST_SMALL_PARAMS)
+                .unwrap()
+                .name(),
+            "test_small"
+        );
+        assert!(registry.from_params(&RESPIRE_TEST_PARAMS).is_err());
+        assert_eq!(
+            registry
+                .find(engine.num_records(), engine.bytes_per_record(), 1)
+                .unwrap()
+                .name(),
+            "test_small"
+        );
+
+        let record = |i: usize| {
+            let mut bytes = vec![0_u8; engine.bytes_per_record()];
+            bytes[0] = i as u8;
+            bytes[1] = 0xa5;
+            bytes
+        };
+        let (db, db_hint) = engine.encode_db(&record).unwrap();
+        assert!(matches!(
+            engine.encode_db(&|_| vec![0_u8; 3]),
+            Err(RespireError::WrongLength { found: 3, .. })
+        ));
+        let (qk, pp) = engine.setup();
+        let qk = engine
+            .deserialize_query_key(&engine.serialize_query_key(&qk))
+            .unwrap();
+        let pp = engine
+            .deserialize_public_params(&engine.serialize_public_params(&pp))
+            .unwrap();
+        let idx = engine.num_records() - 3;
+        let db_hint = engine
+            .deserialize_db_hint(&engine.serialize_db_hint(&db_hint))
+            .unwrap();
+        let (q, st) = engine.query(&qk, &[idx], &db_hint).unwrap();
+        let r = engine.answer(&pp, &db, q.as_slice()).unwrap();
+        assert_eq!(
+            engine.extract(&qk, r.as_slice(), &st).unwrap(),
+            vec![record(idx)]
+        );
+        assert!(engine.answer(&pp, &db, &q[..q.len() - 1]).is_err());
+
+        // A server holds only the database and the public parameters
+        let server = engine.server(db, usize::MAX);
+        let id = server.register_client(pp).unwrap();
+        let r = server.answer(id, &q).unwrap();
+        assert_eq!(engine.extract(&qk, &r, &st).unwrap(), vec![record(idx)]);
+        assert_eq!(server.num_sessions(), 1);
+
+        let report = engine.run_benchmark(1);
+        assert_eq!(report.summary.num_records, engine.num_records());
+        assert!(report.trials[0].correct);
+    }
+
+    #[test]
+    fn test_standard_registry_security() {
+        let registry = standard_respire_registry!();
+        for entry in registry.iter() {
+            let estimate = estimate_security(entry.params());
+            assert!(
+                estimate.bits() >= MIN_SECURITY_BITS,
+                "{}: {}",
+                entry.name(),
+                estimate
+            );
+        }
+    }
+
+    #[test]
+    fn test_registry_shape() {
+        let registry = respire_registry! {
+            "test_small" => respire(RESPIRE_TEST_SMALL_PARAMS),
+        };
+        let host = registry.get("test_small").unwrap();
+
+        // Records spanning two host records, two per query
+        let shape = DbShape {
+            num_records: host.num_records() / 2 - 1,
+            bytes_per_record: host.bytes_per_record() + 5,
+            batch_size: 2,
+        };
+        let engine = registry.for_shape(shape).unwrap();
+        assert_eq!(engine.host().name(), "test_small");
+        assert_eq!(engine.host_queries(), 4);
+        assert!(registry
+            .for_shape(DbShape {
+                num_records: host.num_records(),
+                ..shape
+            })
+            .is_none());
+
+        let record = |i: usize| {
+            let mut bytes = vec![0x5a_u8; shape.bytes_per_record];
+            bytes[0] = i as u8;
+            bytes[shape.bytes_per_record - 1] = (i >> 8) as u8;
+            bytes
+        };
+        let (db, db_hint) = engine.encode_db(&record).unwrap();
+        let (qk, pp) = engine.setup();
+        let idxs = [shape.num_records - 1, 3];
+        for truncated in [false, true] {
+            let (q, st) = if truncated {
+                engine.query_truncated(&qk, &idxs[..1], &db_hint).unwrap()
+            } else {
+                engine.query(&qk, &idxs, &db_hint).unwrap()
+            };
+            let r = engine.answer(&pp, &db, &q).unwrap();
+            let expected = if truncated { &idxs[..1] } else { &idxs[..] };
+            assert_eq!(
+                engine.extract(&qk, &r, &st).unwrap(),
+                expected.iter().map(|&i| record(i)).collect::<Vec<_>>()
+            );
+        }
+        assert!(matches!(
+            engine.query(&qk, &[shape.num_records], &db_hint),
+            Err(RespireError::UnknownRecord { .. })
+        ));
+        assert!(matches!(
+            engine.query(&qk, &[0, 1, 2], &db_hint),
+            Err(RespireError::BatchTooLarge { .. })
+        ));
+
+        // The saved database is only loaded back for the same shape
+        let path = std::env::temp_dir().join(format!("respire_shaped_db_{}", std::process::id()));
+        let contents = DbContents::default();
+        engine.save_db(&db, &db_hint, &contents, &path).unwrap();
+        assert!(engine.load_db(&path, &contents).is_ok());
+        let other = registry
+            .for_shape(DbShape {
+                num_records: shape.num_records - 1,
+                ..shape
+            })
+            .unwrap();
+        for loaded in [
+            other.load_db(&path, &contents),
+            host.load_db(&path, &contents),
+        ] {
+            assert!(matches!(loaded, Err(DbFileError::ContentsMismatch { .. })));
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let server = engine.server(db, usize::MAX);
+        let id = server.register_client(pp).unwrap();
+        let (q, st) = engine.query(&qk, &idxs, &db_hint).unwrap();
+        let r = server.answer(id, &q).unwrap();
+        assert_eq!(engine.extract(&qk, &r, &st).unwrap().len(), idxs.len());
+        assert!(server.answer(ClientId(id.0 ^ 1), &q).is_err());
+    }
+}
@@ -0,0 +1,126 @@
+//! Errors from handling data that may come from the other party: queries on the server, responses
+//! and database hints on the client.
+
+use crate::pir::cuckoo_respire::CuckooError;
+use crate::pir::session::SessionError;
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RespireError {
+    /// More records were queried than fit in a batch
+    BatchTooLarge {
+        len: usize,
+        max: usize,
+    },
+    /// A record index is past the end of the database
+    UnknownRecord {
+        idx: usize,
+        num_records: usize,
+    },
+    /// A cuckoo query names a stash bucket that does not exist
+    UnknownBucket {
+        bucket: usize,
+        num_buckets: usize,
+    },
+    /// A query or response component has the wrong number of elements
+    WrongLength {
+        what: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    /// A query or response coefficient is not reduced modulo its modulus
+    CoefficientOutOfRange {
+        what: &'static str,
+        modulus: u64,
+    },
+    /// Decoded records failed verification, e.g. after a decryption failure
+    Corrupted(CorruptedRecords),
+    Cuckoo(CuckooError),
+    Session(SessionError),
+    /// A query or response could not be deserialized
+    Malformed(bincode::Error),
+}
+
+impl fmt::Display for RespireError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RespireError::BatchTooLarge { len, max } => {
+                write!(f, "{} records queried, but a batch holds {}", len, max)
+            }
+            RespireError::UnknownRecord { idx, num_records } => write!(
+                f,
+                "record {} is not in the database of {} records",
+                idx, num_records
+            ),
+            RespireError::UnknownBucket {
+                bucket,
+                num_buckets,
+            } => write!(
+                f,
+                "bucket {} is not among the {} buckets",
+                bucket, num_buckets
+            ),
+            RespireError::WrongLength {
+                what,
+                expected,
+                found,
+            } => write!(f, "{} has length {}, expected {}", what, found, expected),
+            RespireError::CoefficientOutOfRange { what, modulus } => {
+                write!(f, "{} has a coefficient not reduced mod {}", what, modulus)
+            }
+            RespireError::Corrupted(e) => write!(f, "{}", e),
+            RespireError::Cuckoo(e) => write!(f, "{}", e),
+            RespireError::Session(e) => write!(f, "{}", e),
+            RespireError::Malformed(e) => write!(f, "malformed message: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RespireError {}
+
+impl From<CuckooError> for RespireError {
+    fn from(e: CuckooError) -> Self {
+        RespireError::Cuckoo(e)
+    }
+}
+
+impl From<SessionError> for RespireError {
+    fn from(e: SessionError) -> Self {
+        RespireError::Session(e)
+    }
+}
+
+impl From<bincode::Error> for RespireError {
+    fn from(e: bincode::Error) -> Self {
+        RespireError::Malformed(e)
+    }
+}
+
+///
+/// The records of a batch, some of which failed verification. The positions are those in the
+/// queried batch.
+///
+#[derive(Debug, PartialEq, Eq)]
+pub struct CorruptedRecords {
+    pub records: Vec<Option<Vec<u8>>>,
+}
+
+impl CorruptedRecords {
+    pub fn positions(&self) -> Vec<usize> {
+        (0..self.records.len())
+            .filter(|&i| self.records[i].is_none())
+            .collect()
+    }
+}
+
+impl fmt::Display for CorruptedRecords {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "records at positions {:?} of the batch failed verification",
+            self.positions()
+        )
+    }
+}
+
+impl std::error::Error for CorruptedRecords {}
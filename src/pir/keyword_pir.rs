@@ -0,0 +1,297 @@
+//! Keyword PIR: retrieving values by arbitrary byte string keys instead of record indices.
+//!
+//! The entries are stored in a cuckoo table laid over the records of an underlying PIR database.
+//! Each key hashes to `NUM_HASH` records and its entry occupies one of them. A record holds a tag
+//! derived from its key followed by the value, so the client queries all records of a key and keeps
+//! the one whose tag matches, or concludes that the key is absent. The hashes and tags are keyed
+//! by a random key that is published in the database hint.
+
+use crate::pir::cuckoo_respire::{CUCKOO_MAX_DEPTH, CUCKOO_MAX_KEYS};
+use crate::pir::error::RespireError;
+use crate::pir::pir::{
+    answer_batched, extract_batched, query_batched, BatchedQuery, BatchedQueryAndState,
+    PIRRecordBytes, PIR,
+};
+use itertools::Itertools;
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use siphasher::sip::SipHasher24;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hasher;
+use std::marker::PhantomData;
+
+/// Length of the key tag at the start of each record
+pub const TAG_BYTES: usize = 8;
+
+// Domain separators of the keyed hashes; the record hashes use 0..NUM_HASH
+const TAG_DOMAIN: u8 = 0xff;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeywordHint<H> {
+    /// Key of the hashes of keys to records and tags, see `KeywordPIR::key_to_records`
+    pub hash_key: [u8; 16],
+    /// Hint of the underlying database
+    pub base: H,
+}
+
+pub struct KeywordState<S> {
+    states: Vec<S>,
+    tags: Vec<[u8; TAG_BYTES]>,
+}
+
+#[derive(Debug)]
+pub enum KeywordError {
+    DuplicateKey(Vec<u8>),
+    ValueTooLong {
+        len: usize,
+        max: usize,
+    },
+    TooManyEntries {
+        entries: usize,
+        capacity: usize,
+    },
+    /// No cuckoo table was found for the entries; there are likely too many for `NUM_HASH`
+    InsertionFailed,
+    /// The underlying PIR failed to encode the table
+    Encoding(RespireError),
+}
+
+impl fmt::Display for KeywordError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeywordError::DuplicateKey(key) => write!(f, "duplicate key {:02x?}", key),
+            KeywordError::ValueTooLong { len, max } => {
+                write!(f, "value of {} bytes exceeds the maximum of {}", len, max)
+            }
+            KeywordError::TooManyEntries { entries, capacity } => write!(
+                f,
+                "{} entries do not fit in a database of {} records",
+                entries, capacity
+            ),
+            KeywordError::InsertionFailed => {
+                write!(f, "could not place the entries in a cuckoo table")
+            }
+            KeywordError::Encoding(e) => write!(f, "failed to encode the table: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for KeywordError {}
+
+///
+/// Keyword PIR over `ThePIR`, e.g. `RespireImpl` or `CuckooRespireImpl`. Each key costs `NUM_HASH`
+/// record queries, which are made in as many batches of `ThePIR` as needed; the number of batches
+/// thus reveals roughly how many keys were asked for. With two hashes, a table of `NUM_RECORDS`
+/// records holds up to about `NUM_RECORDS / 2` entries; three allow about 90% occupancy.
+///
+pub struct KeywordPIR<ThePIR: PIR, const NUM_HASH: usize = 2> {
+    phantom: PhantomData<ThePIR>,
+}
+
+impl<ThePIR: PIR, const NUM_HASH: usize> KeywordPIR<ThePIR, NUM_HASH> {
+    /// Length of values, which are zero-padded to it
+    pub const VALUE_BYTES: usize = ThePIR::BYTES_PER_RECORD - TAG_BYTES;
+
+    pub fn encode_db<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        entries: &[(K, V)],
+    ) -> Result<(ThePIR::Database, KeywordHint<ThePIR::DatabaseHint>), KeywordError> {
+        if entries.len() > ThePIR::NUM_RECORDS {
+            return Err(KeywordError::TooManyEntries {
+                entries: entries.len(),
+                capacity: ThePIR::NUM_RECORDS,
+            });
+        }
+        let mut seen = HashSet::with_capacity(entries.len());
+        for (key, value) in entries {
+            if !seen.insert(key.as_ref()) {
+                return Err(KeywordError::DuplicateKey(key.as_ref().to_vec()));
+            }
+            if value.as_ref().len() > Self::VALUE_BYTES {
+                return Err(KeywordError::ValueTooLong {
+                    len: value.as_ref().len(),
+                    max: Self::VALUE_BYTES,
+                });
+            }
+        }
+
+        let keys = entries.iter().map(|(key, _)| key.as_ref()).collect_vec();
+        let mut rng = thread_rng();
+        for _ in 0..CUCKOO_MAX_KEYS {
+            let hash_key = rng.gen();
+            let Some(table) = Self::layout(&hash_key, &keys) else {
+                continue;
+            };
+            let (db, base) = ThePIR::try_encode_db(
+                |i| match table[i] {
+                    Some(e) => {
+                        let (key, value) = &entries[e];
+                        let mut bytes = Vec::with_capacity(ThePIR::BYTES_PER_RECORD);
+                        bytes.extend(Self::key_tag(&hash_key, key.as_ref()));
+                        bytes.extend(value.as_ref());
+                        bytes.resize(ThePIR::BYTES_PER_RECORD, 0);
+                        ThePIR::RecordBytes::from_bytes(&bytes).unwrap()
+                    }
+                    None => ThePIR::RecordBytes::default(),
+                },
+                None,
+            )
+            .map_err(KeywordError::Encoding)?;
+            return Ok((db, KeywordHint { hash_key, base }));
+        }
+        Err(KeywordError::InsertionFailed)
+    }
+
+    pub fn query<K: AsRef<[u8]>>(
+        qk: &ThePIR::QueryKey,
+        keys: &[K],
+        hint: &KeywordHint<ThePIR::DatabaseHint>,
+    ) -> Result<BatchedQueryAndState<ThePIR, KeywordState<ThePIR::State>>, RespireError> {
+        let idxs = keys
+            .iter()
+            .flat_map(|key| Self::key_to_records(&hint.hash_key, key.as_ref()))
+            .collect_vec();
+        let (q, states) = query_batched::<ThePIR>(qk, &idxs, &hint.base)?;
+        let tags = keys
+            .iter()
+            .map(|key| Self::key_tag(&hint.hash_key, key.as_ref()))
+            .collect();
+        Ok((q, KeywordState { states, tags }))
+    }
+
+    pub fn answer(
+        pp: &ThePIR::PublicParams,
+        db: &ThePIR::Database,
+        q: &BatchedQuery<ThePIR::Query>,
+    ) -> Result<Vec<ThePIR::Response>, RespireError> {
+        answer_batched::<ThePIR>(pp, db, q)
+    }
+
+    /// The value of each queried key, or `None` if it is not in the database
+    pub fn extract(
+        qk: &ThePIR::QueryKey,
+        r: &[ThePIR::Response],
+        st: &KeywordState<ThePIR::State>,
+    ) -> Result<Vec<Option<Vec<u8>>>, RespireError> {
+        let records = extract_batched::<ThePIR>(qk, r, &st.states)?;
+        Ok(records
+            .chunks(NUM_HASH)
+            .zip(st.tags.iter())
+            .map(|(candidates, tag)| {
+                candidates
+                    .iter()
+                    .map(|record| record.as_bytes())
+                    .find(|bytes| bytes[..TAG_BYTES] == tag[..])
+                    .map(|bytes| bytes[TAG_BYTES..].to_vec())
+            })
+            .collect())
+    }
+
+    /// The records that may hold `key`
+    pub fn key_to_records(hash_key: &[u8; 16], key: &[u8]) -> [usize; NUM_HASH] {
+        std::array::from_fn(|j| {
+            let mut hasher = SipHasher24::new_with_key(hash_key);
+            hasher.write_u8(j as u8);
+            hasher.write(key);
+            (hasher.finish() % ThePIR::NUM_RECORDS as u64) as usize
+        })
+    }
+
+    /// Never all zero, so that empty records match no key
+    pub fn key_tag(hash_key: &[u8; 16], key: &[u8]) -> [u8; TAG_BYTES] {
+        let mut hasher = SipHasher24::new_with_key(hash_key);
+        hasher.write_u8(TAG_DOMAIN);
+        hasher.write(key);
+        (hasher.finish() | 1).to_le_bytes()
+    }
+
+    /// The entry in each record, by cuckoo insertion
+    fn layout(hash_key: &[u8; 16], keys: &[&[u8]]) -> Option<Vec<Option<usize>>> {
+        let mut table = vec![None; ThePIR::NUM_RECORDS];
+        let mut rng = thread_rng();
+        for e in 0..keys.len() {
+            let mut item = e;
+            let mut depth = 0;
+            loop {
+                let candidates = Self::key_to_records(hash_key, keys[item]);
+                if let Some(&i) = candidates.iter().find(|&&i| table[i].is_none()) {
+                    table[i] = Some(item);
+                    break;
+                }
+                depth += 1;
+                if depth >= CUCKOO_MAX_DEPTH {
+                    return None;
+                }
+                let i = candidates[rng.gen_range(0..NUM_HASH)];
+                item = table[i].replace(item).unwrap();
+            }
+        }
+        Some(table)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::respire_harness::{CuckooRespireTestSmall, RespireTestSmall};
+
+    #[test]
+    fn test_keyword_pir() {
+        type ThePIR = KeywordPIR<RespireTestSmall>;
+        let entries = (0..20)
+            .map(|i| (format!("key {}", i), format!("value {}", i * i)))
+            .collect_vec();
+        let (db, hint) = ThePIR::encode_db(&entries).unwrap();
+        let (qk, pp) = RespireTestSmall::setup(None);
+
+        let keys = ["key 3", "absent", "key 19"];
+        let (q, st) = ThePIR::query(&qk, &keys, &hint).unwrap();
+        assert_eq!(q.queries.len(), 6);
+        let r = ThePIR::answer(&pp, &db, &q).unwrap();
+        let padded = |value: &str| {
+            let mut bytes = value.as_bytes().to_vec();
+            bytes.resize(ThePIR::VALUE_BYTES, 0);
+            bytes
+        };
+        assert_eq!(
+            ThePIR::extract(&qk, &r, &st).unwrap(),
+            vec![Some(padded("value 9")), None, Some(padded("value 361"))]
+        );
+
+        assert!(matches!(
+            ThePIR::encode_db(&[("a", "1"), ("a", "2")]),
+            Err(KeywordError::DuplicateKey(_))
+        ));
+        let long = "x".repeat(ThePIR::VALUE_BYTES + 1);
+        assert!(matches!(
+            ThePIR::encode_db(&[("a", long)]),
+            Err(KeywordError::ValueTooLong { .. })
+        ));
+        let too_many = (0..RespireTestSmall::NUM_RECORDS + 1)
+            .map(|i| (i.to_le_bytes(), [0u8; 0]))
+            .collect_vec();
+        assert!(matches!(
+            ThePIR::encode_db(&too_many),
+            Err(KeywordError::TooManyEntries { .. })
+        ));
+    }
+
+    #[test]
+    fn test_keyword_cuckoo() {
+        type ThePIR = KeywordPIR<CuckooRespireTestSmall, 3>;
+        let entries = (0..40u32)
+            .map(|i| (i.to_be_bytes(), (i * 7).to_be_bytes()))
+            .collect_vec();
+        let (db, hint) = ThePIR::encode_db(&entries).unwrap();
+        let (qk, pp) = CuckooRespireTestSmall::setup(None);
+
+        // Three records per key, so three batches of two
+        let keys = [11u32.to_be_bytes(), 99u32.to_be_bytes()];
+        let (q, st) = ThePIR::query(&qk, &keys, &hint).unwrap();
+        assert_eq!(q.queries.len(), 3);
+        let r = ThePIR::answer(&pp, &db, &q).unwrap();
+        let extracted = ThePIR::extract(&qk, &r, &st).unwrap();
+        assert_eq!(extracted[0].as_ref().unwrap()[..4], 77u32.to_be_bytes());
+        assert_eq!(extracted[1], None);
+    }
+}
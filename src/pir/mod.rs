@@ -1,6 +1,23 @@
 //! PIR (Private Information Retrieval) constructs.
+pub mod checked_pir;
+pub mod cuckoo_planner;
 pub mod cuckoo_respire;
+pub mod db_file;
+pub mod dyn_pir;
+pub mod error;
+pub mod keyword_pir;
+pub mod net;
 pub mod noise;
+pub mod param_search;
 pub mod pir;
+pub mod records;
 pub mod respire;
+pub mod respire_client;
 pub mod respire_harness;
+pub mod respire_server;
+pub mod security;
+pub mod serialization;
+pub mod session;
+pub mod shaped_pir;
+pub mod snapshot;
+pub mod var_len_pir;
@@ -0,0 +1,311 @@
+//! A minimal transport for running the client and server in separate processes.
+//!
+//! Messages are bincode encoded and framed with a little-endian `u64` length prefix. A client
+//! registers by sending a [`ClientMessage::Hello`] carrying its public parameters, answered by a
+//! [`ServerMessage::Hello`] carrying its [`ClientId`] and the database hint. Any number of
+//! [`ClientMessage::Query`]/[`ServerMessage::Response`] exchanges follow, on this or later
+//! connections. If the server has evicted the client's session, it replies
+//! [`ServerMessage::UnknownClient`] and the client has to register again. Queries and responses are
+//! the bincode encodings of `PIR::Query` and `PIR::Response`.
+
+use crate::pir::session::ClientId;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+#[cfg(unix)]
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Upper bound on the size of a single frame, to avoid allocating arbitrary amounts of memory on
+/// a corrupted length prefix. Public parameters are the largest messages, at a few tens of MB.
+pub const MAX_FRAME_LEN: u64 = 1 << 30;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientMessage {
+    Hello {
+        /// Name of the parameter set in the registry, which must match the server's
+        config: String,
+        public_params: Vec<u8>,
+    },
+    Query {
+        client_id: ClientId,
+        query: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ServerMessage {
+    Hello {
+        client_id: ClientId,
+        db_hint: Vec<u8>,
+    },
+    Response(Vec<u8>),
+    UnknownClient(ClientId),
+    Error(String),
+}
+
+pub fn send<W: Write, T: Serialize>(writer: &mut W, msg: &T) -> io::Result<()> {
+    let bytes = bincode::serialize(msg).map_err(io::Error::other)?;
+    writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+    writer.write_all(&bytes)?;
+    writer.flush()
+}
+
+pub fn recv<R: Read, T: DeserializeOwned>(reader: &mut R) -> io::Result<T> {
+    recv_limited(reader, MAX_FRAME_LEN)
+}
+
+///
+/// Like [`recv`], but rejecting frames longer than `max_len` before reading them. Memory is only
+/// allocated as the frame arrives, so a peer announcing a long frame has to send it.
+///
+pub fn recv_limited<R: Read, T: DeserializeOwned>(reader: &mut R, max_len: u64) -> io::Result<T> {
+    let mut len_bytes = [0_u8; 8];
+    reader.read_exact(&mut len_bytes)?;
+    let len = u64::from_le_bytes(len_bytes);
+    if len > max_len.min(MAX_FRAME_LEN) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("frame of {} bytes exceeds the limit", len),
+        ));
+    }
+    let mut bytes = Vec::new();
+    reader.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    bincode::deserialize(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+///
+/// Address to listen on or connect to: `tcp:HOST:PORT` (or just `HOST:PORT`), or `unix:PATH`.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Endpoint {
+    Tcp(String),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for Endpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(format!("unix sockets are not supported: {}", path));
+        }
+        let addr = s.strip_prefix("tcp:").unwrap_or(s);
+        if addr.is_empty() {
+            return Err("empty address".to_owned());
+        }
+        Ok(Endpoint::Tcp(addr.to_owned()))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addr) => write!(f, "tcp:{}", addr),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub enum Connection {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Connection {
+    pub fn connect(endpoint: &Endpoint) -> io::Result<Self> {
+        match endpoint {
+            Endpoint::Tcp(addr) => {
+                let stream = TcpStream::connect(addr)?;
+                stream.set_nodelay(true)?;
+                Ok(Connection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => Ok(Connection::Unix(UnixStream::connect(path)?)),
+        }
+    }
+
+    ///
+    /// Fail reads and writes that block for longer than `timeout`, or never with `None`. A timed
+    /// out operation returns an error for which [`is_timeout`] holds.
+    ///
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+            #[cfg(unix)]
+            Connection::Unix(stream) => {
+                stream.set_read_timeout(timeout)?;
+                stream.set_write_timeout(timeout)
+            }
+        }
+    }
+}
+
+/// Whether `e` is a read or write that ran past the timeout of its [`Connection`].
+pub fn is_timeout(e: &io::Error) -> bool {
+    // Unix reports WouldBlock, Windows TimedOut
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
+
+impl Read for Connection {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => stream.read(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Connection {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Connection::Tcp(stream) => stream.write(buf),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Connection::Tcp(stream) => stream.flush(),
+            #[cfg(unix)]
+            Connection::Unix(stream) => stream.flush(),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+    ///
+    /// Bind to `endpoint`. For Unix sockets, a stale socket file at the path is removed first.
+    ///
+    pub fn bind(endpoint: &Endpoint) -> io::Result<Self> {
+        match endpoint {
+            Endpoint::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr)?)),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                if path.exists() {
+                    std::fs::remove_file(path)?;
+                }
+                Ok(Listener::Unix(UnixListener::bind(path)?))
+            }
+        }
+    }
+
+    pub fn accept(&self) -> io::Result<Connection> {
+        match self {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                stream.set_nodelay(true)?;
+                Ok(Connection::Tcp(stream))
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => Ok(Connection::Unix(listener.accept()?.0)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_parse() {
+        assert_eq!(
+            "127.0.0.1:7000".parse(),
+            Ok(Endpoint::Tcp("127.0.0.1:7000".to_owned()))
+        );
+        assert_eq!(
+            "tcp:localhost:7000".parse(),
+            Ok(Endpoint::Tcp("localhost:7000".to_owned()))
+        );
+        #[cfg(unix)]
+        assert_eq!(
+            "unix:/tmp/respire.sock".parse(),
+            Ok(Endpoint::Unix(PathBuf::from("/tmp/respire.sock")))
+        );
+        assert!("tcp:".parse::<Endpoint>().is_err());
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut buf = Vec::new();
+        let query = ClientMessage::Query {
+            client_id: ClientId(7),
+            query: vec![1, 2, 3],
+        };
+        send(&mut buf, &query).unwrap();
+        send(&mut buf, &ServerMessage::Error("oops".to_owned())).unwrap();
+
+        let mut reader = buf.as_slice();
+        assert!(matches!(
+            recv(&mut reader).unwrap(),
+            ClientMessage::Query { client_id: ClientId(7), query } if query == vec![1, 2, 3]
+        ));
+        assert!(matches!(
+            recv(&mut reader).unwrap(),
+            ServerMessage::Error(e) if e == "oops"
+        ));
+        assert!(recv::<_, ServerMessage>(&mut reader).is_err());
+
+        let oversized = (MAX_FRAME_LEN + 1).to_le_bytes();
+        assert_eq!(
+            recv::<_, ServerMessage>(&mut oversized.as_slice())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        assert_eq!(
+            recv_limited::<_, ServerMessage>(&mut buf.as_slice(), 8)
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+        let cut = (MAX_FRAME_LEN - 1).to_le_bytes();
+        assert_eq!(
+            recv::<_, ServerMessage>(&mut cut.as_slice())
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn test_timeout() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = Endpoint::Tcp(listener.local_addr().unwrap().to_string());
+        let _client = Connection::connect(&endpoint).unwrap();
+        let mut conn = Connection::Tcp(listener.accept().unwrap().0);
+        conn.set_timeout(Some(Duration::from_millis(50))).unwrap();
+        // The client never sends anything
+        let e = recv::<_, ClientMessage>(&mut conn).unwrap_err();
+        assert!(is_timeout(&e), "{:?}", e);
+    }
+}
@@ -1,4 +1,6 @@
-use std::ops::{Add, Mul};
+use serde::Serialize;
+use std::f64::consts::PI;
+use std::ops::{Add, AddAssign, Mul};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Independent(pub f64);
@@ -147,3 +149,55 @@ impl Mul<SubGaussianNoise> for BoundedNoise {
         } * Independent(self.cols as f64)
     }
 }
+
+/// log2 of the subgaussian width (`sqrt(2 pi)` standard deviations) of noise of the given variance
+pub fn variance_to_subgaussian_bits(variance: f64) -> f64 {
+    (variance * (2_f64 * PI)).log2() / 2_f64
+}
+
+///
+/// Measured noise coefficients, e.g. of one phase of answering a query. Only the sum of squares and
+/// a histogram of the magnitudes are kept, so that samples can be accumulated over many trials.
+///
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct NoiseSamples {
+    pub count: u64,
+    pub sum_sq: f64,
+    pub max_abs: u64,
+    /// `bit_lengths[k]` is the number of coefficients `e` with `2^(k-1) <= |e| < 2^k` (`e = 0` for
+    /// `k = 0`)
+    pub bit_lengths: Vec<u64>,
+}
+
+impl NoiseSamples {
+    /// Add a coefficient of absolute value `abs`
+    pub fn add(&mut self, abs: u64) {
+        self.count += 1;
+        self.sum_sq += (abs as f64).powi(2);
+        self.max_abs = self.max_abs.max(abs);
+        let bits = (u64::BITS - abs.leading_zeros()) as usize;
+        if self.bit_lengths.len() <= bits {
+            self.bit_lengths.resize(bits + 1, 0);
+        }
+        self.bit_lengths[bits] += 1;
+    }
+
+    /// The second moment of the coefficients; the noise has mean zero
+    pub fn variance(&self) -> f64 {
+        self.sum_sq / self.count as f64
+    }
+}
+
+impl AddAssign<NoiseSamples> for NoiseSamples {
+    fn add_assign(&mut self, rhs: Self) {
+        self.count += rhs.count;
+        self.sum_sq += rhs.sum_sq;
+        self.max_abs = self.max_abs.max(rhs.max_abs);
+        if self.bit_lengths.len() < rhs.bit_lengths.len() {
+            self.bit_lengths.resize(rhs.bit_lengths.len(), 0);
+        }
+        for (lhs, rhs) in self.bit_lengths.iter_mut().zip(rhs.bit_lengths) {
+            *lhs += rhs;
+        }
+    }
+}
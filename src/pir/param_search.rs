@@ -0,0 +1,358 @@
+//! Choosing RESPIRE parameters for a target database.
+//!
+//! The standard parameter sets are built from the [`FactoryParams`] templates, which fix the
+//! ciphertext moduli and gadgets and leave the database dimensions (`NU1`, `NU2`), the response
+//! vector dimension (`N_VEC`) and the compression modulus (`Q2`) to choose. This enumerates those
+//! choices for a database, keeps the ones whose estimated decryption failure probability (see
+//! [`RespireParamsExpanded::error_rate_estimate`]) meets the target, and ranks them by a simple
+//! cost model: the estimated server time plus the time to send the query and the response.
+//! Candidates below the target security level (see [`crate::pir::security`]) are dropped too.
+//!
+//! Batches of more than one record use cuckoo hashing, with the bucket count and bucket size from
+//! [`plan_cuckoo`]; the search then covers the parameters of each bucket's database.
+
+use crate::math::utils::{ceil_log, floor_log};
+use crate::pir::cuckoo_planner::{plan_cuckoo, CuckooPlan, PlanError};
+use crate::pir::respire::RespireParamsExpanded;
+use crate::pir::respire_harness::FactoryParams;
+use crate::pir::security::estimate_security;
+use std::fmt;
+
+///
+/// Compression moduli with the noise width used alongside each, as `(D2, Q2, width in
+/// millionths)`. The moduli are NTT-friendly primes; the widths were chosen with the LWE estimator
+/// (see the notes in [`FactoryParams::single_record_256`]).
+///
+pub const Q2_CHOICES: &[(usize, u64, u64)] = &[
+    (512, 1032193, 46_000_000),
+    (512, 2056193, 70_000_000),
+    (512, 16760833, 253_600_000),
+    (2048, 249857, 2_001_000),
+];
+
+/// Records per database ring element of `single_record_256`
+const PACK_RATIO_DB: usize = FactoryParams::single_record_256(1, 1)
+    .expand()
+    .expand()
+    .pack_ratio_db();
+
+///
+/// Weights turning a candidate's estimated server work and communication into a time.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct CostModel {
+    /// Time for one modular multiplication on the server
+    pub ns_per_op: f64,
+    /// Time to send one byte between client and server
+    pub ns_per_byte: f64,
+}
+
+impl Default for CostModel {
+    /// One multiplication per nanosecond and a 1 Gbit/s link
+    fn default() -> Self {
+        Self {
+            ns_per_op: 1_f64,
+            ns_per_byte: 8_f64,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct SearchTarget {
+    /// Database size in bytes
+    pub db_size: usize,
+    /// Record size in bytes. Records shorter than the templates' 256 bytes are zero-padded.
+    pub record_size: usize,
+    pub batch_size: usize,
+    /// log2 of the acceptable probability that a record fails to decode, and for batches also of
+    /// the acceptable probability that a batch cannot be assigned to buckets
+    pub failure_log2: f64,
+    /// Hash functions per record, for batches
+    pub num_hash: usize,
+    /// Minimum estimated (core-SVP) security
+    pub min_security_bits: f64,
+    pub cost: CostModel,
+}
+
+impl SearchTarget {
+    pub fn num_records(&self) -> usize {
+        self.db_size.div_ceil(self.record_size)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Candidate {
+    pub factory: FactoryParams,
+    pub params: RespireParamsExpanded,
+    /// For batches, the cuckoo configuration; `params` are then those of each bucket
+    pub cuckoo: Option<CuckooPlan>,
+    pub error_rate_log2: f64,
+    pub security_bits: f64,
+    pub query_size: usize,
+    pub response_size: usize,
+    pub public_param_size: usize,
+    /// Estimated modular multiplications to answer one query
+    pub server_ops: f64,
+    /// Estimated server time plus communication time, in nanoseconds
+    pub cost_ns: f64,
+}
+
+impl Candidate {
+    fn new(factory: FactoryParams, cuckoo: Option<CuckooPlan>, cost: &CostModel) -> Candidate {
+        let params = factory.expand().expand();
+        let query_size = params.query_size();
+        let response_size = params.response_info().0;
+        let server_ops = server_ops(&params);
+        Candidate {
+            factory,
+            params,
+            cuckoo,
+            error_rate_log2: params.error_rate_estimate().log2(),
+            security_bits: estimate_security(&params).bits(),
+            query_size,
+            response_size,
+            public_param_size: params.public_param_size(),
+            server_ops,
+            cost_ns: server_ops * cost.ns_per_op
+                + (query_size + response_size) as f64 * cost.ns_per_byte,
+        }
+    }
+
+    fn num_records(&self) -> usize {
+        match &self.cuckoo {
+            Some(plan) => plan.num_records,
+            None => self.params.num_records(),
+        }
+    }
+}
+
+impl fmt::Display for Candidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "NU1 = {}, NU2 = {}, N_VEC = {}, Q2 = {}: error rate 2^({:.1}), {:.1} bits of security, query {:.1} KiB, response {:.1} KiB, public params {:.1} KiB, {:.2e} server ops, cost {:.3} ms",
+            self.params.NU1,
+            self.params.NU2,
+            self.params.N_VEC,
+            self.params.Q2,
+            self.error_rate_log2,
+            self.security_bits,
+            self.query_size as f64 / 1024_f64,
+            self.response_size as f64 / 1024_f64,
+            self.public_param_size as f64 / 1024_f64,
+            self.server_ops,
+            self.cost_ns / 1_000_000_f64,
+        )?;
+        writeln!(
+            f,
+            "const PARAMS: RespireParamsExpanded = {:#?}",
+            self.factory
+        )?;
+        writeln!(f, ".expand()")?;
+        writeln!(f, ".expand();")?;
+        match &self.cuckoo {
+            Some(plan) => write!(
+                f,
+                "type ThePIR = CuckooRespireImpl<{}, {}, {}, respire!(PARAMS), {}>;",
+                plan.batch_size,
+                plan.num_bucket,
+                self.num_records(),
+                plan.num_hash
+            ),
+            None => write!(f, "type ThePIR = respire!(PARAMS);"),
+        }
+    }
+}
+
+///
+/// A rough count of the modular multiplications to answer one query: the first dimension
+/// multiplies every database element by a query element, and every other step is counted in key
+/// switching units of one NTT plus a pointwise product per gadget element. For cuckoo
+/// configurations, `BATCH_SIZE` is the number of buckets, each with its own database.
+///
+pub fn server_ops(params: &RespireParamsExpanded) -> f64 {
+    let d1 = params.D1 as f64;
+    // Both CRT components
+    let unit = 2_f64 * (d1 * (floor_log(2, params.D1 as u64) as f64) + 2_f64 * d1);
+
+    let first_dim = 2_f64 * d1 * params.packed_db_size() as f64;
+    let expand_reg = params.rlwe_count() * params.T_PROJ_SHORT;
+    let expand_gsw = (1 << ceil_log(2, params.gsw_count() as u64)) * params.T_PROJ_LONG;
+    let reg_to_gsw = (params.NU2 + params.nu3()) * params.T_GSW * params.M_RLWE_TO_GSW;
+    let fold = ((1 << params.NU2) + params.nu3()) * params.M_GSW;
+    let project = params.nu3() * params.T_PROJ_LONG;
+    let per_query =
+        first_dim + unit * (expand_reg + expand_gsw + reg_to_gsw + fold + project) as f64;
+
+    // Each packed ring element of the response is switched into a vector ciphertext once
+    let num_ring_elem = params.BATCH_SIZE.div_ceil(params.pack_ratio_response());
+    let vectorize = num_ring_elem * params.T_VECTORIZE;
+    params.BATCH_SIZE as f64 * per_query + unit * vectorize as f64
+}
+
+// Splits of nu into (NU1, NU2), both at least one
+fn splits(nu: usize) -> impl Iterator<Item = (usize, usize)> {
+    (1..nu).map(move |nu1| (nu1, nu - nu1))
+}
+
+///
+/// All candidates for `target` that meet its failure probability, cheapest first. Only records
+/// of at most 256 bytes are supported. Fails if a batch cannot be cuckoo hashed, see
+/// [`plan_cuckoo`].
+///
+pub fn search_params(target: &SearchTarget) -> Result<Vec<Candidate>, PlanError> {
+    assert!(target.record_size > 0 && target.record_size <= 256);
+    if target.batch_size == 0 {
+        return Err(PlanError::EmptyBatch);
+    }
+    if target.num_records() < target.batch_size {
+        return Err(PlanError::TooFewRecords {
+            num_records: target.num_records(),
+            batch_size: target.batch_size,
+        });
+    }
+
+    let mut candidates = Vec::new();
+    if target.batch_size == 1 {
+        let num_records = target.num_records().max(PACK_RATIO_DB);
+        let nu = ceil_log(2, num_records.div_ceil(PACK_RATIO_DB) as u64).max(2);
+        for (nu1, nu2) in splits(nu) {
+            let template = FactoryParams::single_record_256(nu1, nu2);
+            for &(_, q2, width) in Q2_CHOICES.iter().filter(|c| c.0 == template.D2) {
+                let factory = FactoryParams {
+                    Q2: q2,
+                    WIDTH_COMPRESS_MILLIONTHS: width,
+                    ..template
+                };
+                candidates.push(Candidate::new(factory, None, &target.cost));
+            }
+        }
+    } else {
+        let plan = plan_cuckoo(
+            target.num_records(),
+            target.batch_size,
+            target.num_hash,
+            target.failure_log2,
+            -1_f64,
+        )?;
+        for (nu1, nu2) in splits((plan.nu1 + plan.nu2).max(2)) {
+            for n_vec in 1..=plan.n_vec {
+                let template = FactoryParams::batch_256(plan.num_bucket, n_vec, nu1, nu2);
+                for &(_, q2, width) in Q2_CHOICES.iter().filter(|c| c.0 == template.D2) {
+                    let factory = FactoryParams {
+                        Q2: q2,
+                        WIDTH_COMPRESS_MILLIONTHS: width,
+                        ..template
+                    };
+                    let plan = CuckooPlan {
+                        nu1,
+                        nu2,
+                        n_vec,
+                        ..plan.clone()
+                    };
+                    candidates.push(Candidate::new(factory, Some(plan), &target.cost));
+                }
+            }
+        }
+    }
+
+    candidates.retain(|c| {
+        c.error_rate_log2 <= target.failure_log2 && c.security_bits >= target.min_security_bits
+    });
+    candidates.sort_by(|a, b| a.cost_ns.total_cmp(&b.cost_ns));
+    Ok(candidates)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::cuckoo_planner::cuckoo_failure_log2;
+    use crate::pir::pir::PIR;
+    use crate::pir::respire::Respire;
+    use crate::pir::respire_harness::{CuckooRespireTestSmall, RespireTest, RESPIRE_TEST_PARAMS};
+
+    #[test]
+    fn test_runtime_estimates() {
+        let summary = RespireTest::summary();
+        assert_eq!(RESPIRE_TEST_PARAMS.num_records(), RespireTest::NUM_RECORDS);
+        assert_eq!(RESPIRE_TEST_PARAMS.query_size(), summary.query_size);
+        assert_eq!(RESPIRE_TEST_PARAMS.response_info().0, summary.response_size);
+        assert_eq!(
+            RESPIRE_TEST_PARAMS.error_rate_estimate(),
+            RespireTest::params_error_rate_estimate()
+        );
+
+        // A cuckoo configuration's sizes are those of its base parameters, batched per bucket
+        let summary = CuckooRespireTestSmall::summary();
+        assert_eq!(summary.params.query_size(), summary.query_size);
+        assert_eq!(summary.params.response_info().0, summary.response_size);
+    }
+
+    #[test]
+    fn test_search_single() {
+        let target = SearchTarget {
+            db_size: 256 << 20,
+            record_size: 256,
+            batch_size: 1,
+            failure_log2: -40_f64,
+            num_hash: 3,
+            min_security_bits: 96_f64,
+            cost: CostModel::default(),
+        };
+        let candidates = search_params(&target).unwrap();
+        assert!(!candidates.is_empty());
+        assert!(candidates.windows(2).all(|w| w[0].cost_ns <= w[1].cost_ns));
+        let best = &candidates[0];
+        assert!(best.cuckoo.is_none());
+        assert_eq!(best.params.num_records(), 1 << 20);
+        assert!(best.error_rate_log2 <= -40_f64);
+        assert!(best.security_bits >= 96_f64);
+        assert_eq!(best.params, best.factory.expand().expand());
+
+        // Smaller records are padded, so the same database of twice as many records is needed
+        let half = search_params(&SearchTarget {
+            db_size: 128 << 20,
+            record_size: 128,
+            ..target
+        })
+        .unwrap();
+        assert_eq!(half[0].params, best.params);
+    }
+
+    #[test]
+    fn test_search_batch() {
+        let target = SearchTarget {
+            db_size: 256 << 20,
+            record_size: 256,
+            batch_size: 16,
+            failure_log2: -20_f64,
+            num_hash: 3,
+            min_security_bits: 96_f64,
+            cost: CostModel::default(),
+        };
+        let candidates = search_params(&target).unwrap();
+        let best = &candidates[0];
+        let plan = best.cuckoo.as_ref().unwrap();
+        assert_eq!(best.params.BATCH_SIZE, plan.num_bucket);
+        assert!(cuckoo_failure_log2(16, plan.num_bucket, 3) <= -20_f64);
+        assert!(best.params.num_records() >= plan.bucket_capacity);
+        assert!(best.params.N_VEC <= FactoryParams::MAX_N_VEC);
+        assert!(best.error_rate_log2 <= -20_f64);
+        assert!(best.to_string().contains("CuckooRespireImpl<16, "));
+        assert!(best.to_string().contains("respire!(PARAMS), 3>;"));
+
+        let empty = SearchTarget {
+            batch_size: 0,
+            ..target
+        };
+        assert!(matches!(search_params(&empty), Err(PlanError::EmptyBatch)));
+        let too_large = SearchTarget {
+            batch_size: target.num_records() + 1,
+            ..target
+        };
+        assert!(matches!(
+            search_params(&too_large),
+            Err(PlanError::TooFewRecords { .. })
+        ));
+    }
+}
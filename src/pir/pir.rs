@@ -1,6 +1,15 @@
+use crate::pir::db_file::{DbContents, DbFileError};
+use crate::pir::error::RespireError;
+use crate::pir::noise::NoiseSamples;
+use crate::pir::respire::RespireParamsExpanded;
+use crate::pir::security::SecurityEstimate;
+use log::info;
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::io;
 use std::ops::AddAssign;
+use std::path::Path;
 use std::time::Duration;
 
 pub trait PIRRecordBytes: Clone + Default {
@@ -8,12 +17,12 @@ pub trait PIRRecordBytes: Clone + Default {
     fn as_bytes(&self) -> &[u8];
 }
 
-pub struct Stats<T: AddAssign<T> + Copy + Default> {
+pub struct Stats<T: AddAssign<T> + Clone + Default> {
     order: Vec<&'static str>,
     stats: HashMap<&'static str, T>,
 }
 
-impl<T: AddAssign<T> + Copy + Default> Stats<T> {
+impl<T: AddAssign<T> + Clone + Default> Stats<T> {
     pub fn add(&mut self, name: &'static str, value: T) {
         match self.stats.get_mut(name) {
             Some(it) => {
@@ -35,52 +44,292 @@ impl<T: AddAssign<T> + Copy + Default> Stats<T> {
     pub fn as_vec(&self) -> Vec<(&'static str, T)> {
         let mut result = Vec::with_capacity(self.order.len());
         for name in self.order.iter().copied() {
-            result.push((name, self.stats[name]));
+            result.push((name, self.stats[name].clone()));
         }
         result
     }
+
+    /// Add the values of `other`, e.g. collected on another thread
+    pub fn merge(&mut self, other: &Self) {
+        for (name, value) in other.as_vec() {
+            self.add(name, value);
+        }
+    }
+}
+
+///
+/// Map `f` over `items` in parallel, timing each call into its own `Stats` that are then merged
+/// into `time_stats`. The phases are thus summed over the items, i.e. they add up to the total
+/// work across threads rather than to the elapsed time. Measured noise is merged into
+/// `noise_stats` in the same way.
+///
+pub fn par_map_stats<I, U, F>(
+    items: I,
+    time_stats: Option<&mut Stats<Duration>>,
+    noise_stats: Option<&mut Stats<NoiseSamples>>,
+    f: F,
+) -> Vec<U>
+where
+    I: IndexedParallelIterator,
+    U: Send,
+    F: Fn(I::Item, Option<&mut Stats<Duration>>, Option<&mut Stats<NoiseSamples>>) -> U
+        + Sync
+        + Send,
+{
+    let timed = time_stats.is_some();
+    let measured = noise_stats.is_some();
+    let (results, item_stats): (Vec<U>, Vec<_>) = items
+        .map(|item| {
+            let mut times = Stats::new();
+            let mut noise = Stats::new();
+            let result = f(
+                item,
+                timed.then_some(&mut times),
+                measured.then_some(&mut noise),
+            );
+            (result, (times, noise))
+        })
+        .unzip();
+    if let Some(time_stats) = time_stats {
+        for (times, _) in item_stats.iter() {
+            time_stats.merge(times);
+        }
+    }
+    if let Some(noise_stats) = noise_stats {
+        for (_, noise) in item_stats.iter() {
+            noise_stats.merge(noise);
+        }
+    }
+    results
+}
+
+///
+/// The parameters and communication sizes of a PIR instantiation (sizes in bytes), as printed by
+/// `print_summary`.
+///
+#[derive(Clone, Debug, Serialize)]
+pub struct PIRSummary {
+    /// The (base) RESPIRE parameters. For cuckoo configurations these are the per-bucket parameters.
+    pub params: RespireParamsExpanded,
+    /// Records in the database as encoded, not counting any appended since
+    pub num_records: usize,
+    pub bytes_per_record: usize,
+    pub batch_size: usize,
+    pub public_param_size: usize,
+    pub query_size: usize,
+    pub response_size: usize,
+    /// Full vectors and the length of the remaining one in the response
+    pub response_full_vecs: usize,
+    pub response_rem: usize,
+    /// Size of the records retrieved by a query
+    pub record_size: usize,
+    pub rate: f64,
+    pub error_rate_log2: f64,
+    pub security: SecurityEstimate,
+}
+
+impl PIRSummary {
+    /// Print the summary, following the title line printed by `PIR::print_summary`
+    pub fn print(&self) {
+        eprintln!("Parameters: {:#?}", self.params);
+        eprintln!(
+            "Public param size: {:.3} KiB",
+            self.public_param_size as f64 / 1024_f64
+        );
+        eprintln!("Query size: {:.3} KiB", self.query_size as f64 / 1024_f64);
+        info!(
+            "Response: {} full vector(s), {} remainder",
+            self.response_full_vecs, self.response_rem
+        );
+        eprintln!(
+            "Response size (batch): {:.3} KiB",
+            self.response_size as f64 / 1024_f64
+        );
+        eprintln!(
+            "Record size (batch): {:.3} KiB",
+            self.record_size as f64 / 1024_f64
+        );
+        eprintln!("Rate: {:.3}", self.rate);
+        eprintln!("Error rate (estimated): 2^({:.3})", self.error_rate_log2);
+        eprintln!("Security (estimated): {}", self.security);
+    }
 }
 
 pub trait PIR {
     // Associated types
-    type QueryKey: Sync;
-    type PublicParams: Sync;
+    type QueryKey: Sync + Serialize + for<'de> Deserialize<'de>;
+    type PublicParams: Sync + Serialize + for<'de> Deserialize<'de>;
     type Query: Serialize + for<'de> Deserialize<'de>;
     type Response: Serialize + for<'de> Deserialize<'de>;
     type Database: Sync;
-    type DatabaseHint;
+    type DatabaseHint: Serialize + for<'de> Deserialize<'de>;
     type State;
 
     // A single raw record
     type RecordBytes: PIRRecordBytes;
     const BYTES_PER_RECORD: usize;
+    /// Number of records `encode_db` encodes. Cuckoo configurations can append records past it
+    /// (see `CuckooRespireImpl::append_records`); their hint holds the current number, which
+    /// their queries are checked against instead.
     const NUM_RECORDS: usize;
     const BATCH_SIZE: usize;
 
     fn print_summary();
+    fn summary() -> PIRSummary;
 
     fn encode_db<F: Fn(usize) -> Self::RecordBytes>(
         records_generator: F,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> (Self::Database, Self::DatabaseHint);
-    fn setup() -> (Self::QueryKey, Self::PublicParams); //time_stats: Option<&mut Stats<Duration>>) -> (Self::QueryKey, Self::PublicParams);
+    ///
+    /// Like `encode_db`, but fails where `encode_db` panics: for cuckoo configurations, when no
+    /// hash key lays the records out within the buckets.
+    ///
+    fn try_encode_db<F: Fn(usize) -> Self::RecordBytes>(
+        records_generator: F,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(Self::Database, Self::DatabaseHint), RespireError> {
+        Ok(Self::encode_db(records_generator, time_stats))
+    }
+    fn setup(time_stats: Option<&mut Stats<Duration>>) -> (Self::QueryKey, Self::PublicParams);
+    ///
+    /// Query up to `BATCH_SIZE` records. The query is padded with dummy records, so that neither
+    /// its size nor the response reveals how many records were asked for; `extract` returns only
+    /// the requested ones. Fails on a batch that is too large, unknown records, or a database hint
+    /// the records cannot be located with.
+    ///
     fn query(
         qk: &Self::QueryKey,
         idx: &[usize],
         db_hint: &Self::DatabaseHint,
-        //time_stats: Option<&mut Stats<Duration>>,
-    ) -> (Self::Query, Self::State);
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(Self::Query, Self::State), RespireError>;
+    ///
+    /// Like `query`, but without padding where that shortens the response and the server's work.
+    /// This reveals the number of requested records to the server. Constructions that cannot
+    /// truncate their responses pad as `query` does.
+    ///
+    fn query_truncated(
+        qk: &Self::QueryKey,
+        idx: &[usize],
+        db_hint: &Self::DatabaseHint,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(Self::Query, Self::State), RespireError> {
+        Self::query(qk, idx, db_hint, time_stats)
+    }
+    ///
+    /// Fails without doing any work if the query is malformed, e.g. has the wrong dimensions. Takes
+    /// nothing secret: this is all a server runs.
+    ///
     fn answer(
         pp: &Self::PublicParams,
         db: &Self::Database,
         q: &Self::Query,
-        qk: Option<&Self::QueryKey>,
-        //time_stats: Option<&mut Stats<Duration>>,
-    ) -> Self::Response;
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Self::Response, RespireError>;
+    ///
+    /// Answer `q` as `answer` does, decrypting the intermediate ciphertexts with the query key to
+    /// add the noise after each phase to `noise_stats`. This is for validating the error analysis
+    /// (see [`crate::pir::respire_harness::run_noise_profile`]); a real server never has the key.
+    ///
+    fn answer_measured(
+        pp: &Self::PublicParams,
+        db: &Self::Database,
+        q: &Self::Query,
+        qk: &Self::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
+    ) -> Result<Self::Response, RespireError>;
+    /// Fails if the response is malformed.
     fn extract(
         qk: &Self::QueryKey,
         r: &Self::Response,
         st: &Self::State,
-        //time_stats: Option<&mut Stats<Duration>>,
-    ) -> Vec<Self::RecordBytes>;
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Vec<Self::RecordBytes>, RespireError>;
+
+    /// Write an encoded database of `contents` to `path`, see [`crate::pir::db_file`].
+    fn save_db(
+        db: &Self::Database,
+        db_hint: &Self::DatabaseHint,
+        contents: &DbContents,
+        path: &Path,
+    ) -> io::Result<()>;
+    /// Memory-map a database written by `save_db` for `contents`, without copying or re-encoding
+    /// it.
+    fn load_db(
+        path: &Path,
+        contents: &DbContents,
+    ) -> Result<(Self::Database, Self::DatabaseHint), DbFileError>;
+}
+
+/// Queries for any number of records, made in as many batches of the underlying PIR as needed
+#[derive(Serialize, Deserialize)]
+pub struct BatchedQuery<Q> {
+    pub queries: Vec<Q>,
+}
+
+/// A batched query of `ThePIR` with the client state `S` to extract its responses with
+pub type BatchedQueryAndState<ThePIR, S> = (BatchedQuery<<ThePIR as PIR>::Query>, S);
+
+/// Check that `idxs` fits in one batch and names records of the database, for instantiations
+/// that cannot append records.
+pub fn check_batch<ThePIR: PIR>(idxs: &[usize]) -> Result<(), RespireError> {
+    if idxs.len() > ThePIR::BATCH_SIZE {
+        return Err(RespireError::BatchTooLarge {
+            len: idxs.len(),
+            max: ThePIR::BATCH_SIZE,
+        });
+    }
+    match idxs.iter().find(|&&idx| idx >= ThePIR::NUM_RECORDS) {
+        Some(&idx) => Err(RespireError::UnknownRecord {
+            idx,
+            num_records: ThePIR::NUM_RECORDS,
+        }),
+        None => Ok(()),
+    }
+}
+
+pub fn query_batched<ThePIR: PIR>(
+    qk: &ThePIR::QueryKey,
+    idxs: &[usize],
+    db_hint: &ThePIR::DatabaseHint,
+) -> Result<BatchedQueryAndState<ThePIR, Vec<ThePIR::State>>, RespireError> {
+    let (queries, states) = idxs
+        .chunks(ThePIR::BATCH_SIZE)
+        .map(|batch| ThePIR::query(qk, batch, db_hint, None))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+    Ok((BatchedQuery { queries }, states))
+}
+
+pub fn answer_batched<ThePIR: PIR>(
+    pp: &ThePIR::PublicParams,
+    db: &ThePIR::Database,
+    q: &BatchedQuery<ThePIR::Query>,
+) -> Result<Vec<ThePIR::Response>, RespireError> {
+    q.queries
+        .iter()
+        .map(|q| ThePIR::answer(pp, db, q, None))
+        .collect()
+}
+
+/// The records of a batched query, in the order they were requested
+pub fn extract_batched<ThePIR: PIR>(
+    qk: &ThePIR::QueryKey,
+    r: &[ThePIR::Response],
+    states: &[ThePIR::State],
+) -> Result<Vec<ThePIR::RecordBytes>, RespireError> {
+    if r.len() != states.len() {
+        return Err(RespireError::WrongLength {
+            what: "batched response",
+            expected: states.len(),
+            found: r.len(),
+        });
+    }
+    let mut result = Vec::new();
+    for (r, st) in r.iter().zip(states) {
+        result.extend(ThePIR::extract(qk, r, st, None)?);
+    }
+    Ok(result)
 }
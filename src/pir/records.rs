@@ -0,0 +1,436 @@
+//! Loading database records from files, as a record generator for `PIR::encode_db`.
+//!
+//! The file is validated in a single streaming pass when it is opened, recording only the offset of
+//! each record; records are then read back one at a time while the database is encoded. So apart
+//! from the encoded database, memory use is a few bytes per record.
+//!
+//! Supported formats:
+//! - [`RecordFormat::Fixed`]: records of exactly `bytes_per_record` bytes, concatenated.
+//! - [`RecordFormat::Lines`]: one record per line (`\n` or `\r\n` terminated), zero-padded.
+//! - [`RecordFormat::Csv`]: rows `index,value`, where `value` is the rest of the row, taken
+//!   literally, or hex encoded if prefixed with `0x`. Zero-padded; indices not listed are zero.
+//!
+//! In all formats, records past the end of the file are zero.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RecordFormat {
+    Fixed,
+    Lines,
+    Csv,
+}
+
+#[derive(Debug)]
+pub enum RecordFileError {
+    Io(PathBuf, io::Error),
+    /// The file ends in the middle of a fixed-size record
+    TruncatedRecord {
+        index: usize,
+        len: usize,
+        bytes_per_record: usize,
+    },
+    RecordTooLong {
+        line: usize,
+        len: usize,
+        bytes_per_record: usize,
+    },
+    TooManyRecords {
+        num_records: usize,
+    },
+    MalformedCsv {
+        line: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for RecordFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordFileError::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            RecordFileError::TruncatedRecord {
+                index,
+                len,
+                bytes_per_record,
+            } => write!(
+                f,
+                "file ends in record {}, which has only {} of {} bytes",
+                index, len, bytes_per_record
+            ),
+            RecordFileError::RecordTooLong {
+                line,
+                len,
+                bytes_per_record,
+            } => write!(
+                f,
+                "line {}: record of {} bytes exceeds the record size of {} bytes",
+                line, len, bytes_per_record
+            ),
+            RecordFileError::TooManyRecords { num_records } => {
+                write!(f, "file holds more than {} records", num_records)
+            }
+            RecordFileError::MalformedCsv { line, reason } => {
+                write!(f, "line {}: {}", line, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for RecordFileError {}
+
+// Marks an index that does not appear in a CSV file
+const ABSENT: u64 = u64::MAX;
+
+///
+/// An opened and validated record file. Use [`RecordFile::record`] as the `encode_db` generator.
+///
+pub struct RecordFile {
+    path: PathBuf,
+    file: File,
+    format: RecordFormat,
+    bytes_per_record: usize,
+    num_records: usize,
+    // One past the last record present in the file
+    len: usize,
+    // Lines and Csv: (start offset, length) of the value of each record present in the file
+    spans: Vec<(u64, u32)>,
+}
+
+impl RecordFile {
+    ///
+    /// Open and validate `path`, which may hold at most `num_records` records of (at most)
+    /// `bytes_per_record` bytes.
+    ///
+    pub fn open(
+        path: &Path,
+        format: RecordFormat,
+        bytes_per_record: usize,
+        num_records: usize,
+    ) -> Result<Self, RecordFileError> {
+        let io_err = |e| RecordFileError::Io(path.to_owned(), e);
+        let file = File::open(path).map_err(io_err)?;
+        let (len, spans) = match format {
+            RecordFormat::Fixed => {
+                let file_len = file.metadata().map_err(io_err)?.len() as usize;
+                let len = Self::check_fixed(file_len, bytes_per_record, num_records)?;
+                (len, Vec::new())
+            }
+            RecordFormat::Lines | RecordFormat::Csv => {
+                let spans =
+                    Self::index_lines(BufReader::new(&file), format, bytes_per_record, num_records)
+                        .map_err(|e| match e {
+                            RecordFileError::Io(_, e) => io_err(e),
+                            e => e,
+                        })?;
+                (spans.len(), spans)
+            }
+        };
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+            format,
+            bytes_per_record,
+            num_records,
+            len,
+            spans,
+        })
+    }
+
+    fn check_fixed(
+        file_len: usize,
+        bytes_per_record: usize,
+        num_records: usize,
+    ) -> Result<usize, RecordFileError> {
+        let full = file_len / bytes_per_record;
+        let rem = file_len % bytes_per_record;
+        if full + (rem > 0) as usize > num_records {
+            return Err(RecordFileError::TooManyRecords { num_records });
+        }
+        if rem > 0 {
+            return Err(RecordFileError::TruncatedRecord {
+                index: full,
+                len: rem,
+                bytes_per_record,
+            });
+        }
+        Ok(full)
+    }
+
+    fn index_lines<R: BufRead>(
+        mut reader: R,
+        format: RecordFormat,
+        bytes_per_record: usize,
+        num_records: usize,
+    ) -> Result<Vec<(u64, u32)>, RecordFileError> {
+        let mut spans = Vec::new();
+        let mut line = Vec::new();
+        let mut offset = 0_u64;
+        let mut line_no = 0;
+        loop {
+            line.clear();
+            let read = reader
+                .read_until(b'\n', &mut line)
+                .map_err(|e| RecordFileError::Io(PathBuf::new(), e))?;
+            if read == 0 {
+                break;
+            }
+            line_no += 1;
+            let line_start = offset;
+            offset += read as u64;
+
+            let mut content = line.as_slice();
+            content = content.strip_suffix(b"\n").unwrap_or(content);
+            content = content.strip_suffix(b"\r").unwrap_or(content);
+
+            let (index, value_start, len) = if format == RecordFormat::Csv {
+                let (index, value_start) = Self::parse_csv_row(content, line_no)?;
+                if index >= num_records {
+                    return Err(RecordFileError::MalformedCsv {
+                        line: line_no,
+                        reason: format!("index {} out of range (< {})", index, num_records),
+                    });
+                }
+                let len = Self::csv_value_len(&content[value_start..]).ok_or_else(|| {
+                    RecordFileError::MalformedCsv {
+                        line: line_no,
+                        reason: "invalid hex value".to_owned(),
+                    }
+                })?;
+                (index, value_start, len)
+            } else {
+                (spans.len(), 0, content.len())
+            };
+
+            if index >= num_records {
+                return Err(RecordFileError::TooManyRecords { num_records });
+            }
+            if len > bytes_per_record {
+                return Err(RecordFileError::RecordTooLong {
+                    line: line_no,
+                    len,
+                    bytes_per_record,
+                });
+            }
+            if index >= spans.len() {
+                spans.resize(index + 1, (ABSENT, 0));
+            }
+            if spans[index].0 != ABSENT {
+                return Err(RecordFileError::MalformedCsv {
+                    line: line_no,
+                    reason: format!("duplicate index {}", index),
+                });
+            }
+            spans[index] = (
+                line_start + value_start as u64,
+                (content.len() - value_start) as u32,
+            );
+        }
+        Ok(spans)
+    }
+
+    // Returns the index and the offset of the value within the row
+    fn parse_csv_row(row: &[u8], line: usize) -> Result<(usize, usize), RecordFileError> {
+        let malformed = |reason: String| RecordFileError::MalformedCsv { line, reason };
+        let comma = row
+            .iter()
+            .position(|&b| b == b',')
+            .ok_or_else(|| malformed("expected index,value".to_owned()))?;
+        let index_str = String::from_utf8_lossy(&row[..comma]);
+        let index = index_str
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| malformed(format!("invalid index {:?}", index_str)))?;
+        Ok((index, comma + 1))
+    }
+
+    // Decoded length of a CSV value, or None if it is invalid hex
+    fn csv_value_len(value: &[u8]) -> Option<usize> {
+        match value.strip_prefix(b"0x") {
+            Some(hex) => {
+                if hex.len() % 2 == 0 && hex.iter().all(u8::is_ascii_hexdigit) {
+                    Some(hex.len() / 2)
+                } else {
+                    None
+                }
+            }
+            None => Some(value.len()),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of records that can be read, i.e. one past the last record present in the file.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Read record `i`, zero-padded to `bytes_per_record` bytes. Panics if the file can no longer be
+    /// read, e.g. because it was modified after it was opened.
+    ///
+    pub fn record(&self, i: usize) -> Vec<u8> {
+        assert!(i < self.num_records);
+        let mut record = vec![0_u8; self.bytes_per_record];
+        let (offset, len) = match self.format {
+            RecordFormat::Fixed => (
+                (i * self.bytes_per_record) as u64,
+                if i < self.len {
+                    self.bytes_per_record
+                } else {
+                    0
+                },
+            ),
+            RecordFormat::Lines | RecordFormat::Csv => match self.spans.get(i) {
+                Some(&(offset, len)) if offset != ABSENT => (offset, len as usize),
+                _ => (0, 0),
+            },
+        };
+
+        let mut value = vec![0_u8; len];
+        read_exact_at(&self.file, &mut value, offset).unwrap_or_else(|e| {
+            panic!(
+                "failed to read record {} from {}: {}",
+                i,
+                self.path.display(),
+                e
+            )
+        });
+        match value.strip_prefix(b"0x") {
+            Some(hex) if self.format == RecordFormat::Csv => {
+                for (byte, digits) in record.iter_mut().zip(hex.chunks(2)) {
+                    let digits = std::str::from_utf8(digits).unwrap();
+                    *byte = u8::from_str_radix(digits, 16).unwrap();
+                }
+            }
+            _ => record[..len].copy_from_slice(&value),
+        }
+        record
+    }
+}
+
+#[cfg(unix)]
+fn read_exact_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::read_exact_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+fn read_exact_at(file: &File, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+    use std::os::windows::fs::FileExt;
+    while !buf.is_empty() {
+        match file.seek_read(buf, offset)? {
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Write;
+
+    fn write_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("respire_records_{}_{}", std::process::id(), name));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_formats() {
+        let fixed = write_file("fixed", b"abcdefgh");
+        let f = RecordFile::open(&fixed, RecordFormat::Fixed, 4, 3).unwrap();
+        assert_eq!(f.len(), 2);
+        assert_eq!(f.record(1), b"efgh");
+        assert_eq!(f.record(2), vec![0; 4]);
+
+        let lines = write_file("lines", b"ab\r\n\nabcd\n");
+        let f = RecordFile::open(&lines, RecordFormat::Lines, 4, 4).unwrap();
+        assert_eq!(f.len(), 3);
+        assert_eq!(f.record(0), b"ab\0\0");
+        assert_eq!(f.record(1), vec![0; 4]);
+        assert_eq!(f.record(2), b"abcd");
+        assert_eq!(f.record(3), vec![0; 4]);
+
+        let csv = write_file("csv", b"2,a,b\n 0 ,0x0aff\n");
+        let f = RecordFile::open(&csv, RecordFormat::Csv, 4, 4).unwrap();
+        assert_eq!(f.len(), 3);
+        assert_eq!(f.record(0), b"\x0a\xff\0\0");
+        assert_eq!(f.record(1), vec![0; 4]);
+        assert_eq!(f.record(2), b"a,b\0");
+
+        for path in [fixed, lines, csv] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let cases: [(&str, RecordFormat, &[u8], usize); 9] = [
+            ("short", RecordFormat::Fixed, b"abcdef", 4),
+            ("fixed_many", RecordFormat::Fixed, b"abcdefghijklm", 3),
+            ("long", RecordFormat::Lines, b"ab\nabcde\n", 4),
+            ("lines_many", RecordFormat::Lines, b"a\nb\nc\nd\n", 3),
+            ("no_comma", RecordFormat::Csv, b"0,a\n1\n", 3),
+            ("bad_index", RecordFormat::Csv, b"x,a\n", 3),
+            ("bad_hex", RecordFormat::Csv, b"0,0xabc\n", 3),
+            ("duplicate", RecordFormat::Csv, b"1,a\n1,b\n", 3),
+            ("out_of_range", RecordFormat::Csv, b"3,a\n", 3),
+        ];
+        let mut errors = Vec::new();
+        for (name, format, contents, num_records) in cases {
+            let path = write_file(name, contents);
+            errors.push(
+                RecordFile::open(&path, format, 4, num_records)
+                    .err()
+                    .unwrap(),
+            );
+            std::fs::remove_file(path).unwrap();
+        }
+        assert!(matches!(
+            errors[0],
+            RecordFileError::TruncatedRecord {
+                index: 1,
+                len: 2,
+                ..
+            }
+        ));
+        assert!(matches!(errors[1], RecordFileError::TooManyRecords { .. }));
+        assert!(matches!(
+            errors[2],
+            RecordFileError::RecordTooLong {
+                line: 2,
+                len: 5,
+                ..
+            }
+        ));
+        assert!(matches!(errors[3], RecordFileError::TooManyRecords { .. }));
+        for e in &errors[4..] {
+            assert!(matches!(e, RecordFileError::MalformedCsv { .. }), "{}", e);
+        }
+        assert!(matches!(
+            errors[4],
+            RecordFileError::MalformedCsv { line: 2, .. }
+        ));
+
+        assert!(matches!(
+            RecordFile::open(Path::new("/nonexistent"), RecordFormat::Fixed, 4, 1),
+            Err(RecordFileError::Io(..))
+        ));
+    }
+}
@@ -1,9 +1,12 @@
 use bitvec::prelude::*;
 use itertools::Itertools;
-use log::Level::Info;
-use log::{info, log_enabled};
+use log::info;
 use std::cmp::{max, min};
+use std::collections::BTreeMap;
 use std::f64::consts::PI;
+use std::fmt;
+use std::io;
+use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
@@ -25,10 +28,18 @@ use crate::math::ring_elem::{NormedRingElement, RingElement};
 use crate::math::utils::{ceil_log, floor_log, mod_inverse, reverse_bits_fast};
 
 use crate::math::simd_utils::*;
-use crate::pir::pir::{PIRRecordBytes, Stats, PIR};
+use crate::pir::db_file::{
+    map_db_file, write_db_file, DbContents, DbFileError, DbFileHeader, DbStorage,
+};
+use crate::pir::error::RespireError;
+use crate::pir::noise::{variance_to_subgaussian_bits, NoiseSamples};
+use crate::pir::pir::{check_batch, par_map_stats, PIRRecordBytes, PIRSummary, Stats, PIR};
+use crate::pir::security::estimate_security;
 
 use rayon::prelude::*;
-use serde::{Deserialize, Serialize, Serializer};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::ops::Deref;
 
 pub struct RespireImpl<
     const Q1: u64,
@@ -68,6 +79,7 @@ pub struct RespireImpl<
 > {}
 
 #[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
 pub struct RespireParams {
     pub Q1A: u64,
     pub Q1B: u64,
@@ -144,7 +156,7 @@ impl RespireParams {
 }
 
 #[allow(non_snake_case)]
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct RespireParamsExpanded {
     pub Q1: u64,
     pub Q1A: u64,
@@ -182,6 +194,425 @@ pub struct RespireParamsExpanded {
     pub BYTES_PER_RECORD: usize,
 }
 
+impl RespireParamsExpanded {
+    ///
+    /// Bytes occupied in memory by one set of (expanded) public parameters. Unlike
+    /// `Respire::params_public_param_size`, this counts the random components, and elements take a
+    /// full word per coefficient.
+    ///
+    pub const fn public_params_memory_size(&self) -> usize {
+        // IntModCycloCRTEval<D1, Q1A, Q1B> and IntModCycloEval<D1, Q2>
+        let q_elem_size = 2 * self.D1 * 8;
+        let q2_elem_size = self.D1 * 8;
+
+        let automorph_elems =
+            floor_log(2, self.D1 as u64) * 2 * (self.T_PROJ_SHORT + self.T_PROJ_LONG);
+        let reg_to_gsw_elems = 2 * self.M_RLWE_TO_GSW;
+        let scal_to_vec_elems = self.N_VEC * (1 + self.N_VEC) * self.T_VECTORIZE;
+        let compress_elems = (1 + self.N_VEC) * self.T_COMPRESS;
+
+        (automorph_elems + reg_to_gsw_elems + scal_to_vec_elems) * q_elem_size
+            + compress_elems * q2_elem_size
+    }
+
+    // Runtime counterparts of the `Respire` constants and size estimates, for code that chooses
+    // between parameter sets (see `crate::pir::param_search`)
+
+    pub const fn pack_ratio_db(&self) -> usize {
+        self.D1 / self.D3
+    }
+
+    pub const fn pack_ratio_response(&self) -> usize {
+        self.D2 / self.D3
+    }
+
+    pub const fn packed_dim1_size(&self) -> usize {
+        1 << self.NU1
+    }
+
+    pub const fn packed_db_size(&self) -> usize {
+        1 << (self.NU1 + self.NU2)
+    }
+
+    pub const fn nu3(&self) -> usize {
+        ceil_log(2, self.pack_ratio_db() as u64)
+    }
+
+    pub const fn num_records(&self) -> usize {
+        self.packed_db_size() * self.pack_ratio_db()
+    }
+
+    pub const fn rlwe_count(&self) -> usize {
+        1 << self.NU1
+    }
+
+    pub const fn gsw_count(&self) -> usize {
+        (self.NU2 + self.nu3()) * self.T_GSW
+    }
+
+    /// Serialized size of the seeded public parameters
+    pub fn public_param_size(&self) -> usize {
+        let automorph_elems = floor_log(2, self.D1 as u64) * (self.T_PROJ_SHORT + self.T_PROJ_LONG);
+        let reg_to_gsw_elems = 2 * self.T_RLWE_TO_GSW;
+        let scal_to_vec_elems = self.N_VEC * self.T_VECTORIZE;
+        let q_elem_size = self.D1 * ceil_log(2, self.Q1) / 8;
+
+        let compress_elems = self.N_VEC * self.T_COMPRESS;
+        let q2_elem_size = self.D1 * ceil_log(2, self.Q2) / 8;
+
+        // The randomness components of the public params are derived from a seed (see
+        // SeededPublicParams), so only the other halves are counted.
+
+        info!(
+            "automorph pp: {:.3} KiB",
+            (automorph_elems * q_elem_size) as f64 / 1024_f64
+        );
+        info!(
+            "rlwe to GSW pp: {:.3} KiB",
+            (reg_to_gsw_elems * q_elem_size) as f64 / 1024_f64
+        );
+        info!(
+            "scal to vec pp: {:.3} KiB",
+            (scal_to_vec_elems * q_elem_size) as f64 / 1024_f64
+        );
+        info!(
+            "compress pp: {:.3} KiB",
+            (compress_elems * q2_elem_size) as f64 / 1024_f64
+        );
+        (automorph_elems + reg_to_gsw_elems + scal_to_vec_elems) * q_elem_size
+            + compress_elems * q2_elem_size
+            + 32
+    }
+
+    pub const fn query_one_size(&self) -> usize {
+        (self.rlwe_count() + self.gsw_count()) * ceil_log(2, self.Q1) / 8
+    }
+
+    pub const fn record_one_size(&self) -> usize {
+        self.D3 * floor_log(2, self.P) / 8
+    }
+
+    pub fn response_one_size(&self, trunc_len: usize) -> usize {
+        // Technically we can do ceil(d * (log(q2) + len * log(q1)) by packing into a single large integer.
+        // But for simplicity assume each IntMod<Q1> / IntMod<Q2> is serialized individually.
+        let log_q1 = ceil_log(2, self.Q3);
+        let log_q2 = ceil_log(2, self.Q2);
+        ((self.D2 as f64) * (log_q2 as f64 + (trunc_len as f64) * log_q1 as f64) / 8_f64).ceil()
+            as usize
+    }
+
+    pub const fn query_size(&self) -> usize {
+        self.BATCH_SIZE * self.query_one_size()
+    }
+
+    pub const fn record_size(&self) -> usize {
+        self.BATCH_SIZE * self.record_one_size()
+    }
+
+    ///
+    /// size, number of full vectors, remainder size
+    ///
+    pub fn response_info(&self) -> (usize, usize, usize) {
+        let num_ring_elem = self.BATCH_SIZE.div_ceil(self.pack_ratio_response());
+        let num_full_vecs = num_ring_elem / self.N_VEC;
+        let num_rem = num_ring_elem % self.N_VEC;
+
+        let full_vec_size = self.response_one_size(self.N_VEC);
+        let rem_vec_size = if num_rem > 0 {
+            self.response_one_size(num_rem)
+        } else {
+            0
+        };
+        (
+            num_full_vecs * full_vec_size + rem_vec_size,
+            num_full_vecs,
+            num_rem,
+        )
+    }
+
+    pub fn rate(&self) -> f64 {
+        (self.record_size() as f64) / (self.response_info().0 as f64)
+    }
+
+    ///
+    /// Estimated probability that a record fails to decode. This is the analysis behind
+    /// `Respire::params_error_rate_estimate`, evaluated at runtime so that candidate parameters can
+    /// be compared without instantiating them.
+    ///
+    #[allow(non_snake_case)]
+    pub fn error_rate_estimate(&self) -> f64 {
+        let RespireParamsExpanded { P, D3, Q3, .. } = *self;
+        let NoiseEstimate {
+            compress: e_subg,
+            compress_round: e_round,
+            ..
+        } = self.noise_estimate();
+
+        let error_rate = 2_f64
+            * (D3 as f64)
+            * f64::exp(-PI * (0.5_f64 * (Q3 / P) as f64 - e_round).powi(2) / e_subg);
+
+        info!("Error rate: 2^({})", error_rate.log2());
+        info!("***");
+
+        clamp(error_rate, 0_f64, 1_f64)
+    }
+
+    ///
+    /// The error analysis, phase by phase. Intermediate values are logged at the info level.
+    ///
+    #[allow(non_snake_case)]
+    pub fn noise_estimate(&self) -> NoiseEstimate {
+        let RespireParamsExpanded {
+            Q1,
+            D1,
+            Z_GSW,
+            T_GSW,
+            Z_PROJ_SHORT,
+            T_PROJ_SHORT,
+            Z_PROJ_LONG,
+            T_PROJ_LONG,
+            Z_RLWE_TO_GSW,
+            T_RLWE_TO_GSW,
+            Z_VECTORIZE,
+            T_VECTORIZE,
+            BATCH_SIZE,
+            N_VEC,
+            ERROR_WIDTH_MILLIONTHS,
+            ERROR_WIDTH_VEC_MILLIONTHS,
+            ERROR_WIDTH_COMPRESS_MILLIONTHS,
+            SECRET_BOUND,
+            SECRET_WIDTH_VEC_MILLIONTHS,
+            P,
+            NU2,
+            Q3,
+            Q2,
+            T_COMPRESS,
+            Z_COMPRESS,
+            ..
+        } = *self;
+        let PACKED_DIM1_SIZE = self.packed_dim1_size();
+        let PACK_RATIO_RESPONSE = self.pack_ratio_response();
+        let NU3 = self.nu3();
+
+        info!("*** Error estimates (bits) ***");
+        // We use square subgaussian widths as units
+        let error_width_sq: f64 = ((ERROR_WIDTH_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
+        let error_width_vec_sq: f64 = ((ERROR_WIDTH_VEC_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
+        let error_width_switch_sq: f64 =
+            ((ERROR_WIDTH_COMPRESS_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
+        let secret_bound_sq: f64 = (SECRET_BOUND as f64).powi(2);
+        let secret_width_vec_sq: f64 =
+            ((SECRET_WIDTH_VEC_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
+        // let secret_width_switch_sq: f64 =
+        //     ((SECRET_WIDTH_COMPRESS_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
+
+        let log_d: usize = ceil_log(2, D1 as u64);
+
+        let e_to_bits = |e: f64| -> f64 { e.log2() / 2_f64 };
+
+        let gadget_factor = |t: usize, z: u64| -> f64 {
+            assert!(z >= 2);
+
+            let z_factor = match z {
+                2 => {
+                    // With probability <= 2^(-48.421), a random binary gadget will have <= 1200 equal 1
+                    // N(log(1 - sum(binomial(2048, x) * (1/2)^2048, x, 0, 1200), 2)) = -48.4216972197488
+                    const BINARY_FACTOR: f64 = 1200_f64 / 2048_f64;
+                    BINARY_FACTOR
+                }
+                // 3 => {
+                //     // With probability <= 2^(-48.506), a random ternary gadget will have <= 1528 coefficients equal +- 1
+                //     // N(log(1 - sum(binomial(2048, x) * (2/3)^x * (1/3)^(2048-x), x, 0, 1528), 2)) = -48.5067228501629
+                //     const TERNARY_FACTOR: f64 = 1528_f64 / 2048_f64;
+                //     TERNARY_FACTOR
+                // }
+                _ => {
+                    // TODO noise: verify this factor is right
+                    // const CHERNOFF_FACTOR: f64 = 0.6_f64;
+                    const CHERNOFF_FACTOR: f64 = 1.0_f64;
+                    ((z / 2) as f64).powi(2) * CHERNOFF_FACTOR
+                }
+            };
+
+            (t as f64) * z_factor
+        };
+
+        let select_noise = |e_gsw_sq: f64, e_reg_sq: f64, depth: usize| -> f64 {
+            // m = 2t; t is absorbed into gadget_factor()
+            e_reg_sq + (depth as f64) * 2_f64 * (D1 as f64) * gadget_factor(T_GSW, Z_GSW) * e_gsw_sq
+        };
+
+        let proj_noise = |e_sq: f64, t_auto: usize, z_auto: u64, depth: usize| -> f64 {
+            let ct = (4usize.pow(depth as u32) - 1) / 3;
+            e_sq + ct as f64 * (D1 as f64) * gadget_factor(t_auto, z_auto) * error_width_sq
+        };
+
+        info!("Initial: {}", e_to_bits(error_width_sq));
+
+        // Query expansion
+        let e_reg = proj_noise(error_width_sq, T_PROJ_SHORT, Z_PROJ_SHORT, log_d);
+        info!("Query expand rlwe: {}", e_to_bits(e_reg));
+        let e_gsw_raw = proj_noise(error_width_sq, T_PROJ_LONG, Z_PROJ_LONG, log_d);
+        info!("Query expand GSW (raw): {}", e_to_bits(e_gsw_raw));
+        let e_gsw = {
+            // RLWE to GSW
+            let initial_component = (D1 as f64) * e_gsw_raw * secret_bound_sq;
+            let gadget_component =
+                // m = 2t; t is absorbed into gadget_factor()
+                2_f64 * (D1 as f64) * gadget_factor(T_RLWE_TO_GSW, Z_RLWE_TO_GSW) * error_width_sq;
+            let e_converted = initial_component + gadget_component;
+            info!(
+                "    RLWE to GSW initial component: {}",
+                e_to_bits(initial_component),
+            );
+            info!(
+                "    RLWE to GSW gadget component: {}",
+                e_to_bits(gadget_component),
+            );
+            e_converted
+        };
+        info!("Query expand GSW (converted): {}", e_to_bits(e_gsw));
+
+        // First dimension (NU1)
+        let e_firstdim = (PACKED_DIM1_SIZE as f64) * (D1 as f64) * ((P / 2) as f64).powi(2) * e_reg;
+        info!("First dimension: {}", e_to_bits(e_firstdim));
+
+        // Folding (NU2)
+        let e_fold = select_noise(e_gsw, e_firstdim, NU2);
+        info!("Fold: {}", e_to_bits(e_fold));
+
+        // Rotating (NU3)
+        let e_rot = select_noise(e_gsw, e_fold, NU3);
+        info!("Rotate select: {}", e_to_bits(e_rot));
+
+        // Proj/select (NU3) + ring packing
+        let e_proj_component = proj_noise(0_f64, T_PROJ_LONG, Z_PROJ_LONG, NU3);
+        info!(
+            "    Projection *new* error component: {}",
+            e_to_bits(e_proj_component)
+        );
+        let ring_num_records = min(BATCH_SIZE, PACK_RATIO_RESPONSE);
+        let e_pack_ring = e_rot + e_proj_component * ring_num_records as f64;
+        info!(
+            "Ring packing ({} record(s)): {}",
+            ring_num_records,
+            e_to_bits(e_pack_ring)
+        );
+
+        // Vector packing
+        let e_pack_vec = {
+            // Scalar to vector conversion
+            let e_init = e_pack_ring;
+            let vec_num_elems = min(BATCH_SIZE.div_ceil(PACK_RATIO_RESPONSE), N_VEC);
+            let e_extra = vec_num_elems as f64
+                * (D1 as f64)
+                * gadget_factor(T_VECTORIZE, Z_VECTORIZE)
+                * error_width_vec_sq;
+            info!(
+                "    Vector packing *new* error component ({} ring elem(s)): {}",
+                vec_num_elems,
+                e_to_bits(e_extra),
+            );
+            let e_full = e_init + e_extra;
+            info!("Vector packing: {}", e_to_bits(e_full));
+            e_full
+        };
+
+        let e_preswitch = e_pack_vec;
+        info!("***");
+        info!(
+            "Preswitch noise: {:.3} total bits; approx {:.3} of margin",
+            e_to_bits(e_preswitch),
+            (Q1 as f64).log2() - (P as f64).log2() - e_to_bits(e_preswitch) - 3_f64 // 3 bits = 8 widths
+        );
+        assert_eq!(Z_COMPRESS, 2);
+        let e_preswitch = 8_f64 * e_preswitch;
+
+        let e_subg_preswitch = e_preswitch * (Q3 as f64).powi(2) / (Q1 as f64).powi(2);
+        let e_subg_gadget = (Q3 as f64).powi(2) / (4_f64 * (Q2 as f64).powi(2))
+            * ((D1 as f64) * secret_width_vec_sq
+                + 4_f64
+                    * (D1 as f64)
+                    * gadget_factor(T_COMPRESS, Z_COMPRESS)
+                    * error_width_switch_sq);
+        let e_subg = e_subg_preswitch + e_subg_gadget;
+        let e_round = (2f64 + Q3 as f64 / Q1 as f64 * (Q1 % P) as f64) / 2f64;
+        let threshold = Q3 / (2 * P);
+
+        info!(
+            "Switch rounding term noise bound (absolute / threshold): {} / {}",
+            e_round, threshold
+        );
+
+        info!("Switch subgaussian term noise widths (absolute / threshold):");
+
+        info!(
+            "    preswitch: {:.3} / {}",
+            e_subg_preswitch.sqrt(),
+            threshold
+        );
+        info!("    gadget: {:.3} / {}", e_subg_gadget.sqrt(), threshold);
+        info!("    total: {:.3} / {}", e_subg.sqrt(), threshold);
+
+        NoiseEstimate {
+            expand: e_reg,
+            first_dim: e_firstdim,
+            fold: e_fold,
+            rotate: e_rot,
+            project: e_rot + e_proj_component,
+            vectorize: e_pack_vec,
+            compress: e_subg,
+            compress_round: e_round,
+        }
+    }
+}
+
+///
+/// The noise after each phase of answering a query, according to the error analysis. The noise
+/// terms are squared subgaussian widths of the noise coefficients, modulo `Q1` up to vectorization
+/// and modulo `Q3` after compression.
+///
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct NoiseEstimate {
+    /// Regev ciphertexts from query expansion
+    pub expand: f64,
+    pub first_dim: f64,
+    pub fold: f64,
+    pub rotate: f64,
+    /// A single record after projection, on the coefficients that hold it
+    pub project: f64,
+    /// After ring packing and vectorization
+    pub vectorize: f64,
+    /// The subgaussian part of the noise after compression
+    pub compress: f64,
+    /// Bound on the rounding part of the noise after compression (not squared)
+    pub compress_round: f64,
+}
+
+impl NoiseEstimate {
+    ///
+    /// The estimated variance of the noise coefficients after each phase, under the names the
+    /// measured noise is recorded with. The rounding part of the compression noise is taken to be
+    /// uniform within its bound.
+    ///
+    pub fn variances(&self) -> [(&'static str, f64); 7] {
+        let variance = |e_sq: f64| e_sq / (2_f64 * PI);
+        [
+            ("expand", variance(self.expand)),
+            ("first_dim", variance(self.first_dim)),
+            ("fold", variance(self.fold)),
+            ("rotate", variance(self.rotate)),
+            ("project", variance(self.project)),
+            ("vectorize", variance(self.vectorize)),
+            (
+                "compress",
+                variance(self.compress) + self.compress_round.powi(2) / 3_f64,
+            ),
+        ]
+    }
+}
+
 #[macro_export]
 macro_rules! respire {
     ($params: expr) => {
@@ -395,6 +826,8 @@ pub trait Respire: PIR {
     type RLWEToGSWParams;
     type CompressParams;
     type VectorizeParams;
+    type PublicParamsExpanded: Sync;
+    type PublicParamsSeeded: Serialize + for<'de> Deserialize<'de>;
     type VecRLWEEncoding;
     type VecRLWEEncodingSmallTruncated;
 
@@ -431,31 +864,82 @@ pub trait Respire: PIR {
     fn query_one(
         qk: &<Self as PIR>::QueryKey,
         idx: usize,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> <Self as Respire>::QueryOne;
     fn answer_one(
         pp: &<Self as PIR>::PublicParams,
         db: &<Self as PIR>::Database,
         q: &<Self as Respire>::QueryOne,
-        qk: Option<&<Self as PIR>::QueryKey>,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> <Self as Respire>::AnswerOne;
+    ///
+    /// `answer_one`, decrypting the ciphertext after each phase with the query key to measure the
+    /// noise into `noise_stats` (and log it at the info level). Only for validating the error
+    /// analysis, see [`PIR::answer_measured`].
+    ///
+    fn answer_one_measured(
+        pp: &<Self as PIR>::PublicParams,
+        db: &<Self as PIR>::Database,
+        q: &<Self as Respire>::QueryOne,
+        qk: &<Self as PIR>::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
     ) -> <Self as Respire>::AnswerOne;
     fn answer_compress_chunk(
         pp: &<Self as PIR>::PublicParams,
         chunk: &[<Self as Respire>::AnswerOne],
-        qk: Option<&<Self as PIR>::QueryKey>,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> <Self as Respire>::AnswerOneCompressed;
+    /// `answer_compress_chunk`, measuring the noise as `answer_one_measured` does.
+    fn answer_compress_chunk_measured(
+        pp: &<Self as PIR>::PublicParams,
+        chunk: &[<Self as Respire>::AnswerOne],
+        qk: &<Self as PIR>::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
     ) -> <Self as Respire>::AnswerOneCompressed;
     fn answer_compress_vec(
         pp: &<Self as PIR>::PublicParams,
         vec: &<Self as Respire>::VecRLWEEncoding,
         truncate_len: usize,
     ) -> <Self as Respire>::AnswerOneCompressed;
+    /// Fails if `r` has too many components or unreduced coefficients.
     fn extract_one(
         qk: &<Self as PIR>::QueryKey,
         r: &<Self as Respire>::AnswerOneCompressed,
-        //time_stats: Option<&mut Stats<Duration>>,
-    ) -> Vec<<Self as PIR>::RecordBytes>;
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Vec<<Self as PIR>::RecordBytes>, RespireError>;
+    ///
+    /// Check a query received from a client before expanding it: its vectors must have the
+    /// expected lengths and their coefficients must be reduced. (The seeds have a fixed size, so
+    /// deserialization already checks them.)
+    ///
+    fn check_query_one(q: &<Self as Respire>::QueryOne) -> Result<(), RespireError>;
+
+    fn public_params_compress(
+        pp: &<Self as PIR>::PublicParams,
+    ) -> <Self as Respire>::PublicParamsSeeded;
+    /// Returns `None` if `pp` does not have the right number of components.
+    fn public_params_expand(
+        pp: &<Self as Respire>::PublicParamsSeeded,
+    ) -> Option<<Self as PIR>::PublicParams>;
+
+    /// Number of `SimdVec`s in an encoded database
+    const ENCODED_DB_LEN: usize;
+    fn db_storage(db: &<Self as PIR>::Database) -> &DbStorage;
+    fn db_from_storage(storage: DbStorage) -> <Self as PIR>::Database;
+
+    ///
+    /// Replace the records at the given indices, re-encoding only the packed chunks that contain
+    /// them. Later updates to the same index take precedence. Nothing is changed if an index is
+    /// out of range.
+    ///
+    /// The database is copy-on-write per block (see [`DbStorage`]): only the blocks holding the
+    /// updated chunks are copied, and other holders of `db`, e.g. in-flight `answer` calls, keep
+    /// seeing the old records. A memory-mapped database stays mapped; the file is never written to.
+    ///
+    fn update_records(
+        db: &mut <Self as PIR>::Database,
+        updates: &[(usize, <Self as PIR>::RecordBytes)],
+    ) -> Result<(), RespireError>;
 
     fn params() -> RespireParamsExpanded;
     fn params_error_rate_estimate() -> f64;
@@ -465,6 +949,11 @@ pub trait Respire: PIR {
     fn params_response_one_size(trunc_len: usize) -> usize;
 }
 
+/// A batch query as sent from `RespireClient` to `RespireServer`
+pub type RespireQuery<R> = Vec<<R as Respire>::QueryOne>;
+/// A batch response as sent from `RespireServer` to `RespireClient`
+pub type RespireResponse<R> = Vec<<R as Respire>::AnswerOneCompressed>;
+
 #[repr(transparent)]
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct RecordBytesImpl<const LEN: usize> {
@@ -490,6 +979,76 @@ impl<const LEN: usize> PIRRecordBytes for RecordBytesImpl<LEN> {
     }
 }
 
+// Streams of the public parameter PRG (see `SeededPublicParams`). The low 32 bits index the key
+// within each component.
+const PP_STREAM_AUTO_SHORT: u64 = 0;
+const PP_STREAM_AUTO_LONG: u64 = 1 << 32;
+const PP_STREAM_RLWE_TO_GSW: u64 = 2 << 32;
+const PP_STREAM_VECTORIZE: u64 = 3 << 32;
+const PP_STREAM_COMPRESS: u64 = 4 << 32;
+
+///
+/// Public parameters whose uniformly random components (the `a` halves of the automorphism,
+/// RLWE-to-GSW, vectorize and compress keys) are derived from a 32 byte seed.
+///
+/// In memory, the parameters are held in expanded form, which they dereference to. They serialize
+/// to the seeded form, omitting the random components, and are expanded again on deserialization.
+///
+pub struct SeededPublicParams<R: Respire> {
+    seed: [u8; 32],
+    expanded: <R as Respire>::PublicParamsExpanded,
+}
+
+impl<R: Respire> SeededPublicParams<R> {
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+}
+
+impl<R: Respire> Deref for SeededPublicParams<R> {
+    type Target = <R as Respire>::PublicParamsExpanded;
+    fn deref(&self) -> &Self::Target {
+        &self.expanded
+    }
+}
+
+impl<R: Respire<PublicParams = Self>> Serialize for SeededPublicParams<R> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        R::public_params_compress(self).serialize(serializer)
+    }
+}
+
+impl<'de, R: Respire<PublicParams = Self>> Deserialize<'de> for SeededPublicParams<R> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let seeded = <R as Respire>::PublicParamsSeeded::deserialize(deserializer)?;
+        R::public_params_expand(&seeded)
+            .ok_or_else(|| D::Error::custom("wrong number of public parameter components"))
+    }
+}
+
+impl<R: Respire> PartialEq for SeededPublicParams<R>
+where
+    <R as Respire>::PublicParamsExpanded: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.seed == other.seed && self.expanded == other.expanded
+    }
+}
+
+impl<R: Respire> fmt::Debug for SeededPublicParams<R> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SeededPublicParams")
+            .field("seed", &self.seed)
+            .finish_non_exhaustive()
+    }
+}
+
+fn public_params_rand<T: RandUniformSampled>(seed: &[u8; 32], stream: u64) -> T {
+    let mut rng = ChaCha20Rng::from_seed(*seed);
+    rng.set_stream(stream);
+    T::rand_uniform(&mut rng)
+}
+
 respire_impl!(PIR, {
     // Associated types
     type QueryKey = (
@@ -498,15 +1057,7 @@ respire_impl!(PIR, {
         <Self as Respire>::VecEncodingSecretQ2Small,
     );
 
-    type PublicParams = (
-        (
-            Vec<<Self as Respire>::AutoParamsShort>,
-            Vec<<Self as Respire>::AutoParamsLong>,
-        ),
-        <Self as Respire>::RLWEToGSWParams,
-        <Self as Respire>::CompressParams,
-        <Self as Respire>::VectorizeParams,
-    );
+    type PublicParams = SeededPublicParams<Self>;
 
     type Query = Vec<<Self as Respire>::QueryOne>;
 
@@ -515,9 +1066,10 @@ respire_impl!(PIR, {
     /// We structure the database as `[2] x [D1 / S] x [DIM2_SIZE] x [DIM1_SIZE] x [S]` for optimal first dimension
     /// processing. The outermost pair is the first resp. second CRT projections, packed as two u32 into one u64;
     /// `S` is the SIMD lane count that we can use, i.e. 4 for AVX2.
-    type Database = Arc<Vec<SimdVec>>;
+    type Database = Arc<DbStorage>;
     type DatabaseHint = ();
-    type State = ();
+    /// Number of requested records
+    type State = usize;
 
     // Public types & constants
     type RecordBytes = RecordBytesImpl<BYTES_PER_RECORD>;
@@ -532,45 +1084,31 @@ respire_impl!(PIR, {
             Self::NUM_RECORDS,
             (Self::BYTES_PER_RECORD * Self::NUM_RECORDS) as f64 / 1024_f64 / 1024_f64,
         );
-        eprintln!("Parameters: {:#?}", Self::params());
-        eprintln!(
-            "Public param size: {:.3} KiB",
-            Self::params_public_param_size() as f64 / 1024_f64
-        );
-        eprintln!(
-            "Query size: {:.3} KiB",
-            Self::params_query_size() as f64 / 1024_f64
-        );
-
-        let (resp_size, resp_full_vecs, resp_rem) = Self::params_response_info();
-        info!(
-            "Response: {} record(s) => {} ring elem(s) => {} full vector(s), {} remainder",
-            Self::BATCH_SIZE,
-            Self::BATCH_SIZE.div_ceil(Self::PACK_RATIO_RESPONSE),
-            resp_full_vecs,
-            resp_rem
-        );
-
-        eprintln!(
-            "Response size (batch): {:.3} KiB",
-            resp_size as f64 / 1024_f64
-        );
-
-        eprintln!(
-            "Record size (batch): {:.3} KiB",
-            Self::params_record_size() as f64 / 1024_f64
-        );
-        eprintln!("Rate: {:.3}", Self::params_rate());
-
-        eprintln!(
-            "Error rate (estimated): 2^({:.3})",
-            Self::params_error_rate_estimate().log2()
-        )
+        Self::summary().print();
+    }
+
+    fn summary() -> PIRSummary {
+        let (response_size, response_full_vecs, response_rem) = Self::params_response_info();
+        PIRSummary {
+            params: Self::params(),
+            num_records: Self::NUM_RECORDS,
+            bytes_per_record: Self::BYTES_PER_RECORD,
+            batch_size: Self::BATCH_SIZE,
+            public_param_size: Self::params_public_param_size(),
+            query_size: Self::params_query_size(),
+            response_size,
+            response_full_vecs,
+            response_rem,
+            record_size: Self::params_record_size(),
+            rate: Self::params_rate(),
+            error_rate_log2: Self::params_error_rate_estimate().log2(),
+            security: estimate_security(&Self::params()),
+        }
     }
 
     fn encode_db<F: Fn(usize) -> Self::RecordBytes>(
         records_generator: F,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> (Self::Database, Self::DatabaseHint) {
         let begin = Instant::now();
         let records_encoded_generator = |idx: usize| Self::encode_record(&records_generator(idx));
@@ -588,16 +1126,7 @@ respire_impl!(PIR, {
                     record_packed.coeff[Self::PACK_RATIO_DB * coeff_idx + record_in_chunk] = *coeff;
                 }
             }
-            let value = <Self as Respire>::RingQ1Fast::from(&record_packed.include_into::<Q1>());
-            let mut packed_value = [0u64; D1];
-            for i in 0..D1 {
-                packed_value[i] = {
-                    let lo = u64::from(value.proj1.evals[i]);
-                    let hi = u64::from(value.proj2.evals[i]);
-                    (hi << 32) | lo
-                };
-            }
-            packed_value
+            Self::db_chunk_encode(&record_packed.include_into::<Q1>())
         };
 
         // Quick and dirty buffered iterator implementation
@@ -714,15 +1243,16 @@ respire_impl!(PIR, {
         }
 
         let end = Instant::now();
-        /* if let Some(time_stats) = time_stats {
+        if let Some(time_stats) = time_stats {
             time_stats.add("encode", end - begin);
-        }*/
+        }
 
         info!("Done processing DB");
-        (Arc::new(db), ())
+        (Arc::new(DbStorage::new(db, Self::PACKED_DIM1_SIZE)), ())
     }
 
-    fn setup(// time_stats: Option<&mut Stats<Duration>>,
+    fn setup(
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> (<Self as PIR>::QueryKey, <Self as PIR>::PublicParams) {
         let begin = Instant::now();
         // RLWE/GSW secret
@@ -754,94 +1284,198 @@ respire_impl!(PIR, {
         });
         let s_small_q2 =
             s_small.map_ring(|r| IntModCycloEval::from(IntModCyclo::from(r).include_dim()));
-        let compress_params = Self::compress_setup(&s_vec_q2, &s_small_q2);
 
-        let mut auto_params_short: Vec<<Self as Respire>::AutoParamsShort> =
-            Vec::with_capacity(Self::RLWE_EXPAND_ITERS);
+        // Only the non-random halves of the keys are generated here; the random halves are
+        // regenerated from the seed by public_params_expand
+        let seed: [u8; 32] = ChaCha20Rng::from_entropy().gen();
+
+        let compress_bottom = Self::compress_setup_bottom(
+            &s_vec_q2,
+            &s_small_q2,
+            &public_params_rand(&seed, PP_STREAM_COMPRESS),
+        );
+
+        let mut auto_bottoms_short = Vec::with_capacity(Self::RLWE_EXPAND_ITERS);
         for i in 0..floor_log(2, D1 as u64) {
             let tau_power = (D1 >> i) + 1;
-            auto_params_short.push(Self::auto_setup::<T_PROJ_SHORT, Z_PROJ_SHORT>(
-                tau_power, &s_encode,
+            auto_bottoms_short.push(Self::auto_setup_bottom::<T_PROJ_SHORT, Z_PROJ_SHORT>(
+                tau_power,
+                &s_encode,
+                &public_params_rand(&seed, PP_STREAM_AUTO_SHORT + i as u64),
             ));
         }
-        let mut auto_params_long: Vec<<Self as Respire>::AutoParamsLong> =
-            Vec::with_capacity(Self::GSW_EXPAND_ITERS);
+        let mut auto_bottoms_long = Vec::with_capacity(Self::GSW_EXPAND_ITERS);
         for i in 0..floor_log(2, D1 as u64) {
             let tau_power = (D1 >> i) + 1;
-            auto_params_long.push(Self::auto_setup::<T_PROJ_LONG, Z_PROJ_LONG>(
-                tau_power, &s_encode,
+            auto_bottoms_long.push(Self::auto_setup_bottom::<T_PROJ_LONG, Z_PROJ_LONG>(
+                tau_power,
+                &s_encode,
+                &public_params_rand(&seed, PP_STREAM_AUTO_LONG + i as u64),
             ));
         }
 
-        let rlwe_to_gsw_params = Self::rlwe_to_gsw_setup(&s_encode);
-        let vectorize_params = Self::vectorize_setup(&s_encode, &s_vec);
+        let rlwe_to_gsw_bottom = Self::rlwe_to_gsw_setup_bottom(
+            &s_encode,
+            &public_params_rand(&seed, PP_STREAM_RLWE_TO_GSW),
+        );
+        let vectorize_bottoms = (0..N_VEC)
+            .map(|i| {
+                Self::vectorize_setup_bottom(
+                    &s_encode,
+                    &s_vec,
+                    i,
+                    &public_params_rand(&seed, PP_STREAM_VECTORIZE + i as u64),
+                )
+            })
+            .collect_vec();
+
+        let pp = Self::public_params_expand(&(
+            seed,
+            (auto_bottoms_short, auto_bottoms_long),
+            rlwe_to_gsw_bottom,
+            compress_bottom,
+            vectorize_bottoms,
+        ))
+        .unwrap();
 
         let end = Instant::now();
-        /*if let Some(time_stats) = time_stats {
+        if let Some(time_stats) = time_stats {
             time_stats.add("setup", end - begin);
-        }*/
+        }
 
-        (
-            (s_encode, s_vec, s_small),
-            (
-                (auto_params_short, auto_params_long),
-                rlwe_to_gsw_params,
-                compress_params,
-                vectorize_params,
-            ),
-        )
+        ((s_encode, s_vec, s_small), pp)
     }
 
     fn query(
         qk: &<Self as PIR>::QueryKey,
         indices: &[usize],
         _: &<Self as PIR>::DatabaseHint,
-        //mut time_stats: Option<&mut Stats<Duration>>,
-    ) -> (<Self as PIR>::Query, <Self as PIR>::State) {
-        assert_eq!(indices.len(), Self::BATCH_SIZE);
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(<Self as PIR>::Query, <Self as PIR>::State), RespireError> {
+        check_batch::<Self>(indices)?;
+        // The queried index is encrypted, so any index works as a dummy
+        let q = (0..Self::BATCH_SIZE)
+            .map(|i| {
+                Self::query_one(
+                    qk,
+                    indices.get(i).copied().unwrap_or(0),
+                    time_stats.as_deref_mut(),
+                )
+            })
+            .collect_vec();
+        Ok((q, indices.len()))
+    }
+
+    fn query_truncated(
+        qk: &<Self as PIR>::QueryKey,
+        indices: &[usize],
+        _: &<Self as PIR>::DatabaseHint,
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<(<Self as PIR>::Query, <Self as PIR>::State), RespireError> {
+        check_batch::<Self>(indices)?;
         let q = indices
             .iter()
-            .copied()
-            .map(|idx| Self::query_one(qk, idx)) //time_stats.as_deref_mut()))
+            .map(|&idx| Self::query_one(qk, idx, time_stats.as_deref_mut()))
             .collect_vec();
-        (q, ())
+        Ok((q, indices.len()))
     }
 
     fn answer(
         pp: &<Self as PIR>::PublicParams,
         db: &<Self as PIR>::Database,
         qs: &<Self as PIR>::Query,
-        qk: Option<&<Self as PIR>::QueryKey>,
-        //mut time_stats: Option<&mut Stats<Duration>>,
-    ) -> <Self as PIR>::Response {
-        assert_eq!(qs.len(), Self::BATCH_SIZE);
-        let answers: Vec<<Self as Respire>::AnswerOne> = qs
-            .par_iter()
-            .map(|q| Self::answer_one(pp, db, q, qk)) // time_stats.as_deref_mut()))
-            .collect(); //.collect_vec();
-        let answers_compressed = answers
-            .chunks(N_VEC * Self::PACK_RATIO_RESPONSE)
-            .map(|chunk| Self::answer_compress_chunk(pp, chunk, qk)) // time_stats.as_deref_mut()))
-            .collect_vec();
-        answers_compressed
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<<Self as PIR>::Response, RespireError> {
+        Self::check_query(qs)?;
+        let answers = par_map_stats(
+            qs.par_iter(),
+            time_stats.as_deref_mut(),
+            None,
+            |q, times, _| Self::answer_one(pp, db, q, times),
+        );
+        Ok(answers
+            .chunks(Self::RESPONSE_CHUNK_SIZE)
+            .map(|chunk| Self::answer_compress_chunk(pp, chunk, time_stats.as_deref_mut()))
+            .collect_vec())
+    }
+
+    fn answer_measured(
+        pp: &<Self as PIR>::PublicParams,
+        db: &<Self as PIR>::Database,
+        qs: &<Self as PIR>::Query,
+        qk: &<Self as PIR>::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
+    ) -> Result<<Self as PIR>::Response, RespireError> {
+        Self::check_query(qs)?;
+        let answers = par_map_stats(
+            qs.par_iter(),
+            None,
+            Some(&mut *noise_stats),
+            |q, _, noise| Self::answer_one_measured(pp, db, q, qk, noise.unwrap()),
+        );
+        Ok(answers
+            .chunks(Self::RESPONSE_CHUNK_SIZE)
+            .map(|chunk| Self::answer_compress_chunk_measured(pp, chunk, qk, noise_stats))
+            .collect_vec())
     }
 
     fn extract(
         qk: &Self::QueryKey,
         r: &Self::Response,
-        _: &Self::State,
-        //mut time_stats: Option<&mut Stats<Duration>>,
-    ) -> Vec<Self::RecordBytes> {
-        let mut result = Vec::with_capacity(Self::BATCH_SIZE);
+        num_records: &Self::State,
+        mut time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Vec<Self::RecordBytes>, RespireError> {
+        let mut result = Vec::with_capacity(*num_records);
         for r_one in r {
-            let extracted = Self::extract_one(qk, r_one); //time_stats.as_deref_mut());
+            let extracted = Self::extract_one(qk, r_one, time_stats.as_deref_mut())?;
             for record in extracted {
-                if result.len() < Self::BATCH_SIZE {
+                if result.len() < *num_records {
                     result.push(record);
                 }
             }
         }
-        result
+        if result.len() < *num_records {
+            return Err(RespireError::WrongLength {
+                what: "response",
+                expected: *num_records,
+                found: result.len(),
+            });
+        }
+        Ok(result)
+    }
+
+    fn save_db(
+        db: &<Self as PIR>::Database,
+        _: &<Self as PIR>::DatabaseHint,
+        contents: &DbContents,
+        path: &Path,
+    ) -> io::Result<()> {
+        let header = DbFileHeader::new(
+            Self::NUM_RECORDS,
+            Self::BATCH_SIZE,
+            1,
+            Self::ENCODED_DB_LEN,
+            contents.clone(),
+            bincode::serialize(&()).unwrap(),
+        );
+        write_db_file(path, &Self::params(), &header, &[db.as_ref()])
+    }
+
+    fn load_db(
+        path: &Path,
+        contents: &DbContents,
+    ) -> Result<(<Self as PIR>::Database, <Self as PIR>::DatabaseHint), DbFileError> {
+        let expected = DbFileHeader::new(
+            Self::NUM_RECORDS,
+            Self::BATCH_SIZE,
+            1,
+            Self::ENCODED_DB_LEN,
+            contents.clone(),
+            Vec::new(),
+        );
+        let (mut segments, _) =
+            map_db_file(path, &Self::params(), &expected, Self::PACKED_DIM1_SIZE)?;
+        Ok((Arc::new(segments.remove(0)), ()))
     }
 });
 
@@ -870,6 +1504,24 @@ respire_impl!(Respire, {
         Matrix<1, T_VECTORIZE, Self::RingQ1Fast>,
         Matrix<N_VEC, T_VECTORIZE, Self::RingQ1Fast>,
     )>;
+    type PublicParamsExpanded = (
+        (Vec<Self::AutoParamsShort>, Vec<Self::AutoParamsLong>),
+        Self::RLWEToGSWParams,
+        Self::CompressParams,
+        Self::VectorizeParams,
+    );
+    /// The seed, followed by the non-random halves of the keys, in the same order as
+    /// `PublicParamsExpanded`
+    type PublicParamsSeeded = (
+        [u8; 32],
+        (
+            Vec<Matrix<1, T_PROJ_SHORT, Self::RingQ1Fast>>,
+            Vec<Matrix<1, T_PROJ_LONG, Self::RingQ1Fast>>,
+        ),
+        Matrix<1, M_RLWE_TO_GSW, Self::RingQ1Fast>,
+        Matrix<N_VEC, T_COMPRESS, IntModCycloEval<D1, Q2>>,
+        Vec<Matrix<N_VEC, T_VECTORIZE, Self::RingQ1Fast>>,
+    );
     type VecRLWEEncoding = (Self::RingQ1Fast, Matrix<N_VEC, 1, Self::RingQ1Fast>);
     type VecRLWEEncodingSmallTruncated = (
         IntModCyclo<D2, Q2>,
@@ -914,11 +1566,67 @@ respire_impl!(Respire, {
 
     const GSW_COUNT: usize = (Self::GSW_FOLD_COUNT + Self::GSW_ROT_COUNT) * T_GSW;
     const GSW_EXPAND_ITERS: usize = ceil_log(2, Self::GSW_COUNT as u64);
+    const ENCODED_DB_LEN: usize = (D1 / SIMD_LANES) * Self::PACKED_DB_SIZE;
+
+    fn db_storage(db: &<Self as PIR>::Database) -> &DbStorage {
+        db
+    }
+
+    fn db_from_storage(storage: DbStorage) -> <Self as PIR>::Database {
+        assert_eq!(storage.len(), Self::ENCODED_DB_LEN);
+        assert_eq!(storage.block_len(), Self::PACKED_DIM1_SIZE);
+        Arc::new(storage)
+    }
+
+    fn update_records(
+        db: &mut <Self as PIR>::Database,
+        updates: &[(usize, <Self as PIR>::RecordBytes)],
+    ) -> Result<(), RespireError> {
+        let mut chunks: BTreeMap<usize, Vec<(usize, &<Self as PIR>::RecordBytes)>> =
+            BTreeMap::new();
+        for (idx, record) in updates {
+            if *idx >= Self::DB_SIZE {
+                return Err(RespireError::UnknownRecord {
+                    idx: *idx,
+                    num_records: Self::DB_SIZE,
+                });
+            }
+            chunks
+                .entry(idx / Self::PACK_RATIO_DB)
+                .or_default()
+                .push((idx % Self::PACK_RATIO_DB, record));
+        }
+
+        let storage: &DbStorage = db;
+        let packed = chunks
+            .into_par_iter()
+            .map(|(chunk_idx, records)| {
+                let mut value = Self::db_chunk_decode(storage, chunk_idx);
+                for (record_in_chunk, record) in records {
+                    let encoded = Self::encode_record(record);
+                    for (coeff_idx, coeff) in encoded.coeff.iter().enumerate() {
+                        value.coeff[Self::PACK_RATIO_DB * coeff_idx + record_in_chunk] =
+                            coeff.include_into();
+                    }
+                }
+                (chunk_idx, Self::db_chunk_encode(&value))
+            })
+            .collect::<Vec<_>>();
+
+        let storage = Arc::make_mut(db);
+        for (chunk_idx, packed_value) in packed {
+            for (eval_idx, eval) in packed_value.into_iter().enumerate() {
+                let (block_idx, lane) = Self::db_eval_position(chunk_idx, eval_idx);
+                simd_lanes_mut(storage.block_mut(block_idx))[lane] = eval;
+            }
+        }
+        Ok(())
+    }
 
     fn query_one(
         (s_encode, _, _): &<Self as PIR>::QueryKey,
         idx: usize,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> <Self as Respire>::QueryOne {
         let begin = Instant::now();
         assert!(idx < Self::DB_SIZE);
@@ -972,9 +1680,9 @@ respire_impl!(Respire, {
         );
 
         let end = Instant::now();
-        /*if let Some(time_stats) = time_stats {
+        if let Some(time_stats) = time_stats {
             time_stats.add("query", end - begin);
-        }*/
+        }
         (compressed_rlwe, compressed_gsw)
     }
 
@@ -982,18 +1690,16 @@ respire_impl!(Respire, {
         pp: &<Self as PIR>::PublicParams,
         db: &<Self as PIR>::Database,
         q: &<Self as Respire>::QueryOne,
-        qk: Option<&<Self as PIR>::QueryKey>,
-        //    mut time_stats: Option<&mut Stats<Duration>>,
+        mut time_stats: Option<&mut Stats<Duration>>,
     ) -> <Self as Respire>::AnswerOne {
         // Query expansion
-        let (rlwes, gsws_fold, gsws_rot) = Self::answer_query_unpack(pp, q, qk); //time_stats.as_deref_mut());
-        let rlwe_saved = rlwes[0].clone();
+        let (rlwes, gsws_fold, gsws_rot) =
+            Self::answer_query_unpack(pp, q, time_stats.as_deref_mut());
 
         let i1 = Instant::now();
 
         // First dimension
         let c_firstdim = Self::answer_first_dim(db, &rlwes);
-        let firstdim_saved = c_firstdim[0].clone(); // save for noise logging
         let i2 = Instant::now();
 
         // Folding
@@ -1008,76 +1714,94 @@ respire_impl!(Respire, {
         let c_proj = Self::answer_project(pp, &c_rot);
         let i5 = Instant::now();
 
-        /*
         if let Some(time_stats) = time_stats {
             time_stats.add("answer_first_dim", i2 - i1);
             time_stats.add("answer_fold", i3 - i2);
             time_stats.add("answer_rotate", i4 - i3);
             time_stats.add("answer_project", i5 - i4);
-        }*/
-
-        if let Some((s_enc, _, _)) = qk {
-            if log_enabled!(Info) {
-                let e_rlwe = Self::noise_subgaussian_bits(s_enc, &rlwe_saved);
-                let e_firstdim = Self::noise_subgaussian_bits(s_enc, &firstdim_saved);
-                let e_fold = Self::noise_subgaussian_bits(s_enc, &c_fold);
-                let e_rot = Self::noise_subgaussian_bits(s_enc, &c_rot);
-                let e_proj = Self::noise_subgaussian_bits(s_enc, &c_proj);
-
-                info!("measured noise query expanded rlwe: {}", e_rlwe);
-                info!("measured noise first dim: {}", e_firstdim);
-                info!("measured noise fold: {}", e_fold);
-                info!("measured noise rotate: {}", e_rot);
-                // TODO: note that project noise is lower on the coefficients that are projected away. So reporting this average is a bit inaccurate.
-                info!("measured noise project*: {}", e_proj);
-            }
         }
+        c_proj
+    }
 
+    fn answer_one_measured(
+        pp: &<Self as PIR>::PublicParams,
+        db: &<Self as PIR>::Database,
+        q: &<Self as Respire>::QueryOne,
+        (s_enc, _, _): &<Self as PIR>::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
+    ) -> <Self as Respire>::AnswerOne {
+        let (rlwes, gsws_fold, gsws_rot) = Self::answer_query_unpack(pp, q, None);
+        let c_firstdim = Self::answer_first_dim(db, &rlwes);
+        let firstdim_saved = c_firstdim.clone();
+        let c_fold = Self::answer_fold(c_firstdim, gsws_fold.as_slice());
+        let c_rot = Self::answer_rotate(&c_fold, gsws_rot.as_slice());
+        let c_proj = Self::answer_project(pp, &c_rot);
+
+        let sum_noise = |cts: &[<Self as Respire>::RLWEEncoding]| {
+            let mut samples = NoiseSamples::default();
+            for c in cts {
+                samples += Self::noise_samples(s_enc, c, 1);
+            }
+            samples
+        };
+        // Projection leaves only key switching noise on the coefficients it zeroes out, so
+        // measure the ones holding the record
+        let phases = [
+            ("expand", sum_noise(&rlwes)),
+            ("first_dim", sum_noise(&firstdim_saved)),
+            ("fold", Self::noise_samples(s_enc, &c_fold, 1)),
+            ("rotate", Self::noise_samples(s_enc, &c_rot, 1)),
+            (
+                "project",
+                Self::noise_samples(s_enc, &c_proj, 1 << Self::NU3),
+            ),
+        ];
+        Self::record_noise(phases, Some(noise_stats));
         c_proj
     }
 
     fn answer_compress_chunk(
         pp: &<Self as PIR>::PublicParams,
         chunk: &[<Self as Respire>::AnswerOne],
-        qk: Option<&<Self as PIR>::QueryKey>,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> <Self as Respire>::AnswerOneCompressed {
         let begin = Instant::now();
-        let mut scalar_cts = Vec::with_capacity(Self::RESPONSE_CHUNK_SIZE);
-        let (_, _, _, vectorize_params) = pp;
-        for vec_idx in 0..N_VEC {
-            let mut scalar_ct = Matrix::zero();
-            for pack_idx in 0..Self::PACK_RATIO_RESPONSE {
-                let idx = vec_idx * Self::PACK_RATIO_RESPONSE + pack_idx;
-                if idx < chunk.len() {
-                    scalar_ct += &Self::rlwe_mul_x_pow(&chunk[idx], pack_idx * (D1 / D2));
-                }
-            }
-            scalar_cts.push(scalar_ct)
-        }
-        let vec = Self::scal_to_vec(vectorize_params, scalar_cts.as_slice().try_into().unwrap());
-        let compressed =
-            Self::answer_compress_vec(pp, &vec, chunk.len().div_ceil(Self::PACK_RATIO_RESPONSE));
+        let (vec, truncate_len) = Self::answer_vectorize_chunk(pp, chunk);
+        let compressed = Self::answer_compress_vec(pp, &vec, truncate_len);
 
         let end = Instant::now();
-        /* if let Some(time_stats) = time_stats {
+        if let Some(time_stats) = time_stats {
             time_stats.add("answer_compress", end - begin);
-        }*/
-
-        if let Some((_, s_vec, _)) = qk {
-            info!(
-                "pre compression noise (subgaussian widths): 2^({})",
-                Self::noise_subgaussian_bits_vec(s_vec, &vec)
-            );
         }
         compressed
     }
 
+    fn answer_compress_chunk_measured(
+        pp: &<Self as PIR>::PublicParams,
+        chunk: &[<Self as Respire>::AnswerOne],
+        qk: &<Self as PIR>::QueryKey,
+        noise_stats: &mut Stats<NoiseSamples>,
+    ) -> <Self as Respire>::AnswerOneCompressed {
+        let (vec, truncate_len) = Self::answer_vectorize_chunk(pp, chunk);
+        let compressed = Self::answer_compress_vec(pp, &vec, truncate_len);
+        let (_, s_vec, _) = qk;
+        let phases = [
+            (
+                "vectorize",
+                Self::noise_samples_vec(s_vec, &vec, truncate_len),
+            ),
+            ("compress", Self::noise_samples_compressed(qk, &compressed)),
+        ];
+        Self::record_noise(phases, Some(noise_stats));
+        compressed
+    }
+
     fn answer_compress_vec(
-        (_, _, (a_t, b_mat), _): &<Self as PIR>::PublicParams,
+        pp: &<Self as PIR>::PublicParams,
         (c_r, c_m): &<Self as Respire>::VecRLWEEncoding,
         truncate_len: usize,
     ) -> <Self as Respire>::AnswerOneCompressed {
+        let (_, _, (a_t, b_mat), _) = &**pp;
         let c_r = IntModCyclo::<D1, Q1>::from(c_r);
         let c_m = c_m.map_ring(|r| IntModCyclo::<D1, Q1>::from(r));
         let mut cr_scaled = IntModCyclo::zero();
@@ -1115,287 +1839,230 @@ respire_impl!(Respire, {
     fn extract_one(
         qk: &<Self as PIR>::QueryKey,
         r: &<Self as Respire>::AnswerOneCompressed,
-        //time_stats: Option<&mut Stats<Duration>>,
-    ) -> Vec<<Self as PIR>::RecordBytes> {
-        let begin = Instant::now();
-        let ret = Self::extract_bytes_one(&Self::extract_ring_one(qk, r));
-        let end = Instant::now();
-        /*if let Some(time_stats) = time_stats {
-            time_stats.add("extract", end - begin);
-        }*/
-        ret
-    }
-
-    fn params() -> RespireParamsExpanded {
-        RespireParamsExpanded {
-            Q1,
-            Q1A,
-            Q1B,
-            D1,
-            Z_GSW,
-            T_GSW,
-            M_GSW,
-            Z_PROJ_SHORT,
-            T_PROJ_SHORT,
-            Z_PROJ_LONG,
-            T_PROJ_LONG,
-            Z_RLWE_TO_GSW,
-            T_RLWE_TO_GSW,
-            Z_VECTORIZE,
-            T_VECTORIZE,
-            BATCH_SIZE,
-            N_VEC,
-            M_RLWE_TO_GSW,
-            ERROR_WIDTH_MILLIONTHS,
-            ERROR_WIDTH_VEC_MILLIONTHS,
-            ERROR_WIDTH_COMPRESS_MILLIONTHS,
-            SECRET_BOUND,
-            SECRET_WIDTH_VEC_MILLIONTHS,
-            SECRET_WIDTH_COMPRESS_MILLIONTHS,
-            P,
-            D3,
-            NU1,
-            NU2,
-            Q3,
-            Q2,
-            D2,
-            T_COMPRESS,
-            Z_COMPRESS,
-            BYTES_PER_RECORD,
+        time_stats: Option<&mut Stats<Duration>>,
+    ) -> Result<Vec<<Self as PIR>::RecordBytes>, RespireError> {
+        let (c_r_hat, c_m_hat_trunc) = r;
+        if c_m_hat_trunc.len() > N_VEC {
+            return Err(RespireError::WrongLength {
+                what: "response vector",
+                expected: N_VEC,
+                found: c_m_hat_trunc.len(),
+            });
+        }
+        check_reduced("response", &c_r_hat.coeff)?;
+        for c_m in c_m_hat_trunc {
+            check_reduced("response", &c_m.coeff)?;
         }
-    }
-
-    fn params_error_rate_estimate() -> f64 {
-        info!("*** Error estimates (bits) ***");
-        // We use square subgaussian widths as units
-        let error_width_sq: f64 = ((ERROR_WIDTH_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
-        let error_width_vec_sq: f64 = ((ERROR_WIDTH_VEC_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
-        let error_width_switch_sq: f64 =
-            ((ERROR_WIDTH_COMPRESS_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
-        let secret_bound_sq: f64 = (SECRET_BOUND as f64).powi(2);
-        let secret_width_vec_sq: f64 =
-            ((SECRET_WIDTH_VEC_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
-        // let secret_width_switch_sq: f64 =
-        //     ((SECRET_WIDTH_COMPRESS_MILLIONTHS as f64) / 1_000_000_f64).powi(2);
-
-        let log_d: usize = ceil_log(2, D1 as u64);
-
-        let e_to_bits = |e: f64| -> f64 { e.log2() / 2_f64 };
-
-        let gadget_factor = |t: usize, z: u64| -> f64 {
-            assert!(z >= 2);
-
-            let z_factor = match z {
-                2 => {
-                    // With probability <= 2^(-48.421), a random binary gadget will have <= 1200 equal 1
-                    // N(log(1 - sum(binomial(2048, x) * (1/2)^2048, x, 0, 1200), 2)) = -48.4216972197488
-                    const BINARY_FACTOR: f64 = 1200_f64 / 2048_f64;
-                    BINARY_FACTOR
-                }
-                // 3 => {
-                //     // With probability <= 2^(-48.506), a random ternary gadget will have <= 1528 coefficients equal +- 1
-                //     // N(log(1 - sum(binomial(2048, x) * (2/3)^x * (1/3)^(2048-x), x, 0, 1528), 2)) = -48.5067228501629
-                //     const TERNARY_FACTOR: f64 = 1528_f64 / 2048_f64;
-                //     TERNARY_FACTOR
-                // }
-                _ => {
-                    // TODO noise: verify this factor is right
-                    // const CHERNOFF_FACTOR: f64 = 0.6_f64;
-                    const CHERNOFF_FACTOR: f64 = 1.0_f64;
-                    ((z / 2) as f64).powi(2) * CHERNOFF_FACTOR
-                }
-            };
-
-            (t as f64) * z_factor
-        };
-
-        let select_noise = |e_gsw_sq: f64, e_reg_sq: f64, depth: usize| -> f64 {
-            // m = 2t; t is absorbed into gadget_factor()
-            e_reg_sq + (depth as f64) * 2_f64 * (D1 as f64) * gadget_factor(T_GSW, Z_GSW) * e_gsw_sq
-        };
-
-        let proj_noise = |e_sq: f64, t_auto: usize, z_auto: u64, depth: usize| -> f64 {
-            let ct = (4usize.pow(depth as u32) - 1) / 3;
-            e_sq + ct as f64 * (D1 as f64) * gadget_factor(t_auto, z_auto) * error_width_sq
-        };
-
-        info!("Initial: {}", e_to_bits(error_width_sq));
-
-        // Query expansion
-        let e_reg = proj_noise(error_width_sq, T_PROJ_SHORT, Z_PROJ_SHORT, log_d);
-        info!("Query expand rlwe: {}", e_to_bits(e_reg));
-        let e_gsw_raw = proj_noise(error_width_sq, T_PROJ_LONG, Z_PROJ_LONG, log_d);
-        info!("Query expand GSW (raw): {}", e_to_bits(e_gsw_raw));
-        let e_gsw = {
-            // RLWE to GSW
-            let initial_component = (D1 as f64) * e_gsw_raw * secret_bound_sq;
-            let gadget_component =
-                // m = 2t; t is absorbed into gadget_factor()
-                2_f64 * (D1 as f64) * gadget_factor(T_RLWE_TO_GSW, Z_RLWE_TO_GSW) * error_width_sq;
-            let e_converted = initial_component + gadget_component;
-            info!(
-                "    RLWE to GSW initial component: {}",
-                e_to_bits(initial_component),
-            );
-            info!(
-                "    RLWE to GSW gadget component: {}",
-                e_to_bits(gadget_component),
-            );
-            e_converted
-        };
-        info!("Query expand GSW (converted): {}", e_to_bits(e_gsw));
-
-        // First dimension (NU1)
-        let e_firstdim =
-            (Self::PACKED_DIM1_SIZE as f64) * (D1 as f64) * ((P / 2) as f64).powi(2) * e_reg;
-        info!("First dimension: {}", e_to_bits(e_firstdim));
-
-        // Folding (NU2)
-        let e_fold = select_noise(e_gsw, e_firstdim, Self::NU2);
-        info!("Fold: {}", e_to_bits(e_fold));
-
-        // Rotating (NU3)
-        let e_rot = select_noise(e_gsw, e_fold, Self::NU3);
-        info!("Rotate select: {}", e_to_bits(e_rot));
-
-        // Proj/select (NU3) + ring packing
-        let e_proj_component = proj_noise(0_f64, T_PROJ_LONG, Z_PROJ_LONG, Self::NU3);
-        info!(
-            "    Projection *new* error component: {}",
-            e_to_bits(e_proj_component)
-        );
-        let ring_num_records = min(Self::BATCH_SIZE, Self::PACK_RATIO_RESPONSE);
-        let e_pack_ring = e_rot + e_proj_component * ring_num_records as f64;
-        info!(
-            "Ring packing ({} record(s)): {}",
-            ring_num_records,
-            e_to_bits(e_pack_ring)
-        );
-
-        // Vector packing
-        let e_pack_vec = {
-            // Scalar to vector conversion
-            let e_init = e_pack_ring;
-            let vec_num_elems = min(
-                Self::BATCH_SIZE.div_ceil(Self::PACK_RATIO_RESPONSE),
-                Self::N_VEC,
-            );
-            let e_extra = vec_num_elems as f64
-                * (D1 as f64)
-                * gadget_factor(T_VECTORIZE, Z_VECTORIZE)
-                * error_width_vec_sq;
-            info!(
-                "    Vector packing *new* error component ({} ring elem(s)): {}",
-                vec_num_elems,
-                e_to_bits(e_extra),
-            );
-            let e_full = e_init + e_extra;
-            info!("Vector packing: {}", e_to_bits(e_full));
-            e_full
-        };
-
-        let e_preswitch = e_pack_vec;
-        info!("***");
-        info!(
-            "Preswitch noise: {:.3} total bits; approx {:.3} of margin",
-            e_to_bits(e_preswitch),
-            (Q1 as f64).log2() - (P as f64).log2() - e_to_bits(e_preswitch) - 3_f64 // 3 bits = 8 widths
-        );
-        assert_eq!(Z_COMPRESS, 2);
-        let e_preswitch = 8_f64 * e_preswitch;
 
-        let e_subg_preswitch = e_preswitch * (Q3 as f64).powi(2) / (Q1 as f64).powi(2);
-        let e_subg_gadget = (Q3 as f64).powi(2) / (4_f64 * (Q2 as f64).powi(2))
-            * ((D1 as f64) * secret_width_vec_sq
-                + 4_f64
-                    * (D1 as f64)
-                    * gadget_factor(T_COMPRESS, Z_COMPRESS)
-                    * error_width_switch_sq);
-        let e_subg = e_subg_preswitch + e_subg_gadget;
-        let e_round = (2f64 + Q3 as f64 / Q1 as f64 * (Q1 % P) as f64) / 2f64;
-        let threshold = Q3 / (2 * P);
+        let begin = Instant::now();
+        let ret = Self::extract_bytes_one(&Self::extract_ring_one(qk, r));
+        let end = Instant::now();
+        if let Some(time_stats) = time_stats {
+            time_stats.add("extract", end - begin);
+        }
+        Ok(ret)
+    }
+
+    fn check_query_one(
+        ((_, vec_reg), (_, vec_gsw)): &<Self as Respire>::QueryOne,
+    ) -> Result<(), RespireError> {
+        for (what, vec, expected) in [
+            ("query RLWE vector", vec_reg, Self::RLWE_COUNT),
+            ("query GSW vector", vec_gsw, Self::GSW_COUNT),
+        ] {
+            if vec.len() != expected {
+                return Err(RespireError::WrongLength {
+                    what,
+                    expected,
+                    found: vec.len(),
+                });
+            }
+            check_reduced(what, vec)?;
+        }
+        Ok(())
+    }
 
-        info!(
-            "Switch rounding term noise bound (absolute / threshold): {} / {}",
-            e_round, threshold
-        );
+    fn public_params_compress(
+        pp: &<Self as PIR>::PublicParams,
+    ) -> <Self as Respire>::PublicParamsSeeded {
+        let (
+            (auto_params_short, auto_params_long),
+            rlwe_to_gsw_params,
+            (_, compress_bottom),
+            vectorize_params,
+        ) = &**pp;
+        (
+            pp.seed,
+            (
+                auto_params_short
+                    .iter()
+                    .map(|(w_mat, _)| Self::bottom_row(w_mat))
+                    .collect(),
+                auto_params_long
+                    .iter()
+                    .map(|(w_mat, _)| Self::bottom_row(w_mat))
+                    .collect(),
+            ),
+            Self::bottom_row(rlwe_to_gsw_params),
+            compress_bottom.clone(),
+            vectorize_params
+                .iter()
+                .map(|(_, bottom)| bottom.clone())
+                .collect(),
+        )
+    }
 
-        info!("Switch subgaussian term noise widths (absolute / threshold):");
+    fn public_params_expand(
+        (
+            seed,
+            (auto_bottoms_short, auto_bottoms_long),
+            rlwe_to_gsw_bottom,
+            compress_bottom,
+            vectorize_bottoms,
+        ): &<Self as Respire>::PublicParamsSeeded,
+    ) -> Option<<Self as PIR>::PublicParams> {
+        let log_d = floor_log(2, D1 as u64);
+        if auto_bottoms_short.len() != log_d
+            || auto_bottoms_long.len() != log_d
+            || vectorize_bottoms.len() != N_VEC
+        {
+            return None;
+        }
 
-        info!(
-            "    preswitch: {:.3} / {}",
-            e_subg_preswitch.sqrt(),
-            threshold
+        let auto_params_short = auto_bottoms_short
+            .iter()
+            .enumerate()
+            .map(|(i, bottom)| {
+                let a_t: Matrix<1, T_PROJ_SHORT, _> =
+                    public_params_rand(seed, PP_STREAM_AUTO_SHORT + i as u64);
+                (Matrix::stack(&a_t, bottom), (D1 >> i) + 1)
+            })
+            .collect();
+        let auto_params_long = auto_bottoms_long
+            .iter()
+            .enumerate()
+            .map(|(i, bottom)| {
+                let a_t: Matrix<1, T_PROJ_LONG, _> =
+                    public_params_rand(seed, PP_STREAM_AUTO_LONG + i as u64);
+                (Matrix::stack(&a_t, bottom), (D1 >> i) + 1)
+            })
+            .collect();
+        let rlwe_to_gsw_a_t: Matrix<1, M_RLWE_TO_GSW, _> =
+            public_params_rand(seed, PP_STREAM_RLWE_TO_GSW);
+        let rlwe_to_gsw_params = Matrix::stack(&rlwe_to_gsw_a_t, rlwe_to_gsw_bottom);
+        let compress_params = (
+            public_params_rand(seed, PP_STREAM_COMPRESS),
+            compress_bottom.clone(),
         );
-        info!("    gadget: {:.3} / {}", e_subg_gadget.sqrt(), threshold);
-        info!("    total: {:.3} / {}", e_subg.sqrt(), threshold);
+        let vectorize_params = vectorize_bottoms
+            .iter()
+            .enumerate()
+            .map(|(i, bottom)| {
+                (
+                    public_params_rand(seed, PP_STREAM_VECTORIZE + i as u64),
+                    bottom.clone(),
+                )
+            })
+            .collect();
 
-        use std::f64::consts::PI;
-        let error_rate = 2_f64
-            * (D3 as f64)
-            * f64::exp(-PI * (0.5_f64 * (Q3 / P) as f64 - e_round).powi(2) / e_subg);
+        Some(SeededPublicParams {
+            seed: *seed,
+            expanded: (
+                (auto_params_short, auto_params_long),
+                rlwe_to_gsw_params,
+                compress_params,
+                vectorize_params,
+            ),
+        })
+    }
 
-        info!("Error rate: 2^({})", error_rate.log2());
-        info!("***");
+    fn params() -> RespireParamsExpanded {
+        RespireParamsExpanded {
+            Q1,
+            Q1A,
+            Q1B,
+            D1,
+            Z_GSW,
+            T_GSW,
+            M_GSW,
+            Z_PROJ_SHORT,
+            T_PROJ_SHORT,
+            Z_PROJ_LONG,
+            T_PROJ_LONG,
+            Z_RLWE_TO_GSW,
+            T_RLWE_TO_GSW,
+            Z_VECTORIZE,
+            T_VECTORIZE,
+            BATCH_SIZE,
+            N_VEC,
+            M_RLWE_TO_GSW,
+            ERROR_WIDTH_MILLIONTHS,
+            ERROR_WIDTH_VEC_MILLIONTHS,
+            ERROR_WIDTH_COMPRESS_MILLIONTHS,
+            SECRET_BOUND,
+            SECRET_WIDTH_VEC_MILLIONTHS,
+            SECRET_WIDTH_COMPRESS_MILLIONTHS,
+            P,
+            D3,
+            NU1,
+            NU2,
+            Q3,
+            Q2,
+            D2,
+            T_COMPRESS,
+            Z_COMPRESS,
+            BYTES_PER_RECORD,
+        }
+    }
 
-        clamp(error_rate, 0_f64, 1_f64)
+    fn params_error_rate_estimate() -> f64 {
+        Self::params().error_rate_estimate()
     }
 
     fn params_public_param_size() -> usize {
-        let automorph_elems = floor_log(2, D1 as u64) * (T_PROJ_SHORT + T_PROJ_LONG);
-        let reg_to_gsw_elems = 2 * T_RLWE_TO_GSW;
-        let scal_to_vec_elems = N_VEC * T_VECTORIZE;
-        let q_elem_size = D1 * ceil_log(2, Q1) / 8;
-
-        let compress_elems = N_VEC * T_COMPRESS;
-        let q2_elem_size = D1 * ceil_log(2, Q2) / 8;
-
-        // This code assumes we implement use PRG trick to compress the randomness components of the
-        // public params. Technically though, this hasn't been implemented.
-
-        info!(
-            "automorph pp: {:.3} KiB",
-            (automorph_elems * q_elem_size) as f64 / 1024_f64
-        );
-        info!(
-            "rlwe to GSW pp: {:.3} KiB",
-            (reg_to_gsw_elems * q_elem_size) as f64 / 1024_f64
-        );
-        info!(
-            "scal to vec pp: {:.3} KiB",
-            (scal_to_vec_elems * q_elem_size) as f64 / 1024_f64
-        );
-        info!(
-            "compress pp: {:.3} KiB",
-            (compress_elems * q2_elem_size) as f64 / 1024_f64
-        );
-        return (automorph_elems + reg_to_gsw_elems + scal_to_vec_elems) * q_elem_size
-            + compress_elems * q2_elem_size;
+        Self::params().public_param_size()
     }
 
     fn params_query_one_size() -> usize {
-        (Self::RLWE_COUNT + Self::GSW_COUNT) * ceil_log(2, Q1) / 8
+        Self::params().query_one_size()
     }
 
     fn params_record_one_size() -> usize {
-        let log_p = floor_log(2, P);
-        D3 * log_p / 8
+        Self::params().record_one_size()
     }
 
     fn params_response_one_size(trunc_len: usize) -> usize {
-        // Technically we can do ceil(d * (log(q2) + len * log(q1)) by packing into a single large integer.
-        // But for simplicity assume each IntMod<Q1> / IntMod<Q2> is serialized individually.
-        let log_q1 = ceil_log(2, Q3);
-        let log_q2 = ceil_log(2, Q2);
-        ((D2 as f64) * (log_q2 as f64 + (trunc_len as f64) * log_q1 as f64) / 8_f64).ceil() as usize
+        Self::params().response_one_size(trunc_len)
     }
 });
 
+/// Deserialization does not reduce coefficients, so those from the other party are checked.
+fn check_reduced<const N: u64>(
+    what: &'static str,
+    coeffs: &[IntMod<N>],
+) -> Result<(), RespireError> {
+    if coeffs.iter().all(|&c| u64::from(c) < N) {
+        Ok(())
+    } else {
+        Err(RespireError::CoefficientOutOfRange { what, modulus: N })
+    }
+}
+
 respire_impl!({
     pub fn extract_ring_one(
+        qk: &<Self as PIR>::QueryKey,
+        r: &<Self as Respire>::AnswerOneCompressed,
+    ) -> <Self as Respire>::RecordPackedSmall {
+        Self::decrypt_compressed(qk, r).map_ring(|r| r.round_down_into())
+    }
+
+    ///
+    /// The packed records of a compressed response modulo `Q3`, before rounding away the noise.
+    ///
+    pub fn decrypt_compressed(
         (_, _, s_small): &<Self as PIR>::QueryKey,
         (c_r_hat, c_m_hat_trunc): &<Self as Respire>::AnswerOneCompressed,
-    ) -> <Self as Respire>::RecordPackedSmall {
+    ) -> Matrix<N_VEC, 1, IntModCyclo<D2, Q3>> {
         let neg_s_small_cr =
             (-&(s_small * &IntModCycloEval::from(c_r_hat))).map_ring(|r| IntModCyclo::from(r));
         let mut result = Matrix::<N_VEC, 1, IntModCyclo<D2, Q3>>::zero();
@@ -1412,7 +2079,7 @@ respire_impl!({
             }
             result[(i, 0)] += &c_m_hat_trunc[i];
         }
-        result.map_ring(|r| r.round_down_into())
+        result
     }
 
     pub fn extract_bytes_one(
@@ -1438,11 +2105,47 @@ respire_impl!({
         result
     }
 
+    /// Check a query before answering it, see `PIR::answer`.
+    pub fn check_query(qs: &<Self as PIR>::Query) -> Result<(), RespireError> {
+        // Fewer queries than the batch size come from `query_truncated`, and only the ring elements
+        // holding their answers are sent back
+        if qs.len() > Self::BATCH_SIZE {
+            return Err(RespireError::BatchTooLarge {
+                len: qs.len(),
+                max: Self::BATCH_SIZE,
+            });
+        }
+        for q in qs {
+            Self::check_query_one(q)?;
+        }
+        Ok(())
+    }
+
+    /// Pack the answers of a chunk into one vector ciphertext, and the number of its rows in use
+    pub fn answer_vectorize_chunk(
+        pp: &<Self as PIR>::PublicParams,
+        chunk: &[<Self as Respire>::AnswerOne],
+    ) -> (<Self as Respire>::VecRLWEEncoding, usize) {
+        let mut scalar_cts = Vec::with_capacity(Self::RESPONSE_CHUNK_SIZE);
+        let (_, _, _, vectorize_params) = &**pp;
+        for vec_idx in 0..N_VEC {
+            let mut scalar_ct = Matrix::zero();
+            for pack_idx in 0..Self::PACK_RATIO_RESPONSE {
+                let idx = vec_idx * Self::PACK_RATIO_RESPONSE + pack_idx;
+                if idx < chunk.len() {
+                    scalar_ct += &Self::rlwe_mul_x_pow(&chunk[idx], pack_idx * (D1 / D2));
+                }
+            }
+            scalar_cts.push(scalar_ct)
+        }
+        let vec = Self::scal_to_vec(vectorize_params, scalar_cts.as_slice().try_into().unwrap());
+        (vec, chunk.len().div_ceil(Self::PACK_RATIO_RESPONSE))
+    }
+
     pub fn answer_query_unpack(
-        ((auto_params_short, auto_params_long), rlwe_to_gsw_params, _, _): &<Self as PIR>::PublicParams,
+        ((auto_params_short, auto_params_long), rlwe_to_gsw_params, _, _): &<Self as Respire>::PublicParamsExpanded,
         ((seed_reg, vec_reg), (seed_gsw, vec_gsw)): &<Self as Respire>::QueryOne,
-        _: Option<&<Self as PIR>::QueryKey>,
-        //time_stats: Option<&mut Stats<Duration>>,
+        time_stats: Option<&mut Stats<Duration>>,
     ) -> <Self as Respire>::QueryOneExpanded {
         let inv = <Self as Respire>::RingQ1Fast::from(mod_inverse(D1 as u64, Q1));
         let mut c_rlwes = {
@@ -1515,11 +2218,11 @@ respire_impl!({
 
         let i3 = Instant::now();
 
-        /*if let Some(time_stats) = time_stats {
+        if let Some(time_stats) = time_stats {
             time_stats.add("answer_query_expand_reg", i1 - i0);
             time_stats.add("answer_query_expand_gsw", i2 - i1);
             time_stats.add("answer_query_expand_reg_to_gsw", i3 - i2);
-        }*/
+        }
 
         // TODO measure and report noise through this phase? Difficult because need to know the exact encoding (since they are not rounded to q/p)
         (c_rlwes, c_gsws_fold, c_gsws_rot)
@@ -1584,6 +2287,7 @@ respire_impl!({
         #[cfg(not(target_feature = "avx2"))]
         for eval_idx in 0..D1 {
             for j in 0..Self::PACKED_DIM2_SIZE {
+                let block = db.block(eval_idx * Self::PACKED_DIM2_SIZE + j);
                 let mut sum0_proj1 = 0_u64;
                 let mut sum0_proj2 = 0_u64;
                 let mut sum1_proj1 = 0_u64;
@@ -1598,7 +2302,7 @@ respire_impl!({
                     let lhs1_proj1 = lhs1 as u32 as u64;
                     let lhs1_proj2 = lhs1 >> 32;
 
-                    let rhs = db[eval_idx * Self::PACKED_DB_SIZE + j * Self::PACKED_DIM1_SIZE + i];
+                    let rhs = block[i];
                     let rhs_proj1 = rhs as u32 as u64;
                     let rhs_proj2 = rhs >> 32;
 
@@ -1627,6 +2331,7 @@ respire_impl!({
             use std::arch::x86_64::*;
             unsafe {
                 for j in 0..Self::PACKED_DIM2_SIZE {
+                    let block = db.block(eval_vec_idx * Self::PACKED_DIM2_SIZE + j);
                     let mut sum0_proj1 = _mm256_setzero_si256();
                     let mut sum0_proj2 = _mm256_setzero_si256();
                     let mut sum1_proj1 = _mm256_setzero_si256();
@@ -1639,9 +2344,7 @@ respire_impl!({
                         let lhs1_ptr = c1s.get_unchecked(eval_vec_idx * Self::PACKED_DIM1_SIZE + i)
                             as *const SimdVec
                             as *const __m256i;
-                        let rhs_ptr = db.get_unchecked(
-                            eval_vec_idx * Self::PACKED_DB_SIZE + j * Self::PACKED_DIM1_SIZE + i,
-                        ) as *const SimdVec as *const __m256i;
+                        let rhs_ptr = block.get_unchecked(i) as *const SimdVec as *const __m256i;
 
                         let lhs0_proj1 = _mm256_load_si256(lhs0_ptr);
                         let lhs0_proj2 = _mm256_srli_epi64::<32>(lhs0_proj1);
@@ -1770,7 +2473,7 @@ respire_impl!({
     }
 
     pub fn answer_project(
-        ((_, auto_params_long), _, _, _): &<Self as PIR>::PublicParams,
+        ((_, auto_params_long), _, _, _): &<Self as Respire>::PublicParamsExpanded,
         ct: &<Self as Respire>::RLWEEncoding,
     ) -> <Self as Respire>::RLWEEncoding {
         let mut ct_curr = ct.clone();
@@ -1991,13 +2694,26 @@ respire_impl!({
     ) -> <Self as Respire>::AutoParams<LEN> {
         let mut rng = ChaCha20Rng::from_entropy();
         let a_t: Matrix<1, LEN, <Self as Respire>::RingQ1Fast> = Matrix::rand_uniform(&mut rng);
+        let bottom = Self::auto_setup_bottom::<LEN, BASE>(tau_power, s_encode, &a_t);
+        (Matrix::stack(&a_t, &bottom), tau_power)
+    }
+
+    ///
+    /// Computes the non-random half of an automorphism key with random half `a_t`.
+    ///
+    pub fn auto_setup_bottom<const LEN: usize, const BASE: u64>(
+        tau_power: usize,
+        s_encode: &<Self as Respire>::RingQ1Fast,
+        a_t: &Matrix<1, LEN, <Self as Respire>::RingQ1Fast>,
+    ) -> Matrix<1, LEN, <Self as Respire>::RingQ1Fast> {
+        let mut rng = ChaCha20Rng::from_entropy();
         let e_t =
             Self::rand_discrete_gaussian_matrix::<ERROR_WIDTH_MILLIONTHS, 1, LEN, _>(&mut rng);
-        let mut bottom = &a_t * s_encode;
+        let mut bottom = a_t * s_encode;
         bottom += &e_t;
         bottom -= &(&build_gadget::<<Self as Respire>::RingQ1Fast, 1, LEN, BASE, LEN>()
             * &s_encode.auto(tau_power));
-        (Matrix::stack(&a_t, &bottom), tau_power)
+        bottom
     }
 
     pub fn auto_hom<const LEN: usize, const BASE: u64>(
@@ -2071,11 +2787,23 @@ respire_impl!({
     ) -> <Self as Respire>::RLWEToGSWParams {
         let mut rng = ChaCha20Rng::from_entropy();
         let a_t = Matrix::<1, M_RLWE_TO_GSW, <Self as Respire>::RingQ1Fast>::rand_uniform(&mut rng);
+        let bottom = Self::rlwe_to_gsw_setup_bottom(s_encode, &a_t);
+        Matrix::stack(&a_t, &bottom)
+    }
+
+    ///
+    /// Computes the non-random half of the RLWE to GSW key with random half `a_t`.
+    ///
+    pub fn rlwe_to_gsw_setup_bottom(
+        s_encode: &<Self as Respire>::EncodingSecret,
+        a_t: &Matrix<1, M_RLWE_TO_GSW, <Self as Respire>::RingQ1Fast>,
+    ) -> Matrix<1, M_RLWE_TO_GSW, <Self as Respire>::RingQ1Fast> {
+        let mut rng = ChaCha20Rng::from_entropy();
         let e_mat =
             Self::rand_discrete_gaussian_matrix::<ERROR_WIDTH_MILLIONTHS, 1, M_RLWE_TO_GSW, _>(
                 &mut rng,
             );
-        let mut bottom = &a_t * s_encode;
+        let mut bottom = a_t * s_encode;
         bottom += &e_mat;
         let g_vec = build_gadget::<
             <Self as Respire>::RingQ1Fast,
@@ -2089,8 +2817,7 @@ respire_impl!({
         s_encode_tensor_g.copy_into(&g_vec, 0, T_RLWE_TO_GSW);
         s_encode_tensor_g.copy_into(&(&g_vec * &(-s_encode)), 0, 0);
         bottom -= &(&s_encode_tensor_g * s_encode);
-
-        Matrix::stack(&a_t, &bottom)
+        bottom
     }
 
     pub fn rlwe_to_gsw(
@@ -2126,15 +2853,28 @@ respire_impl!({
     ) -> <Self as Respire>::CompressParams {
         let mut rng = ChaCha20Rng::from_entropy();
         let a_t = Matrix::<1, T_COMPRESS, IntModCycloEval<D1, Q2>>::rand_uniform(&mut rng);
+        let b_mat = Self::compress_setup_bottom(s_from, s_to, &a_t);
+        (a_t, b_mat)
+    }
+
+    ///
+    /// Computes the non-random half of the compression key with random half `a_t`.
+    ///
+    pub fn compress_setup_bottom(
+        s_from: &<Self as Respire>::VecEncodingSecretQ2,
+        s_to: &<Self as Respire>::VecEncodingSecretQ2,
+        a_t: &Matrix<1, T_COMPRESS, IntModCycloEval<D1, Q2>>,
+    ) -> Matrix<N_VEC, T_COMPRESS, IntModCycloEval<D1, Q2>> {
+        let mut rng = ChaCha20Rng::from_entropy();
         let e_mat = Matrix::<N_VEC, T_COMPRESS, IntModCycloEval<D1, Q2>>::rand_discrete_gaussian::<
             _,
             ERROR_WIDTH_COMPRESS_MILLIONTHS,
         >(&mut rng);
         let mut b_mat = &(-s_from)
             * &build_gadget::<IntModCycloEval<D1, Q2>, 1, T_COMPRESS, Z_COMPRESS, T_COMPRESS>();
-        b_mat += &(s_to * &a_t);
+        b_mat += &(s_to * a_t);
         b_mat += &e_mat;
-        (a_t, b_mat)
+        b_mat
     }
 
     pub fn vectorize_setup(
@@ -2144,28 +2884,52 @@ respire_impl!({
         let mut rng = ChaCha20Rng::from_entropy();
         let mut result = Vec::with_capacity(N_VEC);
         for i in 0..N_VEC {
-            let mut unit = Matrix::<N_VEC, 1, <Self as Respire>::RingQ1Fast>::zero();
-            unit[(i, 0)] = <Self as Respire>::RingQ1Fast::one();
-            let unit = unit;
-
             let a_t =
                 Matrix::<1, T_VECTORIZE, <Self as Respire>::RingQ1Fast>::rand_uniform(&mut rng);
-            let e_mat = Self::rand_discrete_gaussian_matrix::<
-                ERROR_WIDTH_VEC_MILLIONTHS,
-                N_VEC,
-                T_VECTORIZE,
-                _,
-            >(&mut rng);
-            let mut bottom = s_vec * &a_t;
-            bottom += &e_mat;
-            let embedding =
-                &(&unit * s_scal) * &build_gadget::<_, 1, T_VECTORIZE, Z_VECTORIZE, T_VECTORIZE>();
-            bottom -= &embedding;
+            let bottom = Self::vectorize_setup_bottom(s_scal, s_vec, i, &a_t);
             result.push((a_t, bottom));
         }
         result
     }
 
+    ///
+    /// Computes the non-random half of the `i`th vectorization key with random half `a_t`.
+    ///
+    pub fn vectorize_setup_bottom(
+        s_scal: &<Self as Respire>::EncodingSecret,
+        s_vec: &<Self as Respire>::VecEncodingSecret,
+        i: usize,
+        a_t: &Matrix<1, T_VECTORIZE, <Self as Respire>::RingQ1Fast>,
+    ) -> Matrix<N_VEC, T_VECTORIZE, <Self as Respire>::RingQ1Fast> {
+        let mut rng = ChaCha20Rng::from_entropy();
+        let mut unit = Matrix::<N_VEC, 1, <Self as Respire>::RingQ1Fast>::zero();
+        unit[(i, 0)] = <Self as Respire>::RingQ1Fast::one();
+        let unit = unit;
+
+        let e_mat = Self::rand_discrete_gaussian_matrix::<
+            ERROR_WIDTH_VEC_MILLIONTHS,
+            N_VEC,
+            T_VECTORIZE,
+            _,
+        >(&mut rng);
+        let mut bottom = s_vec * a_t;
+        bottom += &e_mat;
+        let embedding =
+            &(&unit * s_scal) * &build_gadget::<_, 1, T_VECTORIZE, Z_VECTORIZE, T_VECTORIZE>();
+        bottom -= &embedding;
+        bottom
+    }
+
+    fn bottom_row<const M: usize>(
+        m: &Matrix<2, M, <Self as Respire>::RingQ1Fast>,
+    ) -> Matrix<1, M, <Self as Respire>::RingQ1Fast> {
+        let mut result = Matrix::zero();
+        for col in 0..M {
+            result[(0, col)] = m[(1, col)].clone();
+        }
+        result
+    }
+
     pub fn scal_to_vec(
         s_scal_to_vec: &<Self as Respire>::VectorizeParams,
         cs: &[<Self as Respire>::RLWEEncoding; N_VEC],
@@ -2183,6 +2947,51 @@ respire_impl!({
         (result_rand, result_embed)
     }
 
+    ///
+    /// The evaluations of a packed chunk of records, as stored in the database: the CRT components
+    /// mod `Q1A` and `Q1B` in the low and high halves.
+    ///
+    fn db_chunk_encode(record_packed: &<Self as Respire>::RingQ1) -> [u64; D1] {
+        let value = <Self as Respire>::RingQ1Fast::from(record_packed);
+        std::array::from_fn(|i| {
+            let lo = u64::from(value.proj1.evals[i]);
+            let hi = u64::from(value.proj2.evals[i]);
+            (hi << 32) | lo
+        })
+    }
+
+    /// Inverse of `db_chunk_encode`, reading the chunk from the database.
+    fn db_chunk_decode(storage: &DbStorage, chunk_idx: usize) -> <Self as Respire>::RingQ1 {
+        let eval = |i: usize| {
+            let (block_idx, lane) = Self::db_eval_position(chunk_idx, i);
+            simd_lanes(storage.block(block_idx))[lane]
+        };
+        let proj1 = IntModCycloEval::<D1, Q1A>::from(std::array::from_fn(|i| {
+            IntMod::from(eval(i) & (u32::MAX as u64))
+        }));
+        let proj2 =
+            IntModCycloEval::<D1, Q1B>::from(std::array::from_fn(|i| IntMod::from(eval(i) >> 32)));
+        IntModCyclo::from(&<Self as Respire>::RingQ1Fast::from((proj1, proj2)))
+    }
+
+    ///
+    /// Position of evaluation `eval_idx` of packed chunk `chunk_idx` in the database: the block,
+    /// and the lane within it. The chunks are stored transposed, see `encode_db`, and every block
+    /// is one run of `PACKED_DIM1_SIZE` vectors scanned by `answer_first_dim`.
+    ///
+    fn db_eval_position(chunk_idx: usize, eval_idx: usize) -> (usize, usize) {
+        let (db_i, db_j) = (
+            chunk_idx / Self::PACKED_DIM2_SIZE,
+            chunk_idx % Self::PACKED_DIM2_SIZE,
+        );
+        let eval_vec_idx = eval_idx / SIMD_LANES;
+        let lane = eval_idx - eval_vec_idx * SIMD_LANES;
+        (
+            eval_vec_idx * Self::PACKED_DIM2_SIZE + db_j,
+            db_i * SIMD_LANES + lane,
+        )
+    }
+
     pub fn encode_record(bytes: &RecordBytesImpl<BYTES_PER_RECORD>) -> <Self as Respire>::Record {
         let bit_iter = BitSlice::<u8, Msb0>::from_slice(&bytes.it);
         let p_bits = floor_log(2, P);
@@ -2211,83 +3020,119 @@ respire_impl!({
         bytes.try_into().unwrap()
     }
 
-    pub fn noise_variance(
+    ///
+    /// The noise coefficients of `c`, taking every `step`-th coefficient.
+    ///
+    pub fn noise_samples(
         s_scal: &<Self as Respire>::EncodingSecret,
         c: &<Self as Respire>::RLWEEncoding,
-    ) -> f64 {
-        let decoded: <Self as Respire>::RingQ1 = Self::decode_rlwe(&s_scal, c);
+        step: usize,
+    ) -> NoiseSamples {
+        let decoded: <Self as Respire>::RingQ1 = Self::decode_rlwe(s_scal, c);
         let message: <Self as Respire>::RingP = decoded.round_down_into();
         let noise: <Self as Respire>::RingQ1 = &decoded - &message.scale_up_into();
 
-        let mut sum = 0_f64;
-        let mut samples = 0_usize;
+        let mut samples = NoiseSamples::default();
+        for e in noise.coeff.iter().step_by(step) {
+            samples.add(e.norm());
+        }
+        samples
+    }
+
+    ///
+    /// The noise coefficients of the first `len` components of `vec`.
+    ///
+    pub fn noise_samples_vec(
+        s_vec: &<Self as Respire>::VecEncodingSecret,
+        (cr, cm): &<Self as Respire>::VecRLWEEncoding,
+        len: usize,
+    ) -> NoiseSamples {
+        let mut samples = NoiseSamples::default();
+        for i in 0..len {
+            let mut fake_ct = Matrix::zero();
+            fake_ct[(0, 0)] = cr.clone();
+            fake_ct[(1, 0)] = cm[(i, 0)].clone();
+            let fake_s = s_vec[(i, 0)].clone();
+            samples += Self::noise_samples(&fake_s, &fake_ct, 1);
+        }
+        samples
+    }
 
-        for e in noise.coeff.iter() {
-            let e_sq = (e.norm() as f64) * (e.norm() as f64);
-            sum += e_sq;
-            samples += 1;
+    ///
+    /// The noise coefficients of a compressed response, modulo `Q3`.
+    ///
+    pub fn noise_samples_compressed(
+        qk: &<Self as PIR>::QueryKey,
+        r: &<Self as Respire>::AnswerOneCompressed,
+    ) -> NoiseSamples {
+        let decrypted = Self::decrypt_compressed(qk, r);
+        let mut samples = NoiseSamples::default();
+        for i in 0..r.1.len() {
+            let message: IntModCyclo<D2, P> = decrypted[(i, 0)].round_down_into();
+            let noise = &decrypted[(i, 0)] - &message.scale_up_into();
+            for e in noise.coeff.iter() {
+                samples.add(e.norm());
+            }
         }
+        samples
+    }
 
-        sum / samples as f64
+    /// Log the noise of each phase, and add it to `noise_stats`
+    fn record_noise<const N: usize>(
+        phases: [(&'static str, NoiseSamples); N],
+        noise_stats: Option<&mut Stats<NoiseSamples>>,
+    ) {
+        for (name, samples) in phases.iter() {
+            info!(
+                "measured noise {}: {}",
+                name,
+                variance_to_subgaussian_bits(samples.variance())
+            );
+        }
+        if let Some(noise_stats) = noise_stats {
+            for (name, samples) in phases {
+                noise_stats.add(name, samples);
+            }
+        }
     }
 
-    fn variance_to_subgaussian_bits(x: f64) -> f64 {
-        // Subgaussian widths = sqrt(2*pi) * (standard deviation)
-        (x * (2f64 * PI)).log2() / 2f64
+    pub fn noise_variance(
+        s_scal: &<Self as Respire>::EncodingSecret,
+        c: &<Self as Respire>::RLWEEncoding,
+    ) -> f64 {
+        Self::noise_samples(s_scal, c, 1).variance()
     }
 
     pub fn noise_subgaussian_bits(
         s_scal: &<Self as Respire>::EncodingSecret,
         c: &<Self as Respire>::RLWEEncoding,
     ) -> f64 {
-        Self::variance_to_subgaussian_bits(Self::noise_variance(s_scal, c))
+        variance_to_subgaussian_bits(Self::noise_variance(s_scal, c))
     }
 
     pub fn noise_subgaussian_bits_vec(
         s_vec: &<Self as Respire>::VecEncodingSecret,
-        (cr, cm): &<Self as Respire>::VecRLWEEncoding,
+        vec: &<Self as Respire>::VecRLWEEncoding,
     ) -> f64 {
-        let mut total = 0_f64;
-        for i in 0..N_VEC {
-            let mut fake_ct = Matrix::zero();
-            fake_ct[(0, 0)] = cr.clone();
-            fake_ct[(1, 0)] = cm[(i, 0)].clone();
-            let fake_s = s_vec[(i, 0)].clone();
-            total += Self::noise_variance(&fake_s, &fake_ct);
-        }
-        Self::variance_to_subgaussian_bits(total / N_VEC as f64)
+        variance_to_subgaussian_bits(Self::noise_samples_vec(s_vec, vec, N_VEC).variance())
     }
 
     pub fn params_query_size() -> usize {
-        Self::BATCH_SIZE * Self::params_query_one_size()
+        Self::params().query_size()
     }
 
     pub fn params_record_size() -> usize {
-        Self::BATCH_SIZE * Self::params_record_one_size()
+        Self::params().record_size()
     }
 
     ///
     /// size, number of full vectors, remainder size
     ///
     pub fn params_response_info() -> (usize, usize, usize) {
-        let num_ring_elem = Self::BATCH_SIZE.div_ceil(Self::PACK_RATIO_RESPONSE);
-        let num_full_vecs = num_ring_elem / Self::N_VEC;
-        let num_rem = num_ring_elem % Self::N_VEC;
-
-        let full_vec_size = Self::params_response_one_size(Self::N_VEC);
-        let rem_vec_size = if num_rem > 0 {
-            Self::params_response_one_size(num_rem)
-        } else {
-            0
-        };
-        (
-            num_full_vecs * full_vec_size + rem_vec_size,
-            num_full_vecs,
-            num_rem,
-        )
+        Self::params().response_info()
     }
 
     pub fn params_rate() -> f64 {
-        (Self::params_record_size() as f64) / (Self::params_response_info().0 as f64)
+        Self::params().rate()
     }
 });
@@ -0,0 +1,147 @@
+//! The client side of RESPIRE: owns the query key, builds queries and decodes responses.
+
+use crate::pir::error::RespireError;
+use crate::pir::pir::{check_batch, PIR};
+use crate::pir::respire::{Respire, RespireQuery, RespireResponse};
+use crate::pir::serialization::{deserialize_query_key, serialize_query_key, SerializationError};
+
+pub struct RespireClient<R: Respire> {
+    qk: <R as PIR>::QueryKey,
+}
+
+impl<R: Respire> RespireClient<R> {
+    ///
+    /// Generates a fresh query key. The returned public parameters are to be sent to the server.
+    ///
+    pub fn setup() -> (Self, <R as PIR>::PublicParams) {
+        let (qk, pp) = R::setup(None);
+        (Self { qk }, pp)
+    }
+
+    pub fn from_query_key(qk: <R as PIR>::QueryKey) -> Self {
+        Self { qk }
+    }
+
+    pub fn from_query_key_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        Ok(Self {
+            qk: deserialize_query_key::<R>(bytes)?,
+        })
+    }
+
+    pub fn query_key_bytes(&self) -> Vec<u8> {
+        serialize_query_key::<R>(&self.qk)
+    }
+
+    ///
+    /// Query up to `BATCH_SIZE` records, padded with dummy queries so that the server does not
+    /// learn how many. Pass `indices.len()` to `extract` with the response.
+    ///
+    pub fn query(&self, indices: &[usize]) -> Result<RespireQuery<R>, RespireError> {
+        check_batch::<R>(indices)?;
+        Ok((0..R::BATCH_SIZE)
+            .map(|i| R::query_one(&self.qk, indices.get(i).copied().unwrap_or(0), None))
+            .collect())
+    }
+
+    ///
+    /// Query up to `BATCH_SIZE` records without padding. The response only has as many ring
+    /// elements as the records need, but the server learns their number.
+    ///
+    pub fn query_truncated(&self, indices: &[usize]) -> Result<RespireQuery<R>, RespireError> {
+        check_batch::<R>(indices)?;
+        Ok(indices
+            .iter()
+            .map(|&idx| R::query_one(&self.qk, idx, None))
+            .collect())
+    }
+
+    /// The first `num_records` records of a response, i.e. the ones that were asked for
+    pub fn extract(
+        &self,
+        response: &RespireResponse<R>,
+        num_records: usize,
+    ) -> Result<Vec<<R as PIR>::RecordBytes>, RespireError> {
+        let mut result = Vec::with_capacity(R::BATCH_SIZE);
+        for r_one in response {
+            result.extend(R::extract_one(&self.qk, r_one, None)?);
+        }
+        if result.len() < num_records {
+            return Err(RespireError::WrongLength {
+                what: "response",
+                expected: num_records,
+                found: result.len(),
+            });
+        }
+        result.truncate(num_records);
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::pir::PIRRecordBytes;
+    use crate::pir::respire_harness::RespireTestSmall;
+    use crate::pir::respire_server::RespireServer;
+    use crate::pir::serialization::serialize_public_params;
+    use crate::pir::session::SessionError;
+
+    #[test]
+    fn test_client_server() {
+        let records = (0..RespireTestSmall::NUM_RECORDS)
+            .map(|i| {
+                let bytes = (0..RespireTestSmall::BYTES_PER_RECORD)
+                    .map(|j| (i * 7 + j) as u8)
+                    .collect::<Vec<_>>();
+                <RespireTestSmall as PIR>::RecordBytes::from_bytes(&bytes).unwrap()
+            })
+            .collect::<Vec<_>>();
+        let (db, _) = RespireTestSmall::encode_db(|i| records[i].clone(), None);
+
+        let (client, pp) = RespireClient::<RespireTestSmall>::setup();
+        let server = RespireServer::<RespireTestSmall>::new(db, usize::MAX);
+        let id = server.register_client(pp).unwrap();
+
+        let client =
+            RespireClient::<RespireTestSmall>::from_query_key_bytes(&client.query_key_bytes())
+                .unwrap();
+        let indices = [RespireTestSmall::NUM_RECORDS - 1];
+        let q = client.query(&indices).unwrap();
+        let r = server.answer(id, &q).unwrap();
+        let extracted = client.extract(&r, 1).unwrap();
+        assert_eq!(extracted, vec![records[indices[0]].clone()]);
+
+        let before = server.database();
+        assert_eq!(
+            server
+                .update_records(&[(indices[0], records[0].clone())])
+                .unwrap(),
+            1
+        );
+        assert!(matches!(
+            server.update_records(&[(RespireTestSmall::DB_SIZE, records[0].clone())]),
+            Err(RespireError::UnknownRecord { .. })
+        ));
+        let r = server.answer(id, &client.query(&indices).unwrap()).unwrap();
+        assert_eq!(client.extract(&r, 1).unwrap(), vec![records[0].clone()]);
+        assert_eq!((before.epoch, server.database().epoch), (0, 1));
+
+        // A second client gets its own session
+        let (other, other_pp) = RespireClient::<RespireTestSmall>::setup();
+        let other_id = server
+            .register_client_bytes(&serialize_public_params::<RespireTestSmall>(&other_pp))
+            .unwrap();
+        assert_ne!(id, other_id);
+        assert_eq!(server.sessions().len(), 2);
+        let r = server
+            .answer(other_id, &other.query(&[3]).unwrap())
+            .unwrap();
+        assert_eq!(other.extract(&r, 1).unwrap(), vec![records[3].clone()]);
+
+        assert!(server.sessions().remove(id));
+        assert!(matches!(
+            server.answer(id, &q),
+            Err(RespireError::Session(SessionError::UnknownClient(client))) if client == id
+        ));
+    }
+}
@@ -1,10 +1,17 @@
-use crate::pir::pir::{PIRRecordBytes, Stats, PIR};
+use crate::pir::cuckoo_respire::CuckooRespireImpl;
+use crate::pir::noise::{variance_to_subgaussian_bits, NoiseSamples};
+use crate::pir::pir::{PIRRecordBytes, PIRSummary, Stats, PIR};
 use crate::pir::respire::{RespireParams, RespireParamsExpanded};
 use crate::respire;
 use clap::Parser;
 use itertools::Itertools;
 use rand::{Rng, SeedableRng};
 use rand_chacha::ChaCha20Rng;
+use serde::{Serialize, Serializer};
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 //
@@ -12,6 +19,7 @@ use std::time::{Duration, Instant};
 //
 
 #[allow(non_snake_case)]
+#[derive(Clone, Copy, Debug)]
 pub struct FactoryParams {
     pub BATCH_SIZE: usize,
     pub N_VEC: usize,
@@ -28,6 +36,9 @@ pub struct FactoryParams {
 }
 
 impl FactoryParams {
+    /// Largest response vector dimension used with `batch_256` by the standard configurations
+    pub const MAX_N_VEC: usize = 8;
+
     pub const fn single_record_256(nu1: usize, nu2: usize) -> Self {
         // *** NOTES ***
         //
@@ -147,6 +158,22 @@ pub const RESPIRE_TEST_PARAMS: RespireParamsExpanded =
 
 pub type RespireTest = respire!(RESPIRE_TEST_PARAMS);
 
+// A tiny database (64 records) for tests that run the whole protocol
+
+pub const RESPIRE_TEST_SMALL_PARAMS: RespireParamsExpanded =
+    FactoryParams::single_record_256(2, 2).expand().expand();
+
+pub type RespireTestSmall = respire!(RESPIRE_TEST_SMALL_PARAMS);
+
+// 60 records cuckoo hashed into 6 buckets of 64 records, leaving room to append
+
+pub const CUCKOO_TEST_SMALL_BASE_PARAMS: RespireParamsExpanded =
+    FactoryParams::batch_256(6, 2, 2, 2).expand().expand();
+
+pub type CuckooBaseTestSmall = respire!(CUCKOO_TEST_SMALL_BASE_PARAMS);
+
+pub type CuckooRespireTestSmall = CuckooRespireImpl<2, 6, 60, CuckooBaseTestSmall>;
+
 #[cfg(not(target_feature = "avx2"))]
 pub fn has_avx2() -> bool {
     false
@@ -160,6 +187,9 @@ pub fn has_avx2() -> bool {
 pub struct RunResult {
     pub init_times: Stats<Duration>,
     pub all_trial_times: Vec<Stats<Duration>>,
+    pub all_trial_indices: Vec<Vec<usize>>,
+    /// Whether each trial retrieved all its records correctly
+    pub all_trial_correct: Vec<bool>,
 }
 
 pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
@@ -194,8 +224,8 @@ pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
 
     let mut init_times = Stats::new();
     let begin = Instant::now();
-    let (db, db_hint) = ThePIR::encode_db(records_generator); //, Some(&mut init_times));
-    let (qk, pp) = ThePIR::setup(); //Some(&mut init_times));
+    let (db, db_hint) = ThePIR::encode_db(records_generator, Some(&mut init_times));
+    let (qk, pp) = ThePIR::setup(Some(&mut init_times));
     let end = Instant::now();
 
     init_times.add(
@@ -214,6 +244,8 @@ pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
     eprintln!("========");
 
     let mut all_trial_times = Vec::new();
+    let mut all_trial_indices = Vec::new();
+    let mut all_trial_correct = Vec::new();
 
     let mut run_trial = |indices: &[usize]| {
         eprintln!("Running trial on indices {:?}", &indices);
@@ -221,9 +253,9 @@ pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
         let mut trial_times = Stats::new();
 
         let begin = Instant::now();
-        let (q, st) = ThePIR::query(&qk, indices, &db_hint); // Some(&mut trial_times));
-        let response = ThePIR::answer(&pp, &db, &q, Some(&qk)); // Some(&mut trial_times));
-        let extracted = ThePIR::extract(&qk, &response, &st); // Some(&mut trial_times));
+        let (q, st) = ThePIR::query(&qk, indices, &db_hint, Some(&mut trial_times)).unwrap();
+        let response = ThePIR::answer(&pp, &db, &q, Some(&mut trial_times)).unwrap();
+        let extracted = ThePIR::extract(&qk, &response, &st, Some(&mut trial_times)).unwrap();
         let end = Instant::now();
 
         trial_times.add(
@@ -240,9 +272,12 @@ pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
         }
         eprintln!("Trial time (end-to-end): {:?}", end - begin);
         all_trial_times.push(trial_times);
+        all_trial_indices.push(indices.to_vec());
 
+        let mut correct = true;
         for (idx, decoded_record) in indices.iter().copied().zip(extracted) {
             if decoded_record.as_bytes() != records_generator(idx).as_bytes() {
+                correct = false;
                 eprintln!("**** **** **** **** ERROR **** **** **** ****");
                 eprintln!("protocol failed");
                 eprintln!("idx = {}", idx);
@@ -250,6 +285,7 @@ pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
                 eprintln!("actual record = {:?}", records_generator(idx).as_bytes());
             }
         }
+        all_trial_correct.push(correct);
         eprintln!("========");
     };
 
@@ -261,6 +297,8 @@ pub fn run_pir<ThePIR: PIR, I: Iterator<Item = usize>>(iter: I) -> RunResult {
     RunResult {
         init_times,
         all_trial_times,
+        all_trial_indices,
+        all_trial_correct,
     }
 }
 
@@ -276,61 +314,391 @@ macro_rules! generate_main {
 #[derive(Parser, Debug)]
 struct Args {
     trials: usize,
+    /// Write the parameters, communication sizes, timings and trial outcomes as JSON to this path
+    #[arg(long)]
+    json: Option<PathBuf>,
+    /// Write the same report as `key,value` rows, with nested keys joined by dots
+    #[arg(long)]
+    csv: Option<PathBuf>,
 }
 
 pub fn harness_main<ThePIR: PIR>() {
     env_logger::init();
     let args = Args::parse();
+    let report = run_benchmark::<ThePIR>(args.trials);
+    if let Some(path) = &args.json {
+        report
+            .write_json(path)
+            .expect("failed to write the JSON report");
+    }
+    if let Some(path) = &args.csv {
+        report
+            .write_csv(path)
+            .expect("failed to write the CSV report");
+    }
+}
 
+///
+/// Run `trials` queries of `BATCH_SIZE` random records, print a summary, and return the report.
+///
+pub fn run_benchmark<ThePIR: PIR>(trials: usize) -> BenchReport {
     let mut rng = ChaCha20Rng::from_entropy();
     let record_gen = |_| rng.gen_range(0_usize..ThePIR::NUM_RECORDS);
-    let run_result =
-        run_pir::<ThePIR, _>((0usize..args.trials * ThePIR::BATCH_SIZE).map(record_gen));
+    let run_result = run_pir::<ThePIR, _>((0usize..trials * ThePIR::BATCH_SIZE).map(record_gen));
+    let report = BenchReport::new(ThePIR::summary(), &run_result);
 
-    let trial_times = run_result
-        .all_trial_times
-        .iter()
-        .map(|tt| tt.as_vec())
-        .collect_vec();
-    let stat_names = trial_times[0].iter().map(|x| x.0).collect_vec();
-    for tt in trial_times.iter() {
-        assert_eq!(tt.iter().map(|x| x.0).collect_vec(), stat_names);
+    eprintln!("Summary times:");
+    for (stat_name, phase) in report.phases.0.iter() {
+        eprintln!(
+            "    {}: {:?} mean, {:?} stddev ({:.3}%)",
+            stat_name,
+            Duration::from_nanos(phase.mean_ns),
+            Duration::from_nanos(phase.stddev_ns),
+            phase.stddev_percent,
+        );
     }
 
-    let mut means = Vec::with_capacity(trial_times.len());
-    let mut stddevs = Vec::with_capacity(trial_times.len());
+    eprintln!("mean, stddev in CSV format (times in nanoseconds):");
+    eprintln!("{}", report.phases.0.iter().map(|p| p.0).join(", "));
+    eprintln!("{}", report.phases.0.iter().map(|p| p.1.mean_ns).join(", "));
+    eprintln!(
+        "{}",
+        report.phases.0.iter().map(|p| p.1.stddev_ns).join(", ")
+    );
+    eprintln!(
+        "{} of {} trials correct",
+        report.trials.iter().filter(|t| t.correct).count(),
+        report.trials.len()
+    );
+    report
+}
 
-    eprintln!("Summary times:");
+///
+/// Run `trials` queries of `BATCH_SIZE` random records from a database of random records, measuring
+/// the noise after each phase of answering them (see `PIR::answer_measured`). Prints the measured
+/// noise next to the error analysis, and returns the report.
+///
+pub fn run_noise_profile<ThePIR: PIR>(trials: usize) -> NoiseReport {
+    eprintln!("Profiling noise...");
+    eprintln!("========");
+    ThePIR::print_summary();
+    eprintln!("========");
 
-    for (stat_i, stat_name) in stat_names.iter().copied().enumerate() {
-        let mut sum = 0_f64;
-        let mut sum_sq = 0_f64;
-        for tt in trial_times.iter() {
-            let value = tt[stat_i].1.as_nanos() as f64;
-            sum += value;
-            sum_sq += value.powi(2);
-        }
+    let records_generator = |i: usize| {
+        let mut record = vec![0_u8; ThePIR::BYTES_PER_RECORD];
+        ChaCha20Rng::seed_from_u64(i as u64).fill(record.as_mut_slice());
+        ThePIR::RecordBytes::from_bytes(record.as_slice()).unwrap()
+    };
+    let (db, db_hint) = ThePIR::encode_db(records_generator, None);
+    let (qk, pp) = ThePIR::setup(None);
 
-        let mean = sum / trial_times.len() as f64;
-        let stddev = (sum_sq / trial_times.len() as f64 - mean.powi(2)).sqrt();
+    let mut rng = ChaCha20Rng::from_entropy();
+    let mut noise_stats = Stats::new();
+    let mut correct = 0;
+    for trial in 0..trials {
+        let indices = (0..ThePIR::BATCH_SIZE)
+            .map(|_| rng.gen_range(0_usize..ThePIR::NUM_RECORDS))
+            .collect_vec();
+        let (q, st) = ThePIR::query(&qk, &indices, &db_hint, None).unwrap();
+        let response = ThePIR::answer_measured(&pp, &db, &q, &qk, &mut noise_stats).unwrap();
+        let extracted = ThePIR::extract(&qk, &response, &st, None).unwrap();
+        let trial_correct = indices
+            .iter()
+            .zip(extracted.iter())
+            .all(|(&idx, record)| record.as_bytes() == records_generator(idx).as_bytes());
+        if trial_correct {
+            correct += 1;
+        } else {
+            eprintln!("**** **** **** **** ERROR **** **** **** ****");
+            eprintln!("protocol failed on indices {:?}", indices);
+        }
+        eprintln!("Trial {} of {} done", trial + 1, trials);
+    }
 
-        let mean = mean.round() as u64;
-        let stddev = stddev.round() as u64;
-        means.push(mean);
-        stddevs.push(stddev);
+    let report = NoiseReport::new(ThePIR::summary(), trials, correct, &noise_stats);
+    eprintln!("========");
+    eprintln!("Noise widths in bits (measured / estimated, variance ratio):");
+    for (name, phase) in report.phases.0.iter() {
         eprintln!(
-            "    {}: {:?} mean, {:?} stddev ({:.3}%)",
-            stat_name,
-            Duration::from_nanos(mean),
-            Duration::from_nanos(stddev),
-            stddev as f64 / mean as f64 / 100_f64,
+            "    {}: {:.3} / {:.3} ({:.3e}), max 2^{:.3}",
+            name,
+            phase.width_bits,
+            phase.estimated_width_bits,
+            phase.ratio,
+            (phase.max_abs as f64).log2()
         );
     }
+    eprintln!("{} of {} trials correct", correct, trials);
+    report
+}
 
-    eprintln!("mean, stddev in CSV format (times in nanoseconds):");
-    eprintln!("{}", stat_names.join(", "));
-    eprintln!("{}", means.iter().map(u64::to_string).join(", "));
-    eprintln!("{}", stddevs.iter().map(u64::to_string).join(", "));
+/// Named values, serialized as a map in the order given
+#[derive(Debug)]
+pub struct Named<V>(pub Vec<(&'static str, V)>);
+
+impl<V: Serialize> Serialize for Named<V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(name, value)| (name, value)))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PhaseTimes {
+    pub mean_ns: u64,
+    pub stddev_ns: u64,
+    pub stddev_percent: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TrialReport {
+    pub indices: Vec<usize>,
+    /// Whether every record was retrieved correctly
+    pub correct: bool,
+    pub times_ns: Named<u64>,
+}
+
+///
+/// The outcome of a benchmark run, for tracking results across commits. Times are in nanoseconds
+/// and sizes in bytes; phases are listed in the order they run.
+///
+#[derive(Debug, Serialize)]
+pub struct BenchReport {
+    pub avx2: bool,
+    pub summary: PIRSummary,
+    pub init_times_ns: Named<u64>,
+    pub phases: Named<PhaseTimes>,
+    pub trials: Vec<TrialReport>,
+}
+
+impl BenchReport {
+    pub fn new(summary: PIRSummary, run_result: &RunResult) -> Self {
+        let nanos = |stats: &Stats<Duration>| {
+            Named(
+                stats
+                    .as_vec()
+                    .into_iter()
+                    .map(|(name, value)| (name, value.as_nanos() as u64))
+                    .collect(),
+            )
+        };
+        let trials = run_result
+            .all_trial_times
+            .iter()
+            .zip(run_result.all_trial_indices.iter())
+            .zip(run_result.all_trial_correct.iter())
+            .map(|((times, indices), &correct)| TrialReport {
+                indices: indices.clone(),
+                correct,
+                times_ns: nanos(times),
+            })
+            .collect_vec();
+
+        let mut phases = Vec::new();
+        if let Some(first) = trials.first() {
+            let stat_names = first.times_ns.0.iter().map(|x| x.0).collect_vec();
+            for trial in trials.iter() {
+                assert_eq!(
+                    trial.times_ns.0.iter().map(|x| x.0).collect_vec(),
+                    stat_names
+                );
+            }
+            for (stat_i, stat_name) in stat_names.into_iter().enumerate() {
+                let mut sum = 0_f64;
+                let mut sum_sq = 0_f64;
+                for trial in trials.iter() {
+                    let value = trial.times_ns.0[stat_i].1 as f64;
+                    sum += value;
+                    sum_sq += value.powi(2);
+                }
+
+                let mean = sum / trials.len() as f64;
+                let stddev = (sum_sq / trials.len() as f64 - mean.powi(2))
+                    .max(0_f64)
+                    .sqrt();
+                phases.push((
+                    stat_name,
+                    PhaseTimes {
+                        mean_ns: mean.round() as u64,
+                        stddev_ns: stddev.round() as u64,
+                        stddev_percent: stddev / mean * 100_f64,
+                    },
+                ));
+            }
+        }
+
+        Self {
+            avx2: has_avx2(),
+            summary,
+            init_times_ns: nanos(&run_result.init_times),
+            phases: Named(phases),
+            trials,
+        }
+    }
+
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        write_json(self, path)
+    }
+
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        write_csv(self, path)
+    }
+
+    pub fn csv_rows(&self) -> Vec<(String, String)> {
+        csv_rows(self)
+    }
+}
+
+///
+/// The noise of one phase of answering queries, measured over all trials, against the error
+/// analysis. Widths are in bits, as log2 of the subgaussian width.
+///
+#[derive(Debug, Serialize)]
+pub struct PhaseNoise {
+    pub samples: u64,
+    pub variance: f64,
+    pub estimated_variance: f64,
+    /// Measured over estimated variance; at most 1 if the analysis is a valid bound
+    pub ratio: f64,
+    pub width_bits: f64,
+    pub estimated_width_bits: f64,
+    pub max_abs: u64,
+    /// `histogram[k]` is the number of coefficients `e` with `2^(k-1) <= |e| < 2^k` (`e = 0` for
+    /// `k = 0`)
+    pub histogram: Vec<u64>,
+}
+
+///
+/// The outcome of a noise profiling run. Phases are listed in the order they run; the noise is
+/// modulo `Q1` up to vectorization and modulo `Q3` after compression.
+///
+#[derive(Debug, Serialize)]
+pub struct NoiseReport {
+    pub summary: PIRSummary,
+    pub trials: usize,
+    /// Trials that retrieved all their records correctly
+    pub correct: usize,
+    pub phases: Named<PhaseNoise>,
+}
+
+impl NoiseReport {
+    pub fn new(
+        summary: PIRSummary,
+        trials: usize,
+        correct: usize,
+        noise_stats: &Stats<NoiseSamples>,
+    ) -> Self {
+        let estimates = summary.params.noise_estimate().variances();
+        let phases = noise_stats
+            .as_vec()
+            .into_iter()
+            .map(|(name, samples)| {
+                let estimated_variance = estimates
+                    .iter()
+                    .find(|e| e.0 == name)
+                    .map_or(f64::NAN, |e| e.1);
+                let variance = samples.variance();
+                (
+                    name,
+                    PhaseNoise {
+                        samples: samples.count,
+                        variance,
+                        estimated_variance,
+                        ratio: variance / estimated_variance,
+                        width_bits: variance_to_subgaussian_bits(variance),
+                        estimated_width_bits: variance_to_subgaussian_bits(estimated_variance),
+                        max_abs: samples.max_abs,
+                        histogram: samples.bit_lengths,
+                    },
+                )
+            })
+            .collect();
+        Self {
+            summary,
+            trials,
+            correct,
+            phases: Named(phases),
+        }
+    }
+}
+
+pub fn write_json<R: Serialize>(report: &R, path: &Path) -> io::Result<()> {
+    let file = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(file, report)?;
+    Ok(())
+}
+
+/// Write the leaves of the JSON report as `key,value` rows, see [`csv_rows`]
+pub fn write_csv<R: Serialize>(report: &R, path: &Path) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    writeln!(file, "key,value")?;
+    for (key, value) in csv_rows(report) {
+        writeln!(file, "{},{}", key, value)?;
+    }
+    file.flush()
+}
+
+/// The leaves of the JSON report, keyed by their dot-separated paths
+pub fn csv_rows<R: Serialize>(report: &R) -> Vec<(String, String)> {
+    fn flatten(prefix: String, value: &serde_json::Value, rows: &mut Vec<(String, String)>) {
+        let child = |key: &str| {
+            if prefix.is_empty() {
+                key.to_owned()
+            } else {
+                format!("{}.{}", prefix, key)
+            }
+        };
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    flatten(child(key), value, rows);
+                }
+            }
+            serde_json::Value::Array(values) => {
+                for (i, value) in values.iter().enumerate() {
+                    flatten(child(&i.to_string()), value, rows);
+                }
+            }
+            serde_json::Value::String(s) => rows.push((prefix, s.clone())),
+            value => rows.push((prefix, value.to_string())),
+        }
+    }
+    let mut rows = Vec::new();
+    flatten(
+        String::new(),
+        &serde_json::to_value(report).unwrap(),
+        &mut rows,
+    );
+    rows
+}
+
+///
+/// Parse a size in bytes with an optional binary suffix, as in the configuration names: `256m` is
+/// 256 MiB and `1g` is 1 GiB. The suffixes `k`, `m`, `g` and `t` are case-insensitive.
+///
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let (digits, shift) = match s.chars().last().map(|c| c.to_ascii_lowercase()) {
+        Some('k') => (&s[..s.len() - 1], 10),
+        Some('m') => (&s[..s.len() - 1], 20),
+        Some('g') => (&s[..s.len() - 1], 30),
+        Some('t') => (&s[..s.len() - 1], 40),
+        _ => (s, 0),
+    };
+    let value = digits
+        .parse::<usize>()
+        .map_err(|e| format!("invalid size {:?}: {}", s, e))?;
+    value
+        .checked_mul(1 << shift)
+        .ok_or_else(|| format!("size {:?} is too large", s))
+}
+
+/// Parse a count that must be positive, such as a record size or a batch size.
+pub fn parse_count(s: &str) -> Result<usize, String> {
+    match s.trim().parse::<usize>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(value) => Ok(value),
+        Err(e) => Err(format!("invalid count {:?}: {}", s, e)),
+    }
 }
 
 #[cfg(test)]
@@ -340,7 +708,10 @@ mod test {
     use crate::math::int_mod_cyclo::IntModCyclo;
     use crate::math::int_mod_poly::IntModPoly;
     use crate::math::matrix::Matrix;
+    use crate::math::simd_utils::simd_lanes;
+    use crate::pir::error::RespireError;
     use crate::pir::respire::Respire;
+    use std::sync::Arc;
 
     #[test]
     fn test_regev() {
@@ -425,7 +796,7 @@ mod test {
 
     #[test]
     fn test_post_process_only() {
-        let (qk, pp) = RespireTest::setup(); //None);
+        let (qk, pp) = RespireTest::setup(None);
         let (_, s_vec, _) = &qk;
         let mut m = <RespireTest as Respire>::RecordPackedSmall::zero();
         for i in 0..RESPIRE_TEST_PARAMS.N_VEC {
@@ -459,4 +830,253 @@ mod test {
     fn test_respire_one() {
         run_pir::<RespireTest, _>([711_711].into_iter());
     }
+
+    #[test]
+    fn test_update_records() {
+        let record = |i: usize, salt: u8| {
+            let bytes = (0..RespireTestSmall::BYTES_PER_RECORD)
+                .map(|j| (i * 3 + j) as u8 ^ salt)
+                .collect::<Vec<_>>();
+            <RespireTestSmall as PIR>::RecordBytes::from_bytes(&bytes).unwrap()
+        };
+        let last = RespireTestSmall::NUM_RECORDS - 1;
+        let (mut db, _) = RespireTestSmall::encode_db(|i| record(i, 0), None);
+        let old = db.clone();
+        let updates = [
+            (0, record(0, 1)),
+            (5, record(5, 1)),
+            (last, record(last, 1)),
+            (5, record(5, 2)),
+        ];
+        RespireTestSmall::update_records(&mut db, &updates).unwrap();
+
+        let (expected, _) = RespireTestSmall::encode_db(
+            |i| match i {
+                5 => record(i, 2),
+                i if i == 0 || i == last => record(i, 1),
+                i => record(i, 0),
+            },
+            None,
+        );
+        assert_eq!(simd_lanes(&db.to_vec()), simd_lanes(&expected.to_vec()));
+        assert!(db.num_changed_blocks() < db.num_blocks());
+
+        // The database was shared, so the old snapshot is untouched
+        let (original, _) = RespireTestSmall::encode_db(|i| record(i, 0), None);
+        assert_eq!(simd_lanes(&old.to_vec()), simd_lanes(&original.to_vec()));
+        assert_eq!(old.num_changed_blocks(), 0);
+
+        let mut failed = db.clone();
+        assert!(matches!(
+            RespireTestSmall::update_records(
+                &mut failed,
+                &[(RespireTestSmall::DB_SIZE, record(0, 1))]
+            ),
+            Err(RespireError::UnknownRecord { .. })
+        ));
+        assert!(Arc::ptr_eq(&failed, &db));
+    }
+
+    #[test]
+    fn test_partial_batch() {
+        type ThePIR = CuckooBaseTestSmall;
+        let record = |i: usize| {
+            let bytes = (0..ThePIR::BYTES_PER_RECORD)
+                .map(|j| (i * 5 + j) as u8)
+                .collect::<Vec<_>>();
+            <ThePIR as PIR>::RecordBytes::from_bytes(&bytes).unwrap()
+        };
+        let (db, db_hint) = ThePIR::encode_db(record, None);
+        let (qk, pp) = ThePIR::setup(None);
+        let indices = [5, ThePIR::NUM_RECORDS - 1, 17];
+        let expected = indices.iter().map(|&i| record(i)).collect_vec();
+
+        let (q, st) = ThePIR::query(&qk, &indices, &db_hint, None).unwrap();
+        assert_eq!(q.len(), ThePIR::BATCH_SIZE);
+        let r = ThePIR::answer(&pp, &db, &q, None).unwrap();
+        assert_eq!(ThePIR::extract(&qk, &r, &st, None).unwrap(), expected);
+        let (full_q, _) = ThePIR::query(&qk, &[0; ThePIR::BATCH_SIZE], &db_hint, None).unwrap();
+        let full_r = ThePIR::answer(&pp, &db, &full_q, None).unwrap();
+        let padded_size = bincode::serialized_size(&r).unwrap();
+        assert_eq!(padded_size, bincode::serialized_size(&full_r).unwrap());
+
+        // Three records fit in one of the two ring elements of the response vector
+        let (q, st) = ThePIR::query_truncated(&qk, &indices, &db_hint, None).unwrap();
+        assert_eq!(q.len(), indices.len());
+        let r = ThePIR::answer(&pp, &db, &q, None).unwrap();
+        assert_eq!(r[0].1.len(), 1);
+        assert!(bincode::serialized_size(&r).unwrap() < padded_size);
+        assert_eq!(ThePIR::extract(&qk, &r, &st, None).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_time_stats() {
+        type ThePIR = CuckooBaseTestSmall;
+        let mut init_times = Stats::new();
+        let (db, db_hint) = ThePIR::encode_db(|_| Default::default(), Some(&mut init_times));
+        let (qk, pp) = ThePIR::setup(Some(&mut init_times));
+        let names = |stats: &Stats<Duration>| stats.as_vec().iter().map(|x| x.0).collect_vec();
+        assert_eq!(names(&init_times), ["encode", "setup"]);
+
+        // The queries are answered in parallel, each timing into its own stats
+        let mut trial_times = Stats::new();
+        let (q, st) = ThePIR::query(&qk, &[1, 2, 3], &db_hint, Some(&mut trial_times)).unwrap();
+        let r = ThePIR::answer(&pp, &db, &q, Some(&mut trial_times)).unwrap();
+        ThePIR::extract(&qk, &r, &st, Some(&mut trial_times)).unwrap();
+        assert_eq!(
+            names(&trial_times),
+            [
+                "query",
+                "answer_query_expand_reg",
+                "answer_query_expand_gsw",
+                "answer_query_expand_reg_to_gsw",
+                "answer_first_dim",
+                "answer_fold",
+                "answer_rotate",
+                "answer_project",
+                "answer_compress",
+                "extract",
+            ]
+        );
+
+        let mut merged = Stats::new();
+        merged.merge(&trial_times);
+        merged.merge(&trial_times);
+        assert_eq!(merged.as_vec()[0].1, 2 * trial_times.as_vec()[0].1);
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("256"), Ok(256));
+        assert_eq!(parse_size("64k"), Ok(64 << 10));
+        assert_eq!(parse_size("256m"), Ok(256 << 20));
+        assert_eq!(parse_size("1G"), Ok(1 << 30));
+        assert!(parse_size("").is_err());
+        assert!(parse_size("m").is_err());
+        assert!(parse_size("1.5g").is_err());
+    }
+
+    #[test]
+    fn test_parse_count() {
+        assert_eq!(parse_count("16"), Ok(16));
+        assert!(parse_count("0").is_err());
+        assert!(parse_count("-1").is_err());
+    }
+
+    #[test]
+    fn test_bench_report() {
+        type ThePIR = RespireTestSmall;
+        let run_result = run_pir::<ThePIR, _>([3, 7].into_iter());
+        let report = BenchReport::new(ThePIR::summary(), &run_result);
+        assert_eq!(report.summary.num_records, ThePIR::NUM_RECORDS);
+        assert_eq!(report.summary.query_size, ThePIR::summary().query_size);
+        assert_eq!(
+            report
+                .trials
+                .iter()
+                .map(|t| t.indices.clone())
+                .collect_vec(),
+            [[3], [7]]
+        );
+        assert!(report.trials.iter().all(|t| t.correct));
+
+        let (name, phase) = &report.phases.0[0];
+        assert_eq!(*name, "query");
+        let times = report.trials.iter().map(|t| t.times_ns.0[0].1 as f64);
+        let mean = times.clone().sum::<f64>() / 2_f64;
+        let stddev = (times.map(|x| (x - mean).powi(2)).sum::<f64>() / 2_f64).sqrt();
+        assert!((phase.stddev_percent - stddev / mean * 100_f64).abs() < 1e-6);
+
+        let rows = report.csv_rows();
+        let value = |key: &str| rows.iter().find(|row| row.0 == key).unwrap().1.clone();
+        assert_eq!(value("trials.1.indices.0"), "7");
+        assert_eq!(value("trials.0.correct"), "true");
+        assert_eq!(value("summary.batch_size"), ThePIR::BATCH_SIZE.to_string());
+        assert!(rows
+            .iter()
+            .any(|row| row.0 == "phases.answer_compress.mean_ns"));
+    }
+
+    #[test]
+    fn test_noise_profile() {
+        type ThePIR = CuckooBaseTestSmall;
+        let report = run_noise_profile::<ThePIR>(2);
+        assert_eq!(report.correct, 2);
+        assert_eq!(
+            report.phases.0.iter().map(|x| x.0).collect_vec(),
+            [
+                "expand",
+                "first_dim",
+                "fold",
+                "rotate",
+                "project",
+                "vectorize",
+                "compress"
+            ]
+        );
+        let batch = ThePIR::BATCH_SIZE as u64;
+        let (name, expand) = &report.phases.0[0];
+        assert_eq!(*name, "expand");
+        assert_eq!(
+            expand.samples,
+            2 * batch * (ThePIR::PACKED_DIM1_SIZE * ThePIR::params().D1) as u64
+        );
+        for (name, phase) in report.phases.0.iter() {
+            // The analysis bounds the noise from above
+            assert!(phase.ratio <= 1_f64, "{}: {:?}", name, phase);
+            assert_eq!(phase.histogram.iter().sum::<u64>(), phase.samples);
+            assert!(phase.max_abs < 1 << (phase.histogram.len() - 1));
+        }
+
+        let rows = csv_rows(&report);
+        assert!(rows
+            .iter()
+            .any(|row| row.0 == "phases.fold.estimated_variance"));
+    }
+
+    #[test]
+    fn test_malformed() {
+        type ThePIR = RespireTestSmall;
+        let (db, db_hint) = ThePIR::encode_db(|_| Default::default(), None);
+        let (qk, pp) = ThePIR::setup(None);
+        assert!(matches!(
+            ThePIR::query(&qk, &[0, 1], &db_hint, None),
+            Err(RespireError::BatchTooLarge { len: 2, max: 1 })
+        ));
+        assert!(matches!(
+            ThePIR::query(&qk, &[ThePIR::NUM_RECORDS], &db_hint, None),
+            Err(RespireError::UnknownRecord { .. })
+        ));
+
+        let (q, st) = ThePIR::query(&qk, &[3], &db_hint, None).unwrap();
+        let mut short = q.clone();
+        Arc::make_mut(&mut short[0].0 .1).pop();
+        assert!(matches!(
+            ThePIR::answer(&pp, &db, &short, None),
+            Err(RespireError::WrongLength { .. })
+        ));
+        let mut unreduced = q.clone();
+        Arc::make_mut(&mut unreduced[0].1 .1)[0] =
+            bincode::deserialize(&u64::MAX.to_le_bytes()).unwrap();
+        assert!(matches!(
+            ThePIR::answer(&pp, &db, &unreduced, None),
+            Err(RespireError::CoefficientOutOfRange { .. })
+        ));
+        assert!(matches!(
+            ThePIR::answer(&pp, &db, &vec![q[0].clone(); 2], None),
+            Err(RespireError::BatchTooLarge { .. })
+        ));
+
+        let mut r = ThePIR::answer(&pp, &db, &q, None).unwrap();
+        let extra = r[0].1.clone();
+        r[0].1.extend(extra);
+        assert!(matches!(
+            ThePIR::extract(&qk, &r, &st, None),
+            Err(RespireError::WrongLength { .. })
+        ));
+        assert!(matches!(
+            ThePIR::extract(&qk, &vec![], &st, None),
+            Err(RespireError::WrongLength { .. })
+        ));
+    }
 }
@@ -0,0 +1,102 @@
+//! The server side of RESPIRE: owns the database and the public parameters of its clients only.
+//! It never sees a query key, so no noise is measured while answering.
+//!
+//! Records can be updated while queries are being answered: every query is answered against the
+//! database snapshot current when it arrived, see [`crate::pir::snapshot`].
+//!
+//! The server works for any [`PIR`] built on RESPIRE, e.g. cuckoo configurations too; a RESPIRE
+//! query is answered with [`Respire::answer_one`] and [`Respire::answer_compress_chunk`].
+
+use crate::pir::error::RespireError;
+use crate::pir::pir::PIR;
+use crate::pir::respire::{Respire, RespireParamsExpanded};
+use crate::pir::serialization::{deserialize_public_params, SerializationError};
+use crate::pir::session::{ClientId, SessionError, SessionRegistry};
+use crate::pir::snapshot::{Snapshot, Versioned};
+
+pub struct RespireServer<ThePIR: PIR> {
+    db: Versioned<ThePIR::Database>,
+    sessions: SessionRegistry<ThePIR::PublicParams>,
+    /// Memory taken by the public parameters of one client
+    pp_size: usize,
+}
+
+impl<ThePIR: PIR> RespireServer<ThePIR>
+where
+    ThePIR::Database: Clone,
+{
+    ///
+    /// Serve `db` with the (base) RESPIRE parameters `params`, keeping the public parameters of at
+    /// most `max_session_bytes` worth of clients. This must be enough for at least one client.
+    ///
+    pub fn with_params(
+        db: ThePIR::Database,
+        params: &RespireParamsExpanded,
+        max_session_bytes: usize,
+    ) -> Self {
+        let pp_size = params.public_params_memory_size();
+        assert!(
+            max_session_bytes >= pp_size,
+            "session memory cap is too small for a single client"
+        );
+        Self {
+            db: Versioned::new(db),
+            sessions: SessionRegistry::new(max_session_bytes),
+            pp_size,
+        }
+    }
+
+    pub fn database(&self) -> Snapshot<ThePIR::Database> {
+        self.db.snapshot()
+    }
+
+    pub fn sessions(&self) -> &SessionRegistry<ThePIR::PublicParams> {
+        &self.sessions
+    }
+
+    pub fn register_client(&self, pp: ThePIR::PublicParams) -> Result<ClientId, SessionError> {
+        self.sessions.insert(pp, self.pp_size)
+    }
+
+    /// Fails without doing any work if the query is malformed or the client is unknown.
+    pub fn answer(
+        &self,
+        client: ClientId,
+        q: &ThePIR::Query,
+    ) -> Result<ThePIR::Response, RespireError> {
+        let pp = self.sessions.get(client)?;
+        let db = self.db.snapshot();
+        ThePIR::answer(&pp, &db.value, q, None)
+    }
+}
+
+impl<R: Respire> RespireServer<R>
+where
+    <R as PIR>::Database: Clone,
+{
+    /// See [`Self::with_params`].
+    pub fn new(db: <R as PIR>::Database, max_session_bytes: usize) -> Self {
+        Self::with_params(db, &R::params(), max_session_bytes)
+    }
+
+    ///
+    /// Replace the given records, see [`Respire::update_records`]. Returns the new database epoch.
+    /// Queries keep being answered while the records are encoded.
+    ///
+    pub fn update_records(
+        &self,
+        updates: &[(usize, <R as PIR>::RecordBytes)],
+    ) -> Result<u64, RespireError> {
+        self.db.update(|db| {
+            let mut db = db.clone();
+            R::update_records(&mut db, updates)?;
+            Ok(db)
+        })
+    }
+
+    pub fn register_client_bytes(&self, pp_bytes: &[u8]) -> Result<ClientId, SerializationError> {
+        let pp = deserialize_public_params::<R>(pp_bytes)?;
+        // All clients' public parameters have the same size, which fits by the check in new
+        Ok(self.register_client(pp).unwrap())
+    }
+}
@@ -0,0 +1,336 @@
+//! Lattice security estimates for parameter sets.
+//!
+//! A parameter set relies on three RLWE instances: the scalar encryptions and key switching keys
+//! (ring dimension `D1`, modulus `Q1`, secret uniform in `[-SECRET_BOUND, SECRET_BOUND)`), the
+//! vector encryptions (`D1`, `Q1`, Gaussian secret), and the compression key, whose target secret
+//! lives in the subring of dimension `D2` and so splits into RLWE instances of dimension `D2`
+//! modulo `Q2`.
+//!
+//! Each instance is estimated in the core-SVP model, where BKZ with block size `beta` costs
+//! `2^(0.292 beta)`, against two primal attacks over the number of samples `m`:
+//! - uSVP, using the "2016 estimate": BKZ-`beta` recovers the embedded short vector once
+//!   `sigma sqrt(beta) <= delta^(2 beta - d) Vol^(1 / d)` under the geometric series assumption.
+//! - BDD, where BKZ-`beta` is followed by a sieve in the last `eta >= beta` dimensions, which
+//!   succeeds once `sigma sqrt(eta) <= delta^(2 eta - d) Vol^(1 / d)`.
+//!
+//! Secrets narrower than the error are rescaled to the error's width, as the lattice estimator
+//! does. These are approximations for comparing parameter sets and catching mistakes, and are no
+//! substitute for running the lattice estimator on a final parameter set.
+
+use crate::pir::respire::{RespireParams, RespireParamsExpanded};
+use serde::Serialize;
+use std::f64::consts::{E, PI};
+use std::fmt;
+
+/// log2 of the core-SVP cost of BKZ per unit of block size (classical sieving)
+pub const CORE_SVP_EXPONENT: f64 = 0.292;
+
+/// Security, in core-SVP bits, that the standard parameter sets must reach
+pub const MIN_SECURITY_BITS: f64 = 96.0;
+
+/// Smallest block size considered; BKZ with smaller blocks is cheap regardless
+const MIN_BLOCK_SIZE: usize = 40;
+
+/// Samples are tried in steps of `n / SAMPLE_STEPS`
+const SAMPLE_STEPS: usize = 128;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct RlweInstance {
+    pub name: &'static str,
+    /// Ring dimension
+    pub n: usize,
+    pub log2_q: f64,
+    pub secret_stddev: f64,
+    pub error_stddev: f64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct AttackCost {
+    pub beta: usize,
+    /// Sieving dimension of the final BDD step; equal to `beta` for uSVP
+    pub eta: usize,
+    /// Number of RLWE samples (coefficients) used
+    pub samples: usize,
+    /// log2 of the core-SVP cost
+    pub bits: f64,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct InstanceSecurity {
+    pub instance: RlweInstance,
+    pub usvp: AttackCost,
+    pub bdd: AttackCost,
+}
+
+impl InstanceSecurity {
+    pub fn bits(&self) -> f64 {
+        self.usvp.bits.min(self.bdd.bits)
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct SecurityEstimate {
+    pub instances: Vec<InstanceSecurity>,
+}
+
+impl SecurityEstimate {
+    /// Security of the weakest instance
+    pub fn bits(&self) -> f64 {
+        self.instances
+            .iter()
+            .map(|i| i.bits())
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+impl fmt::Display for SecurityEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1} bits (core-SVP)", self.bits())?;
+        for i in self.instances.iter() {
+            write!(
+                f,
+                "; {}: {:.1} (uSVP beta = {}, BDD beta = {}, eta = {})",
+                i.instance.name,
+                i.bits(),
+                i.usvp.beta,
+                i.bdd.beta,
+                i.bdd.eta
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parameters rejected by [`RespireParams::expand_secure`].
+#[derive(Debug)]
+pub struct InsecureParams {
+    pub estimate: SecurityEstimate,
+    pub min_bits: f64,
+}
+
+impl fmt::Display for InsecureParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "parameters give {}, below the required {} bits",
+            self.estimate, self.min_bits
+        )
+    }
+}
+
+impl std::error::Error for InsecureParams {}
+
+impl RespireParams {
+    ///
+    /// Expand the parameters, refusing them if the estimated security of any RLWE instance is
+    /// below `min_bits` (see [`crate::pir::security`]).
+    ///
+    pub fn expand_secure(&self, min_bits: f64) -> Result<RespireParamsExpanded, InsecureParams> {
+        let params = self.expand();
+        let estimate = estimate_security(&params);
+        if estimate.bits() >= min_bits {
+            Ok(params)
+        } else {
+            Err(InsecureParams { estimate, min_bits })
+        }
+    }
+}
+
+/// Standard deviation of a discrete Gaussian of the given width (`exp(-pi x^2 / width^2)`)
+fn width_to_stddev(width_millionths: u64) -> f64 {
+    (width_millionths as f64 / 1_000_000_f64) / (2_f64 * PI).sqrt()
+}
+
+pub fn rlwe_instances(params: &RespireParamsExpanded) -> [RlweInstance; 3] {
+    // Uniform on [-B, B): the second moment is the variance (4 B^2 - 1) / 12 plus the mean 1/2
+    // squared
+    let bound = params.SECRET_BOUND as f64;
+    let uniform_stddev = ((4_f64 * bound * bound - 1_f64) / 12_f64 + 0.25_f64).sqrt();
+    [
+        RlweInstance {
+            name: "scalar",
+            n: params.D1,
+            log2_q: (params.Q1 as f64).log2(),
+            secret_stddev: uniform_stddev,
+            error_stddev: width_to_stddev(params.ERROR_WIDTH_MILLIONTHS),
+        },
+        RlweInstance {
+            name: "vector",
+            n: params.D1,
+            log2_q: (params.Q1 as f64).log2(),
+            secret_stddev: width_to_stddev(params.SECRET_WIDTH_VEC_MILLIONTHS),
+            error_stddev: width_to_stddev(params.ERROR_WIDTH_VEC_MILLIONTHS),
+        },
+        RlweInstance {
+            name: "compress",
+            n: params.D2,
+            log2_q: (params.Q2 as f64).log2(),
+            secret_stddev: width_to_stddev(params.SECRET_WIDTH_COMPRESS_MILLIONTHS),
+            error_stddev: width_to_stddev(params.ERROR_WIDTH_COMPRESS_MILLIONTHS),
+        },
+    ]
+}
+
+pub fn estimate_security(params: &RespireParamsExpanded) -> SecurityEstimate {
+    SecurityEstimate {
+        instances: rlwe_instances(params)
+            .into_iter()
+            .map(|instance| InstanceSecurity {
+                instance,
+                usvp: estimate_usvp(&instance),
+                bdd: estimate_bdd(&instance),
+            })
+            .collect(),
+    }
+}
+
+/// log2 of the root Hermite factor of BKZ-`beta`
+fn log2_delta(beta: usize) -> f64 {
+    let beta = beta as f64;
+    ((PI * beta).log2() / beta + beta.log2() - (2_f64 * PI * E).log2()) / (2_f64 * (beta - 1_f64))
+}
+
+// The embedding lattice for m samples: its dimension, log2 of its volume, and log2 of the
+// per-coordinate standard deviation of the short vector
+fn embedding(instance: &RlweInstance, m: usize) -> (usize, f64, f64) {
+    let n = instance.n as f64;
+    let m_f = m as f64;
+    let scale = (instance.error_stddev / instance.secret_stddev).max(1_f64);
+    let secret_stddev = instance.secret_stddev * scale;
+    let log2_vol = m_f * instance.log2_q + n * scale.log2();
+    let sigma_sq = (m_f * instance.error_stddev.powi(2) + n * secret_stddev.powi(2)) / (m_f + n);
+    (instance.n + m + 1, log2_vol, sigma_sq.log2() / 2_f64)
+}
+
+// Whether a projection of dimension `k` of the short vector is found after BKZ-`beta`
+fn succeeds(beta: usize, k: usize, d: usize, log2_vol: f64, log2_sigma: f64) -> bool {
+    let lhs = log2_sigma + (k as f64).log2() / 2_f64;
+    let rhs = (2_f64 * k as f64 - d as f64) * log2_delta(beta) + log2_vol / d as f64;
+    lhs <= rhs
+}
+
+fn sample_counts(instance: &RlweInstance) -> impl Iterator<Item = usize> {
+    let step = (instance.n / SAMPLE_STEPS).max(1);
+    (1..=2 * SAMPLE_STEPS).map(move |i| i * step)
+}
+
+// No attack found (yet)
+fn unbroken() -> AttackCost {
+    AttackCost {
+        beta: usize::MAX,
+        eta: usize::MAX,
+        samples: 0,
+        bits: f64::INFINITY,
+    }
+}
+
+// Block sizes that could still beat `best`, as the cost is at least that of BKZ itself
+fn block_sizes(best: &AttackCost, d: usize) -> std::ops::Range<usize> {
+    let limit = (best.bits / CORE_SVP_EXPONENT).ceil().min(d as f64) as usize;
+    MIN_BLOCK_SIZE..limit
+}
+
+pub fn estimate_usvp(instance: &RlweInstance) -> AttackCost {
+    let mut best = unbroken();
+    for m in sample_counts(instance) {
+        let (d, log2_vol, log2_sigma) = embedding(instance, m);
+        let found =
+            block_sizes(&best, d).find(|&beta| succeeds(beta, beta, d, log2_vol, log2_sigma));
+        if let Some(beta) = found {
+            best = AttackCost {
+                beta,
+                eta: beta,
+                samples: m,
+                bits: CORE_SVP_EXPONENT * beta as f64,
+            };
+        }
+    }
+    best
+}
+
+pub fn estimate_bdd(instance: &RlweInstance) -> AttackCost {
+    let cost = |beta: usize, eta: usize| {
+        let (beta, eta) = (beta as f64, eta as f64);
+        CORE_SVP_EXPONENT * eta.max(beta)
+            + (1_f64 + (-CORE_SVP_EXPONENT * (eta - beta).abs()).exp2()).log2()
+    };
+    let mut best = unbroken();
+    for m in sample_counts(instance) {
+        let (d, log2_vol, log2_sigma) = embedding(instance, m);
+        for beta in block_sizes(&best, d) {
+            // The condition only gets easier as eta grows, so find the smallest eta that works
+            if !succeeds(beta, d - 1, d, log2_vol, log2_sigma) {
+                continue;
+            }
+            let (mut lo, mut hi) = (beta, d - 1);
+            while lo < hi {
+                let mid = (lo + hi) / 2;
+                if succeeds(beta, mid, d, log2_vol, log2_sigma) {
+                    hi = mid;
+                } else {
+                    lo = mid + 1;
+                }
+            }
+            let bits = cost(beta, lo);
+            if bits < best.bits {
+                best = AttackCost {
+                    beta,
+                    eta: lo,
+                    samples: m,
+                    bits,
+                };
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::respire_harness::FactoryParams;
+
+    #[test]
+    fn test_security() {
+        for factory in [
+            FactoryParams::single_record_256(9, 9),
+            FactoryParams::batch_256(25, 7, 8, 7),
+        ] {
+            let estimate = estimate_security(&factory.expand().expand());
+            assert_eq!(estimate.instances.len(), 3);
+            assert!(estimate.bits() > 96_f64, "{}", estimate);
+            for i in estimate.instances.iter() {
+                assert!(i.bdd.eta >= i.bdd.beta);
+                assert!(i.bits() <= i.usvp.bits);
+            }
+            assert!(factory.expand().expand_secure(MIN_SECURITY_BITS).is_ok());
+        }
+
+        // Security grows with the dimension and shrinks with the modulus
+        let instance = RlweInstance {
+            name: "test",
+            n: 1024,
+            log2_q: 32_f64,
+            secret_stddev: 3.2_f64,
+            error_stddev: 3.2_f64,
+        };
+        let bits = estimate_usvp(&instance).bits;
+        let larger_n = estimate_usvp(&RlweInstance {
+            n: 2048,
+            ..instance
+        });
+        let larger_q = estimate_usvp(&RlweInstance {
+            log2_q: 48_f64,
+            ..instance
+        });
+        assert!(larger_n.bits > bits && larger_q.bits < bits);
+
+        // A small compression ring is rejected
+        let params = RespireParams {
+            D2: 256,
+            ..FactoryParams::batch_256(25, 7, 8, 7).expand()
+        };
+        let err = params.expand_secure(128_f64).unwrap_err();
+        assert!(err.estimate.bits() < 128_f64);
+    }
+}
@@ -0,0 +1,216 @@
+//! Versioned serialization of query keys and public parameters.
+//!
+//! Unlike queries and responses, which are consumed immediately, query keys and public parameters
+//! are long-lived: clients persist their keys, and servers receive public parameters once and keep
+//! them. Both are therefore written with a header recording the format version and the full
+//! [`RespireParamsExpanded`] they were generated for, and decoding refuses objects produced for a
+//! different parameter set.
+//!
+//! Layout: `magic (4 bytes) || version (u16) || kind || params || body`, all bincode encoded.
+
+use crate::pir::pir::PIR;
+use crate::pir::respire::{Respire, RespireParamsExpanded};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const FORMAT_MAGIC: [u8; 4] = *b"RSPR";
+pub const FORMAT_VERSION: u16 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ObjectKind {
+    QueryKey,
+    PublicParams,
+    /// Header of an encoded database file, see [`crate::pir::db_file`]
+    Database,
+}
+
+#[derive(Debug)]
+pub enum SerializationError {
+    BadMagic,
+    UnsupportedVersion(u16),
+    WrongKind {
+        expected: ObjectKind,
+        found: ObjectKind,
+    },
+    ParamsMismatch {
+        expected: Box<RespireParamsExpanded>,
+        found: Box<RespireParamsExpanded>,
+    },
+    TrailingBytes(usize),
+    Malformed(bincode::Error),
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::BadMagic => write!(f, "not a serialized RESPIRE object"),
+            SerializationError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported format version {} (expected {})",
+                v, FORMAT_VERSION
+            ),
+            SerializationError::WrongKind { expected, found } => {
+                write!(f, "expected {:?}, found {:?}", expected, found)
+            }
+            SerializationError::ParamsMismatch { expected, found } => write!(
+                f,
+                "object was generated for different parameters (expected {:?}, found {:?})",
+                expected, found
+            ),
+            SerializationError::TrailingBytes(n) => {
+                write!(f, "{} unexpected trailing byte(s)", n)
+            }
+            SerializationError::Malformed(e) => write!(f, "malformed object: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {}
+
+impl From<bincode::Error> for SerializationError {
+    fn from(e: bincode::Error) -> Self {
+        SerializationError::Malformed(e)
+    }
+}
+
+pub fn serialize_with_header<T: Serialize>(
+    kind: ObjectKind,
+    params: &RespireParamsExpanded,
+    value: &T,
+) -> Vec<u8> {
+    let mut result = Vec::new();
+    bincode::serialize_into(&mut result, &(FORMAT_MAGIC, FORMAT_VERSION)).unwrap();
+    bincode::serialize_into(&mut result, &(kind, params)).unwrap();
+    bincode::serialize_into(&mut result, value).unwrap();
+    result
+}
+
+pub fn deserialize_with_header<T: DeserializeOwned>(
+    kind: ObjectKind,
+    params: &RespireParamsExpanded,
+    bytes: &[u8],
+) -> Result<T, SerializationError> {
+    let mut reader = bytes;
+    let (magic, version): ([u8; 4], u16) =
+        bincode::deserialize_from(&mut reader).map_err(|_| SerializationError::BadMagic)?;
+    if magic != FORMAT_MAGIC {
+        return Err(SerializationError::BadMagic);
+    }
+    if version != FORMAT_VERSION {
+        return Err(SerializationError::UnsupportedVersion(version));
+    }
+
+    let (found_kind, found_params): (ObjectKind, RespireParamsExpanded) =
+        bincode::deserialize_from(&mut reader)?;
+    if found_kind != kind {
+        return Err(SerializationError::WrongKind {
+            expected: kind,
+            found: found_kind,
+        });
+    }
+    if found_params != *params {
+        return Err(SerializationError::ParamsMismatch {
+            expected: Box::new(*params),
+            found: Box::new(found_params),
+        });
+    }
+
+    let value = bincode::deserialize_from(&mut reader)?;
+    if !reader.is_empty() {
+        return Err(SerializationError::TrailingBytes(reader.len()));
+    }
+    Ok(value)
+}
+
+pub fn serialize_query_key<ThePIR: Respire>(qk: &<ThePIR as PIR>::QueryKey) -> Vec<u8> {
+    serialize_with_header(ObjectKind::QueryKey, &ThePIR::params(), qk)
+}
+
+pub fn deserialize_query_key<ThePIR: Respire>(
+    bytes: &[u8],
+) -> Result<<ThePIR as PIR>::QueryKey, SerializationError> {
+    deserialize_with_header(ObjectKind::QueryKey, &ThePIR::params(), bytes)
+}
+
+pub fn serialize_public_params<ThePIR: Respire>(pp: &<ThePIR as PIR>::PublicParams) -> Vec<u8> {
+    serialize_with_header(ObjectKind::PublicParams, &ThePIR::params(), pp)
+}
+
+pub fn deserialize_public_params<ThePIR: Respire>(
+    bytes: &[u8],
+) -> Result<<ThePIR as PIR>::PublicParams, SerializationError> {
+    deserialize_with_header(ObjectKind::PublicParams, &ThePIR::params(), bytes)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::respire_harness::{RespireTestSmall, RESPIRE_TEST_PARAMS};
+
+    #[test]
+    fn test_roundtrip() {
+        let (qk, pp) = RespireTestSmall::setup(None);
+
+        let qk_bytes = serialize_query_key::<RespireTestSmall>(&qk);
+        let qk_decoded = deserialize_query_key::<RespireTestSmall>(&qk_bytes).unwrap();
+        assert_eq!(qk, qk_decoded);
+
+        let pp_bytes = serialize_public_params::<RespireTestSmall>(&pp);
+        let pp_decoded = deserialize_public_params::<RespireTestSmall>(&pp_bytes).unwrap();
+        assert_eq!(pp, pp_decoded);
+    }
+
+    #[test]
+    fn test_public_params_seeded() {
+        let (_, pp) = RespireTestSmall::setup(None);
+        let seeded = RespireTestSmall::public_params_compress(&pp);
+        let expanded = RespireTestSmall::public_params_expand(&seeded).unwrap();
+        assert_eq!(pp, expanded);
+
+        // Only the non-random halves (and the seed) are sent
+        let pp_bytes = serialize_public_params::<RespireTestSmall>(&pp);
+        let expanded_size = bincode::serialized_size(&*pp).unwrap() as usize;
+        assert!(pp_bytes.len() < expanded_size * 2 / 3);
+
+        let memory_size = RespireTestSmall::params().public_params_memory_size();
+        assert!(expanded_size.abs_diff(memory_size) < expanded_size / 100);
+    }
+
+    #[test]
+    fn test_rejects_mismatch() {
+        let (qk, _) = RespireTestSmall::setup(None);
+        let qk_bytes = serialize_query_key::<RespireTestSmall>(&qk);
+
+        assert!(matches!(
+            deserialize_public_params::<RespireTestSmall>(&qk_bytes),
+            Err(SerializationError::WrongKind { .. })
+        ));
+        assert!(matches!(
+            deserialize_with_header::<<RespireTestSmall as PIR>::QueryKey>(
+                ObjectKind::QueryKey,
+                &RESPIRE_TEST_PARAMS,
+                &qk_bytes
+            ),
+            Err(SerializationError::ParamsMismatch { .. })
+        ));
+
+        let mut bad_magic = qk_bytes.clone();
+        bad_magic[0] ^= 1;
+        assert!(matches!(
+            deserialize_query_key::<RespireTestSmall>(&bad_magic),
+            Err(SerializationError::BadMagic)
+        ));
+
+        let mut bad_version = qk_bytes.clone();
+        bad_version[4] ^= 1;
+        assert!(matches!(
+            deserialize_query_key::<RespireTestSmall>(&bad_version),
+            Err(SerializationError::UnsupportedVersion(_))
+        ));
+
+        assert!(
+            deserialize_query_key::<RespireTestSmall>(&qk_bytes[..qk_bytes.len() - 1]).is_err()
+        );
+    }
+}
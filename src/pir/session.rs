@@ -0,0 +1,211 @@
+//! Per-client public parameter storage for servers answering many clients.
+//!
+//! Every client sends its public parameters once and is assigned a [`ClientId`]; queries then only
+//! carry the ID. Public parameters are large (tens of MB expanded), so the registry is bounded by
+//! a memory cap, evicting the least recently used sessions first. An evicted client has to send
+//! its public parameters again.
+
+use rand::{thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ClientId(pub u64);
+
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SessionError {
+    /// The client never registered, or its session was evicted
+    UnknownClient(ClientId),
+    /// The public parameters alone exceed the memory cap
+    TooLarge { size: usize, max_bytes: usize },
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::UnknownClient(id) => write!(f, "unknown client {}", id),
+            SessionError::TooLarge { size, max_bytes } => write!(
+                f,
+                "public parameters of {} bytes exceed the session memory cap of {} bytes",
+                size, max_bytes
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+struct Session<PP> {
+    pp: Arc<PP>,
+    size: usize,
+    last_used: u64,
+}
+
+struct Sessions<PP> {
+    entries: HashMap<ClientId, Session<PP>>,
+    // last_used -> client, oldest first
+    lru: BTreeMap<u64, ClientId>,
+    clock: u64,
+    total_bytes: usize,
+}
+
+impl<PP> Sessions<PP> {
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    fn remove(&mut self, id: ClientId) -> Option<Session<PP>> {
+        let session = self.entries.remove(&id)?;
+        self.lru.remove(&session.last_used);
+        self.total_bytes -= session.size;
+        Some(session)
+    }
+}
+
+///
+/// Thread-safe map from [`ClientId`] to public parameters with least-recently-used eviction.
+/// Sizes are supplied by the caller, e.g. from
+/// [`RespireParamsExpanded::public_params_memory_size`](crate::pir::respire::RespireParamsExpanded::public_params_memory_size).
+///
+pub struct SessionRegistry<PP> {
+    max_bytes: usize,
+    sessions: Mutex<Sessions<PP>>,
+}
+
+impl<PP> SessionRegistry<PP> {
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            sessions: Mutex::new(Sessions {
+                entries: HashMap::new(),
+                lru: BTreeMap::new(),
+                clock: 0,
+                total_bytes: 0,
+            }),
+        }
+    }
+
+    ///
+    /// Store `pp` under a fresh random ID, evicting least recently used sessions as needed to stay
+    /// under the memory cap.
+    ///
+    pub fn insert(&self, pp: PP, size: usize) -> Result<ClientId, SessionError> {
+        if size > self.max_bytes {
+            return Err(SessionError::TooLarge {
+                size,
+                max_bytes: self.max_bytes,
+            });
+        }
+
+        let mut sessions = self.sessions.lock().unwrap();
+        while sessions.total_bytes + size > self.max_bytes {
+            let (_, &oldest) = sessions.lru.iter().next().unwrap();
+            sessions.remove(oldest);
+        }
+
+        let mut rng = thread_rng();
+        let id = loop {
+            let id = ClientId(rng.gen());
+            if !sessions.entries.contains_key(&id) {
+                break id;
+            }
+        };
+        let last_used = sessions.tick();
+        sessions.lru.insert(last_used, id);
+        sessions.total_bytes += size;
+        sessions.entries.insert(
+            id,
+            Session {
+                pp: Arc::new(pp),
+                size,
+                last_used,
+            },
+        );
+        Ok(id)
+    }
+
+    ///
+    /// Look up the public parameters of `id`, marking the session as most recently used. The
+    /// returned handle stays valid even if the session is evicted while it is in use.
+    ///
+    pub fn get(&self, id: ClientId) -> Result<Arc<PP>, SessionError> {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = sessions.tick();
+        let session = sessions
+            .entries
+            .get_mut(&id)
+            .ok_or(SessionError::UnknownClient(id))?;
+        let before = std::mem::replace(&mut session.last_used, now);
+        let pp = session.pp.clone();
+        sessions.lru.remove(&before);
+        sessions.lru.insert(now, id);
+        Ok(pp)
+    }
+
+    pub fn remove(&self, id: ClientId) -> bool {
+        self.sessions.lock().unwrap().remove(id).is_some()
+    }
+
+    pub fn contains(&self, id: ClientId) -> bool {
+        self.sessions.lock().unwrap().entries.contains_key(&id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn total_bytes(&self) -> usize {
+        self.sessions.lock().unwrap().total_bytes
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let registry = SessionRegistry::new(100);
+        let a = registry.insert("a", 40).unwrap();
+        let b = registry.insert("b", 40).unwrap();
+        assert_eq!(registry.total_bytes(), 80);
+
+        // Touch a, so b is the least recently used
+        assert_eq!(*registry.get(a).unwrap(), "a");
+        let c = registry.insert("c", 40).unwrap();
+        assert!(registry.contains(a));
+        assert!(!registry.contains(b));
+        assert!(registry.contains(c));
+        assert_eq!(registry.get(b), Err(SessionError::UnknownClient(b)));
+
+        // Needs both slots
+        let d = registry.insert("d", 70).unwrap();
+        assert_eq!(registry.len(), 1);
+        assert_eq!(*registry.get(d).unwrap(), "d");
+
+        assert!(matches!(
+            registry.insert("e", 101),
+            Err(SessionError::TooLarge { .. })
+        ));
+        assert!(registry.remove(d));
+        assert!(registry.is_empty());
+        assert_eq!(registry.total_bytes(), 0);
+    }
+}
@@ -0,0 +1,409 @@
+//! Database shapes that no registered parameter set has exactly.
+//!
+//! Every compiled parameter set fixes the number of records, the record size and the batch size
+//! (see [`crate::pir::dyn_pir`]). [`ShapedPIR`] serves a database of some other [`DbShape`] on top
+//! of one of them, the host. A record is split over `slots` consecutive records of the host (as
+//! [`crate::pir::var_len_pir`] splits values), and a query for a batch is split over as many host
+//! queries as its slots need. All parameters (moduli, ring dimensions and noise) are those of the
+//! host, so the security and error estimates of the host apply, while the shape only changes how
+//! records and batches map onto it.
+
+use crate::pir::db_file::{DbContents, DbFileError};
+use crate::pir::dyn_pir::{
+    DynDatabase, DynDatabaseHint, DynPIR, DynPublicParams, DynQueryKey, DynServer, DynState,
+};
+use crate::pir::error::RespireError;
+use crate::pir::respire::RespireParamsExpanded;
+use crate::pir::respire_harness::{BenchReport, NoiseReport};
+use crate::pir::serialization::SerializationError;
+use crate::pir::session::{ClientId, SessionError};
+use crate::pir::var_len_pir::{join_slots, num_slots, slot_bytes};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+use std::io;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DbShape {
+    pub num_records: usize,
+    pub bytes_per_record: usize,
+    /// Number of records retrieved per query
+    pub batch_size: usize,
+}
+
+impl DbShape {
+    pub fn of_params(params: &RespireParamsExpanded) -> Self {
+        Self {
+            num_records: params.num_records(),
+            bytes_per_record: params.BYTES_PER_RECORD,
+            batch_size: params.BATCH_SIZE,
+        }
+    }
+}
+
+struct RuntimeState {
+    /// States of the host queries for requested records; the remaining host queries are padding
+    states: Vec<DynState>,
+    num_records: usize,
+}
+
+///
+/// A database of any [`DbShape`] on top of a host parameter set, see the module
+/// documentation. Queries and responses are the bincode encoding of the host's queries and
+/// responses, in a `Vec`.
+///
+pub struct ShapedPIR<'a> {
+    name: String,
+    host: &'a dyn DynPIR,
+    shape: DbShape,
+    /// Host records per record
+    slots: usize,
+    /// Host queries per (padded) query
+    host_queries: usize,
+}
+
+impl<'a> ShapedPIR<'a> {
+    ///
+    /// Serve `shape` on `host`, or `None` if the host does not have enough records for it (or the
+    /// shape is empty).
+    ///
+    pub fn new(host: &'a dyn DynPIR, shape: DbShape) -> Option<Self> {
+        if shape.num_records == 0 || shape.bytes_per_record == 0 || shape.batch_size == 0 {
+            return None;
+        }
+        let slots = num_slots(shape.bytes_per_record, host.bytes_per_record());
+        if shape.num_records.checked_mul(slots)? > host.num_records() {
+            return None;
+        }
+        let host_queries = shape
+            .batch_size
+            .checked_mul(slots)?
+            .div_ceil(host.batch_size());
+        Some(Self {
+            name: format!(
+                "{}x{}_{}@{}",
+                shape.num_records,
+                shape.bytes_per_record,
+                shape.batch_size,
+                host.name()
+            ),
+            host,
+            shape,
+            slots,
+            host_queries,
+        })
+    }
+
+    pub fn host(&self) -> &'a dyn DynPIR {
+        self.host
+    }
+
+    pub fn shape(&self) -> DbShape {
+        self.shape
+    }
+
+    pub fn host_queries(&self) -> usize {
+        self.host_queries
+    }
+
+    ///
+    /// Rough server work per query, for choosing a host: the number of host queries times the
+    /// records each of them scans.
+    ///
+    pub fn cost(&self) -> usize {
+        self.host_queries.saturating_mul(self.host.num_records())
+    }
+
+    fn host_contents(&self, contents: &DbContents) -> DbContents {
+        let mut contents = contents.clone();
+        contents.shape = Some(self.shape);
+        contents
+    }
+
+    fn slot_indices(&self, indices: &[usize]) -> Result<Vec<usize>, RespireError> {
+        if indices.len() > self.shape.batch_size {
+            return Err(RespireError::BatchTooLarge {
+                len: indices.len(),
+                max: self.shape.batch_size,
+            });
+        }
+        if let Some(&idx) = indices.iter().find(|&&i| i >= self.shape.num_records) {
+            return Err(RespireError::UnknownRecord {
+                idx,
+                num_records: self.shape.num_records,
+            });
+        }
+        Ok(indices
+            .iter()
+            .flat_map(|&i| i * self.slots..(i + 1) * self.slots)
+            .collect())
+    }
+
+    fn query_impl(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+        truncated: bool,
+    ) -> Result<(Vec<u8>, DynState), RespireError> {
+        let slot_indices = self.slot_indices(indices)?;
+        let mut queries = Vec::with_capacity(self.host_queries);
+        let mut states = Vec::new();
+        for chunk in slot_indices.chunks(self.host.batch_size()) {
+            let (q, st) = if truncated {
+                self.host.query_truncated(qk, chunk, db_hint)?
+            } else {
+                self.host.query(qk, chunk, db_hint)?
+            };
+            queries.push(q);
+            states.push(st);
+        }
+        // Pad with dummy queries, so that the server cannot tell how many records were asked for
+        if !truncated {
+            while queries.len() < self.host_queries {
+                queries.push(self.host.query(qk, &[0], db_hint)?.0);
+            }
+        }
+        let st = RuntimeState {
+            states,
+            num_records: indices.len(),
+        };
+        Ok((bincode::serialize(&queries).unwrap(), DynState::new(st)))
+    }
+}
+
+/// Answer each host query of the serialized query `q` with `answer_host`
+fn answer_host_queries(
+    host_queries: usize,
+    q: &[u8],
+    answer_host: impl Fn(&[u8]) -> Result<Vec<u8>, RespireError>,
+) -> Result<Vec<u8>, RespireError> {
+    let queries: Vec<Vec<u8>> = bincode::deserialize(q)?;
+    if queries.len() > host_queries {
+        return Err(RespireError::WrongLength {
+            what: "shaped query",
+            expected: host_queries,
+            found: queries.len(),
+        });
+    }
+    let responses = queries
+        .iter()
+        .map(|q| answer_host(q))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(bincode::serialize(&responses).unwrap())
+}
+
+/// Server of a [`ShapedPIR`], on a server of its host
+struct ShapedServer<'a> {
+    host: Box<dyn DynServer + 'a>,
+    host_queries: usize,
+}
+
+impl DynServer for ShapedServer<'_> {
+    fn register_client(&self, pp: DynPublicParams) -> Result<ClientId, SessionError> {
+        self.host.register_client(pp)
+    }
+
+    fn answer(&self, client: ClientId, q: &[u8]) -> Result<Vec<u8>, RespireError> {
+        answer_host_queries(self.host_queries, q, |q| self.host.answer(client, q))
+    }
+
+    fn num_sessions(&self) -> usize {
+        self.host.num_sessions()
+    }
+
+    fn session_bytes(&self) -> usize {
+        self.host.session_bytes()
+    }
+}
+
+impl DynPIR for ShapedPIR<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The parameters of the host
+    fn params(&self) -> &RespireParamsExpanded {
+        self.host.params()
+    }
+
+    fn bytes_per_record(&self) -> usize {
+        self.shape.bytes_per_record
+    }
+
+    fn num_records(&self) -> usize {
+        self.shape.num_records
+    }
+
+    fn batch_size(&self) -> usize {
+        self.shape.batch_size
+    }
+
+    fn print_summary(&self) {
+        eprintln!(
+            "{} records of {} bytes, {} per query, on {}: {} slot(s) per record, {} host queries per query",
+            self.shape.num_records,
+            self.shape.bytes_per_record,
+            self.shape.batch_size,
+            self.host.name(),
+            self.slots,
+            self.host_queries
+        );
+        self.host.print_summary();
+    }
+
+    fn encode_db(
+        &self,
+        records_generator: &dyn Fn(usize) -> Vec<u8>,
+    ) -> Result<(DynDatabase, DynDatabaseHint), RespireError> {
+        let host_bytes = self.host.bytes_per_record();
+        let wrong_len = Cell::new(None);
+        let result = self.host.encode_db(&|i| {
+            let (record_idx, slot) = (i / self.slots, i % self.slots);
+            if record_idx >= self.shape.num_records {
+                return vec![0_u8; host_bytes];
+            }
+            let record = records_generator(record_idx);
+            if record.len() != self.shape.bytes_per_record {
+                wrong_len.set(wrong_len.get().or(Some(record.len())));
+                return vec![0_u8; host_bytes];
+            }
+            slot_bytes(record, slot, host_bytes)
+        })?;
+        match wrong_len.get() {
+            Some(found) => Err(RespireError::WrongLength {
+                what: "record",
+                expected: self.shape.bytes_per_record,
+                found,
+            }),
+            None => Ok(result),
+        }
+    }
+
+    fn setup(&self) -> (DynQueryKey, DynPublicParams) {
+        self.host.setup()
+    }
+
+    fn query(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+    ) -> Result<(Vec<u8>, DynState), RespireError> {
+        self.query_impl(qk, indices, db_hint, false)
+    }
+
+    fn query_truncated(
+        &self,
+        qk: &DynQueryKey,
+        indices: &[usize],
+        db_hint: &DynDatabaseHint,
+    ) -> Result<(Vec<u8>, DynState), RespireError> {
+        self.query_impl(qk, indices, db_hint, true)
+    }
+
+    fn answer(
+        &self,
+        pp: &DynPublicParams,
+        db: &DynDatabase,
+        q: &[u8],
+    ) -> Result<Vec<u8>, RespireError> {
+        answer_host_queries(self.host_queries, q, |q| self.host.answer(pp, db, q))
+    }
+
+    fn extract(
+        &self,
+        qk: &DynQueryKey,
+        r: &[u8],
+        st: &DynState,
+    ) -> Result<Vec<Vec<u8>>, RespireError> {
+        let st = st.downcast::<RuntimeState>();
+        let responses: Vec<Vec<u8>> = bincode::deserialize(r)?;
+        if responses.len() < st.states.len() {
+            return Err(RespireError::WrongLength {
+                what: "shaped response",
+                expected: st.states.len(),
+                found: responses.len(),
+            });
+        }
+        let mut slots = Vec::with_capacity(st.num_records * self.slots);
+        for (r, host_st) in responses.iter().zip(st.states.iter()) {
+            slots.extend(self.host.extract(qk, r, host_st)?);
+        }
+        Ok(slots
+            .chunks(self.slots)
+            .take(st.num_records)
+            .map(|parts| {
+                let mut record = join_slots(parts.iter().map(Vec::as_slice));
+                record.truncate(self.shape.bytes_per_record);
+                record
+            })
+            .collect_vec())
+    }
+
+    fn serialize_query_key(&self, qk: &DynQueryKey) -> Vec<u8> {
+        self.host.serialize_query_key(qk)
+    }
+
+    fn deserialize_query_key(&self, bytes: &[u8]) -> Result<DynQueryKey, SerializationError> {
+        self.host.deserialize_query_key(bytes)
+    }
+
+    fn serialize_public_params(&self, pp: &DynPublicParams) -> Vec<u8> {
+        self.host.serialize_public_params(pp)
+    }
+
+    fn deserialize_public_params(
+        &self,
+        bytes: &[u8],
+    ) -> Result<DynPublicParams, SerializationError> {
+        self.host.deserialize_public_params(bytes)
+    }
+
+    fn serialize_db_hint(&self, db_hint: &DynDatabaseHint) -> Vec<u8> {
+        self.host.serialize_db_hint(db_hint)
+    }
+
+    fn deserialize_db_hint(&self, bytes: &[u8]) -> Result<DynDatabaseHint, bincode::Error> {
+        self.host.deserialize_db_hint(bytes)
+    }
+
+    fn server(&self, db: DynDatabase, max_session_bytes: usize) -> Box<dyn DynServer + '_> {
+        Box::new(ShapedServer {
+            host: self.host.server(db, max_session_bytes),
+            host_queries: self.host_queries,
+        })
+    }
+
+    /// The file holds the host database, with the shape in its contents.
+    fn save_db(
+        &self,
+        db: &DynDatabase,
+        db_hint: &DynDatabaseHint,
+        contents: &DbContents,
+        path: &Path,
+    ) -> io::Result<()> {
+        self.host
+            .save_db(db, db_hint, &self.host_contents(contents), path)
+    }
+
+    /// Fails with [`DbFileError::ContentsMismatch`] if the file was saved for another shape.
+    fn load_db(
+        &self,
+        path: &Path,
+        contents: &DbContents,
+    ) -> Result<(DynDatabase, DynDatabaseHint), DbFileError> {
+        self.host.load_db(path, &self.host_contents(contents))
+    }
+
+    /// Benchmarks the host parameter set; a query takes `host_queries` of its queries.
+    fn run_benchmark(&self, trials: usize) -> BenchReport {
+        self.host.run_benchmark(trials)
+    }
+
+    /// Profiles the host parameter set, whose arithmetic the shape shares.
+    fn run_noise_profile(&self, trials: usize) -> NoiseReport {
+        self.host.run_noise_profile(trials)
+    }
+}
@@ -0,0 +1,101 @@
+//! Epoch-versioned values, for databases that are updated while queries are being answered.
+//!
+//! Readers take a [`Snapshot`] of the current version and keep using it for as long as they need
+//! to; writers install new versions, each starting a new epoch. With a cheaply cloned,
+//! copy-on-write `T` such as a RESPIRE database (see [`crate::pir::db_file::DbStorage`]), taking a
+//! snapshot only bumps a reference count, and an update only copies the parts of the value it
+//! changes.
+
+use std::ops::Deref;
+use std::sync::{Mutex, RwLock};
+
+#[derive(Clone, Debug)]
+pub struct Snapshot<T> {
+    pub epoch: u64,
+    pub value: T,
+}
+
+impl<T> Deref for Snapshot<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.value
+    }
+}
+
+pub struct Versioned<T> {
+    current: RwLock<Snapshot<T>>,
+    /// Held by the writer computing the next version, so that writers do not overwrite each other
+    writer: Mutex<()>,
+}
+
+impl<T: Clone> Versioned<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            current: RwLock::new(Snapshot { epoch: 0, value }),
+            writer: Mutex::new(()),
+        }
+    }
+
+    pub fn snapshot(&self) -> Snapshot<T> {
+        self.current.read().unwrap().clone()
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.current.read().unwrap().epoch
+    }
+
+    ///
+    /// Compute the next value from the current one and start a new epoch, which is returned.
+    /// Snapshots can be taken while `f` runs, and see the current value; the new value is only
+    /// swapped in once `f` returns. Updates are applied one at a time. Nothing changes if `f`
+    /// fails.
+    ///
+    pub fn update<E>(&self, f: impl FnOnce(&T) -> Result<T, E>) -> Result<u64, E> {
+        let _writer = self.writer.lock().unwrap();
+        let next = f(&self.snapshot().value)?;
+        let mut current = self.current.write().unwrap();
+        current.value = next;
+        current.epoch += 1;
+        Ok(current.epoch)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_snapshots() {
+        let versioned = Versioned::new(Arc::new(vec![1, 2, 3]));
+        let old = versioned.snapshot();
+        assert_eq!(old.epoch, 0);
+
+        let set = |i: usize, x: i32| {
+            move |v: &Arc<Vec<i32>>| {
+                let mut v = v.clone();
+                Arc::make_mut(&mut v)[i] = x;
+                Ok::<_, ()>(v)
+            }
+        };
+        let epoch = versioned.update(set(0, 10)).unwrap();
+        assert_eq!(epoch, 1);
+        assert_eq!(**old, vec![1, 2, 3]);
+        let new = versioned.snapshot();
+        assert_eq!((new.epoch, &**new), (1, &vec![10, 2, 3]));
+
+        // Readers are not blocked while the next value is computed
+        versioned
+            .update(|v| {
+                assert_eq!(versioned.snapshot().epoch, 1);
+                set(1, 20)(v)
+            })
+            .unwrap();
+        assert_eq!(versioned.epoch(), 2);
+
+        assert_eq!(versioned.update(|_| Err("failed")), Err("failed"));
+        let current = versioned.snapshot();
+        assert_eq!((current.epoch, &**current), (2, &vec![10, 20, 3]));
+    }
+}
@@ -0,0 +1,254 @@
+//! Variable-length values stored over consecutive records of an underlying PIR database.
+//!
+//! Each value is prefixed with its length (a little endian `u32`) and split over as many records,
+//! or slots, as it needs; values are laid out back to back, and the database hint lists the slot
+//! each starts at. To hide the length of the value, a query always fetches `MAX_SLOTS` slots from
+//! its start, and `extract` cuts the value back out using the length prefix.
+
+use crate::pir::error::{CorruptedRecords, RespireError};
+use crate::pir::pir::{
+    answer_batched, extract_batched, query_batched, BatchedQuery, BatchedQueryAndState,
+    PIRRecordBytes, PIR,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::marker::PhantomData;
+
+/// Length of the length prefix of each value
+pub const LEN_BYTES: usize = 4;
+
+/// Number of slots of `slot_len` bytes that `len` bytes occupy
+pub const fn num_slots(len: usize, slot_len: usize) -> usize {
+    len.div_ceil(slot_len)
+}
+
+/// Slot `slot` of `bytes` split into slots of `slot_len` bytes, zero-padded
+pub fn slot_bytes(bytes: impl IntoIterator<Item = u8>, slot: usize, slot_len: usize) -> Vec<u8> {
+    let mut result = bytes
+        .into_iter()
+        .skip(slot * slot_len)
+        .take(slot_len)
+        .collect::<Vec<_>>();
+    result.resize(slot_len, 0);
+    result
+}
+
+/// The bytes of consecutive slots, the inverse of [`slot_bytes`] up to the padding
+pub fn join_slots<'a>(slots: impl IntoIterator<Item = &'a [u8]>) -> Vec<u8> {
+    slots.into_iter().flatten().copied().collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VarLenHint<H> {
+    /// The first slot of each value
+    pub starts: Vec<usize>,
+    /// Hint of the underlying database
+    pub base: H,
+}
+
+pub struct VarLenState<S> {
+    states: Vec<S>,
+    num_values: usize,
+}
+
+#[derive(Debug)]
+pub enum VarLenError {
+    ValueTooLong {
+        len: usize,
+        max: usize,
+    },
+    /// The values need more slots than the database has
+    DatabaseFull {
+        slots: usize,
+        capacity: usize,
+    },
+    /// The underlying PIR failed to encode the slots
+    Encoding(RespireError),
+}
+
+impl fmt::Display for VarLenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VarLenError::ValueTooLong { len, max } => {
+                write!(f, "value of {} bytes exceeds the maximum of {}", len, max)
+            }
+            VarLenError::DatabaseFull { slots, capacity } => write!(
+                f,
+                "values take {} slots, but the database has {}",
+                slots, capacity
+            ),
+            VarLenError::Encoding(e) => write!(f, "failed to encode the slots: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for VarLenError {}
+
+///
+/// Values of up to `MAX_SLOTS` records of `ThePIR` (less the length prefix). Retrieving a value
+/// queries `MAX_SLOTS` records, in one batch of `ThePIR` if `MAX_SLOTS <= BATCH_SIZE` and in as
+/// many batches as needed otherwise.
+///
+pub struct VarLenPIR<ThePIR: PIR, const MAX_SLOTS: usize> {
+    phantom: PhantomData<ThePIR>,
+}
+
+impl<ThePIR: PIR, const MAX_SLOTS: usize> VarLenPIR<ThePIR, MAX_SLOTS> {
+    pub const MAX_VALUE_BYTES: usize = MAX_SLOTS * ThePIR::BYTES_PER_RECORD - LEN_BYTES;
+
+    /// Number of slots a value of `len` bytes occupies
+    pub const fn value_slots(len: usize) -> usize {
+        num_slots(LEN_BYTES + len, ThePIR::BYTES_PER_RECORD)
+    }
+
+    pub fn encode_db<V: AsRef<[u8]>>(
+        values: &[V],
+    ) -> Result<(ThePIR::Database, VarLenHint<ThePIR::DatabaseHint>), VarLenError> {
+        let mut starts = Vec::with_capacity(values.len());
+        let mut slots = 0;
+        for value in values {
+            let len = value.as_ref().len();
+            if len > Self::MAX_VALUE_BYTES {
+                return Err(VarLenError::ValueTooLong {
+                    len,
+                    max: Self::MAX_VALUE_BYTES,
+                });
+            }
+            starts.push(slots);
+            slots += Self::value_slots(len);
+        }
+        if slots > ThePIR::NUM_RECORDS {
+            return Err(VarLenError::DatabaseFull {
+                slots,
+                capacity: ThePIR::NUM_RECORDS,
+            });
+        }
+
+        let (db, base) = ThePIR::try_encode_db(
+            |i| {
+                if i >= slots {
+                    return ThePIR::RecordBytes::default();
+                }
+                let value_idx = starts.partition_point(|&start| start <= i) - 1;
+                let value = values[value_idx].as_ref();
+                let bytes = slot_bytes(
+                    (value.len() as u32)
+                        .to_le_bytes()
+                        .into_iter()
+                        .chain(value.iter().copied()),
+                    i - starts[value_idx],
+                    ThePIR::BYTES_PER_RECORD,
+                );
+                ThePIR::RecordBytes::from_bytes(&bytes).unwrap()
+            },
+            None,
+        )
+        .map_err(VarLenError::Encoding)?;
+        Ok((db, VarLenHint { starts, base }))
+    }
+
+    pub fn query(
+        qk: &ThePIR::QueryKey,
+        value_idxs: &[usize],
+        hint: &VarLenHint<ThePIR::DatabaseHint>,
+    ) -> Result<BatchedQueryAndState<ThePIR, VarLenState<ThePIR::State>>, RespireError> {
+        if let Some(&idx) = value_idxs.iter().find(|&&v| v >= hint.starts.len()) {
+            return Err(RespireError::UnknownRecord {
+                idx,
+                num_records: hint.starts.len(),
+            });
+        }
+        // Slots past the end wrap around; they are never read
+        let idxs = value_idxs
+            .iter()
+            .flat_map(|&v| hint.starts[v]..hint.starts[v] + MAX_SLOTS)
+            .map(|i| i % ThePIR::NUM_RECORDS)
+            .collect::<Vec<_>>();
+        let (q, states) = query_batched::<ThePIR>(qk, &idxs, &hint.base)?;
+        let st = VarLenState {
+            states,
+            num_values: value_idxs.len(),
+        };
+        Ok((q, st))
+    }
+
+    pub fn answer(
+        pp: &ThePIR::PublicParams,
+        db: &ThePIR::Database,
+        q: &BatchedQuery<ThePIR::Query>,
+    ) -> Result<Vec<ThePIR::Response>, RespireError> {
+        answer_batched::<ThePIR>(pp, db, q)
+    }
+
+    /// The queried values, or `RespireError::Corrupted` if some have an invalid length prefix
+    pub fn extract(
+        qk: &ThePIR::QueryKey,
+        r: &[ThePIR::Response],
+        st: &VarLenState<ThePIR::State>,
+    ) -> Result<Vec<Vec<u8>>, RespireError> {
+        let records = extract_batched::<ThePIR>(qk, r, &st.states)?;
+        if records.len() != st.num_values * MAX_SLOTS {
+            return Err(RespireError::WrongLength {
+                what: "response",
+                expected: st.num_values * MAX_SLOTS,
+                found: records.len(),
+            });
+        }
+        let values = records
+            .chunks(MAX_SLOTS)
+            .map(|slots| {
+                let bytes = join_slots(slots.iter().map(|record| record.as_bytes()));
+                let len = u32::from_le_bytes(bytes[..LEN_BYTES].try_into().unwrap()) as usize;
+                (len <= Self::MAX_VALUE_BYTES).then(|| bytes[LEN_BYTES..LEN_BYTES + len].to_vec())
+            })
+            .collect::<Vec<_>>();
+        if values.iter().all(Option::is_some) {
+            Ok(values.into_iter().flatten().collect())
+        } else {
+            Err(RespireError::Corrupted(CorruptedRecords {
+                records: values,
+            }))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pir::respire_harness::RespireTestSmall;
+
+    #[test]
+    fn test_var_len() {
+        type ThePIR = VarLenPIR<RespireTestSmall, 3>;
+        let max = ThePIR::MAX_VALUE_BYTES;
+        let slot = RespireTestSmall::BYTES_PER_RECORD;
+        let values = [0, 1, slot - LEN_BYTES, slot - LEN_BYTES + 1, max, 40]
+            .iter()
+            .enumerate()
+            .map(|(i, &len)| (0..len).map(|j| (i * 31 + j) as u8).collect::<Vec<_>>())
+            .collect::<Vec<_>>();
+        let (db, hint) = ThePIR::encode_db(&values).unwrap();
+        assert_eq!(hint.starts, vec![0, 1, 2, 3, 5, 8]);
+        let (qk, pp) = RespireTestSmall::setup(None);
+
+        // The last value is near the end of the used slots, and the one before spans three slots
+        let idxs = [4, 5];
+        let (q, st) = ThePIR::query(&qk, &idxs, &hint).unwrap();
+        assert_eq!(q.queries.len(), 6);
+        let r = ThePIR::answer(&pp, &db, &q).unwrap();
+        assert_eq!(
+            ThePIR::extract(&qk, &r, &st).unwrap(),
+            vec![values[4].clone(), values[5].clone()]
+        );
+
+        assert!(matches!(
+            ThePIR::encode_db(&[vec![0; max + 1]]),
+            Err(VarLenError::ValueTooLong { len, .. }) if len == max + 1
+        ));
+        let too_many = vec![vec![0u8; max]; RespireTestSmall::NUM_RECORDS / 3 + 1];
+        assert!(matches!(
+            ThePIR::encode_db(&too_many),
+            Err(VarLenError::DatabaseFull { .. })
+        ));
+    }
+}